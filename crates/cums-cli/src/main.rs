@@ -0,0 +1,121 @@
+use cums_sekiro::{
+    export_file_name, replace_sample, AudioSettings, Encryption, FsbBank, Version,
+    DEFAULT_NAME_TEMPLATE,
+};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage:
+  cums-cli list <fsb>
+  cums-cli info <fsb>
+  cums-cli extract <fsb> <outdir>
+  cums-cli replace <fsb> <index> <audio> <out>
+
+FSB5 replacements run fsbankcl from CUMS_FSBANKCL, or from the search path.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["list", fsb] => list(Path::new(fsb)),
+        ["info", fsb] => info(Path::new(fsb)),
+        ["extract", fsb, out_dir] => extract(Path::new(fsb), Path::new(out_dir)),
+        ["replace", fsb, index, audio, out] => match index.parse() {
+            Ok(index) => replace(Path::new(fsb), index, Path::new(audio), Path::new(out)),
+            Err(_) => Err(format!("Bad sample index: {}", index).into()),
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn list(fsb: &Path) -> Result<(), Box<dyn Error>> {
+    let bank = FsbBank::load(fsb)?;
+    for sample in &bank.samples {
+        let duration = if sample.frequency > 0 {
+            sample.samples as f64 / sample.frequency as f64
+        } else {
+            0.0
+        };
+        println!(
+            "{}\t{}\t{} Hz\t{} ch\t{:.2}s",
+            sample.index,
+            sample.display_name(),
+            sample.frequency,
+            sample.channels,
+            duration
+        );
+    }
+    Ok(())
+}
+
+fn info(fsb: &Path) -> Result<(), Box<dyn Error>> {
+    let bank = FsbBank::load(fsb)?;
+    println!("Version: {:?}", bank.version);
+    println!("Codec: {:?}", bank.codec);
+    println!("Encryption: {}", bank.encryption_scheme());
+    println!("Samples: {}", bank.samples.len());
+    println!("Game: {}", bank.guess_game().label());
+    Ok(())
+}
+
+fn extract(fsb: &Path, out_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let bank = FsbBank::load(fsb)?;
+    std::fs::create_dir_all(out_dir)?;
+    let mut failed = 0;
+    for sample in &bank.samples {
+        let exported = bank.export_sample(sample.index).and_then(|(data, ext)| {
+            let path = out_dir.join(export_file_name(DEFAULT_NAME_TEMPLATE, sample, ext)?);
+            std::fs::write(&path, data)?;
+            Ok(path)
+        });
+        match exported {
+            Ok(path) => println!("{}", path.display()),
+            Err(e) => {
+                eprintln!("Skipped {}: {}", sample.display_name(), e);
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "Extracted {} of {} samples",
+        bank.samples.len() - failed,
+        bank.samples.len()
+    );
+    Ok(())
+}
+
+fn replace(fsb: &Path, index: usize, audio: &Path, out: &Path) -> Result<(), Box<dyn Error>> {
+    let mut bank = FsbBank::load(fsb)?;
+    let temp = std::env::temp_dir().join("cums");
+    std::fs::create_dir_all(&temp)?;
+    match bank.version {
+        Version::Fsb5 => {
+            let fsbankcl = std::env::var_os("CUMS_FSBANKCL")
+                .filter(|p| !p.is_empty())
+                .map_or_else(|| PathBuf::from("fsbankcl"), PathBuf::from);
+            replace_sample(
+                &mut bank,
+                index,
+                audio,
+                &fsbankcl,
+                &temp,
+                &AudioSettings::default(),
+            )?;
+        }
+        Version::Fsb3 | Version::Fsb4 => bank.replace_sample(index, audio, &temp)?,
+    }
+    bank.save(out, bank.encryption != Encryption::None)?;
+    println!("Saved {}", out.display());
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../cums-sekiro/tests/fixtures")
+        .join(name)
+}
+
+fn cums(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cums-cli"))
+        .args(args)
+        .output()
+        .expect("run cums-cli")
+}
+
+#[test]
+fn extract_writes_every_sample() {
+    let out_dir = std::env::temp_dir().join(format!("cums-cli-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&out_dir);
+    let bank = fixture("pcm.fsb5");
+    let output = cums(&["extract", bank.to_str().unwrap(), out_dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Extracted 3 of 3 samples"));
+
+    for name in ["alpha", "beta", "gamma"] {
+        let wav = std::fs::read(out_dir.join(format!("{}.wav", name))).expect(name);
+        assert_eq!(&wav[..4], b"RIFF");
+    }
+    let _ = std::fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn list_and_info_describe_the_bank() {
+    let bank = fixture("aes.fsb5");
+    let output = cums(&["list", bank.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let names: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.split('\t').nth(1).unwrap())
+        .collect();
+    assert_eq!(names, ["alpha", "beta", "gamma"]);
+
+    let output = cums(&["info", bank.to_str().unwrap()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Version: Fsb5"));
+    assert!(stdout.contains("Encryption: AES"));
+}
+
+#[test]
+fn bad_arguments_print_usage() {
+    let output = cums(&["extract"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Usage:"));
+}
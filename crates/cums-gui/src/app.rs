@@ -1,34 +1,129 @@
+use crate::config::AppConfig;
+use crate::progress::Task;
 use cums_sekiro::{
-    extract_mp3, rebuild_ogg, replace_sample, AudioSettings, Codec, FsbBank, Version,
+    build_txth, export_audacity, export_file_name, export_vorbis_header_json, has_codec_handler,
+    has_vorbis_setup_header, rebuild_ogg_with_replaygain, render_preview, AudioSettings, Codec,
+    CodecSource, DiffKind, Encryption, FsbBank, FsbError, GameProfile, IssueKind, Sample, Version,
+    DEFAULT_NAME_TEMPLATE, NAME_PLACEHOLDERS,
 };
-use eframe::egui::{self, Color32, RichText, Rounding, Stroke, Vec2};
-use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
+use eframe::egui::{self, Color32, Key, Modifiers, RichText, Rounding, Stroke, Vec2};
+use rodio::buffer::SamplesBuffer;
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use std::collections::{BTreeSet, HashMap};
 use std::io::Cursor;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Bins, and pixels, of the waveform on each sound card
+const WAVEFORM_BINS: usize = 128;
+// Waveforms decoded per frame, so scrolling a large bank stays responsive
+const WAVEFORMS_PER_FRAME: usize = 2;
+
+const OPEN_AS: [(&str, Version, Encryption); 6] = [
+    ("FSB3", Version::Fsb3, Encryption::None),
+    ("FSB4", Version::Fsb4, Encryption::None),
+    ("FSB4 (fsbext)", Version::Fsb4, Encryption::Fsbext),
+    ("FSB5", Version::Fsb5, Encryption::None),
+    ("FSB5 (AES)", Version::Fsb5, Encryption::Aes),
+    ("FSB5 (fsbext)", Version::Fsb5, Encryption::Fsbext),
+];
+
+// Applied on the UI thread once a background task's worker is done
+type Finish = Box<dyn FnOnce(&mut CumsApp) + Send>;
+
+// Min/max per waveform bin
+type Peaks = Vec<(f32, f32)>;
 
 struct Replacement {
     sound_idx: usize,
     path: PathBuf,
     settings: AudioSettings,
+    linked: Vec<usize>,
+    loop_override: Option<(u32, u32)>,
 }
 
 struct SoundInfo {
     index: usize,
     name: String,
     duration_secs: f32,
+    samples: u64,
     sample_rate: u32,
     channels: u32,
     modified: bool,
+    pinned: bool,
+    missing_header: bool,
+    crc: Option<u32>,
+    aliases: Vec<String>,
+    empty: bool,
+}
+
+// Offset and size of a sample now and in the file a save would write
+struct LayoutRow {
+    name: String,
+    now: (u64, u64),
+    saved: (u64, u64),
+}
+
+// Sample-name search over every open bank. The scan is spread across frames
+// so sessions with tens of thousands of sounds stay responsive.
+struct GlobalSearch {
+    query: String,
+    files: Vec<usize>,
+    cursor: (usize, usize),
+    hits: Vec<(usize, usize, String)>,
+}
+
+const SEARCH_SAMPLES_PER_FRAME: usize = 20_000;
+const SEARCH_HITS_SHOWN: usize = 500;
+
+struct CoverageRow {
+    bank: String,
+    encryption: Option<Encryption>,
+    known: usize,
+    total: usize,
+    error: Option<String>,
+}
+
+impl CoverageRow {
+    fn percent(&self) -> f32 {
+        if self.total == 0 {
+            100.0
+        } else {
+            self.known as f32 * 100.0 / self.total as f32
+        }
+    }
+}
+
+const COVERAGE_COLUMNS: [&str; 5] = ["Bank", "Encryption", "Vorbis", "Known", "Covered"];
+
+fn find_fsb_files(dir: &std::path::Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for e in entries.flatten() {
+        let p = e.path();
+        if p.is_dir() {
+            find_fsb_files(&p, out);
+        } else if p.extension().map(|x| x == "fsb").unwrap_or(false) {
+            out.push(p);
+        }
+    }
 }
 
 struct OpenFile {
     id: usize,
     path: PathBuf,
     bank: FsbBank,
+    disk_size: u64,
     replacements: Vec<Replacement>,
+    swapped: Vec<usize>,
 }
 
 impl OpenFile {
+    fn pin_key(&self) -> String {
+        self.path.display().to_string()
+    }
+
     fn name(&self) -> String {
         self.path
             .file_name()
@@ -47,23 +142,234 @@ impl OpenFile {
                     .clone()
                     .unwrap_or_else(|| format!("sound_{}", s.index)),
                 duration_secs: if s.frequency > 0 {
-                    s.samples as f32 / s.frequency as f32
+                    s.effective_samples() as f32 / s.frequency as f32
                 } else {
                     0.0
                 },
+                samples: s.samples,
                 sample_rate: s.frequency,
                 channels: s.channels,
-                modified: self.replacements.iter().any(|r| r.sound_idx == s.index),
+                modified: self
+                    .replacements
+                    .iter()
+                    .any(|r| r.sound_idx == s.index || r.linked.contains(&s.index))
+                    || self.swapped.contains(&s.index),
+                pinned: false,
+                missing_header: self.bank.codec == Codec::Vorbis
+                    && !s.vorbis_crc.is_some_and(has_vorbis_setup_header),
+                crc: s.vorbis_crc,
+                aliases: self
+                    .bank
+                    .aliases_of(s.index)
+                    .into_iter()
+                    .map(|i| self.bank.samples[i].display_name())
+                    .collect(),
+                empty: s.is_empty(),
             })
             .collect()
     }
 
     fn has_changes(&self) -> bool {
-        !self.replacements.is_empty()
+        !self.replacements.is_empty() || !self.swapped.is_empty()
     }
     fn sample_count(&self) -> usize {
         self.bank.samples.len()
     }
+
+    fn mode_summary(&self) -> Option<(String, String)> {
+        if self.bank.version != Version::Fsb5 {
+            return None;
+        }
+        let mode = self.bank.fsb5_mode;
+        if self.bank.codec_source == CodecSource::Mode {
+            let detail = format!(
+                "FSB5 mode 0x{:08X}\nThe codec is stored in the mode's low byte",
+                mode.0
+            );
+            return Some(("codec in mode".to_string(), detail));
+        }
+        let label = if mode.basic_headers() {
+            "basic headers"
+        } else {
+            "full headers"
+        };
+        let mut detail = format!("FSB5 mode 0x{:08X}", mode.0);
+        if mode.unknown_bits() != 0 {
+            detail += &format!(
+                "\nUnknown bits 0x{:08X} are kept as-is on save",
+                mode.unknown_bits()
+            );
+        }
+        Some((label.to_string(), detail))
+    }
+
+    fn size_summary(&self) -> String {
+        let predicted = self.bank.predicted_save_size() as i64;
+        let diff = predicted - self.disk_size as i64;
+        if diff == 0 {
+            format_size(predicted)
+        } else {
+            let sign = if diff > 0 { "+" } else { "-" };
+            format!(
+                "new size: {} ({}{})",
+                format_size(predicted),
+                sign,
+                format_size(diff.abs())
+            )
+        }
+    }
+}
+
+// Loop points are picked against the original length, so they're clamped to
+// whatever the replacement turned out to be
+fn apply_loop_override(sample: &mut Sample, (start, end): (u32, u32)) {
+    let end = end.min(sample.samples as u32);
+    sample.loop_start = Some(start.min(end));
+    sample.loop_end = Some(end);
+}
+
+fn write_txth_sidecar(
+    bank: &FsbBank,
+    sample: &Sample,
+    data_path: &std::path::Path,
+) -> std::io::Result<()> {
+    if data_path.extension().is_none_or(|e| e != "bin") {
+        return Ok(());
+    }
+    let txth = build_txth(bank, sample)?;
+    let mut txth_path = data_path.as_os_str().to_owned();
+    txth_path.push(".txth");
+    std::fs::write(txth_path, txth)
+}
+
+// Decodes the first frame so a broken rebuild is reported up front instead
+// of the sink going quiet after playback has started
+fn probe_decode(data: &[u8]) -> Result<(), String> {
+    let mut decoder = Decoder::new(Cursor::new(data.to_vec())).map_err(|e| e.to_string())?;
+    match decoder.next() {
+        Some(_) => Ok(()),
+        None => Err("no audio frames could be decoded".into()),
+    }
+}
+
+// Frame range that loop playback repeats; the end defaults to the whole sample
+fn loop_region(sample: &Sample) -> Option<(u32, u32)> {
+    sample
+        .loop_start
+        .map(|start| (start, sample.loop_end.unwrap_or(u32::MAX)))
+}
+
+// Decodes everything so the loop region can be cut out by frame
+fn loop_source(data: Vec<u8>, (start, end): (u32, u32)) -> Result<SamplesBuffer<i16>, String> {
+    let decoder = Decoder::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+    let channels = decoder.channels();
+    let rate = decoder.sample_rate();
+    let pcm: Vec<i16> = decoder.collect();
+    let frames = pcm.len() / channels.max(1) as usize;
+    let end = (end as usize).saturating_add(1).min(frames);
+    let start = (start as usize).min(end.saturating_sub(1));
+    if start >= end {
+        return Err("the loop region is empty".into());
+    }
+    let region = pcm[start * channels as usize..end * channels as usize].to_vec();
+    Ok(SamplesBuffer::new(channels, rate, region))
+}
+
+// What the export settings ask for beyond the bank's default export
+#[derive(Debug, Clone, Copy)]
+struct ExportOptions {
+    replaygain: bool,
+    // FMOD's Vorbis packets as stored, for vgmstream and other tools
+    raw_vorbis: bool,
+}
+
+impl ExportOptions {
+    fn from_config(config: &AppConfig) -> Self {
+        Self {
+            replaygain: config.replaygain,
+            raw_vorbis: config.raw_vorbis_export,
+        }
+    }
+}
+
+fn export_audio(
+    bank: &FsbBank,
+    sample: &Sample,
+    options: ExportOptions,
+) -> std::io::Result<(Vec<u8>, &'static str)> {
+    if options.raw_vorbis && bank.codec == Codec::Vorbis {
+        return Ok((bank.sample_data(sample.index)?.to_vec(), "fsbvorbis"));
+    }
+    if options.replaygain && bank.codec == Codec::Vorbis && !has_codec_handler(Codec::Vorbis) {
+        let temp = std::env::temp_dir().join("cums");
+        match rebuild_ogg_with_replaygain(bank, sample, &temp) {
+            Ok(data) => return Ok((data, "ogg")),
+            Err(e) => tracing::warn!("ReplayGain skipped for {}: {}", sample.display_name(), e),
+        }
+    }
+    bank.export_sample(sample.index)
+}
+
+fn export_into(
+    bank: &FsbBank,
+    sample: &Sample,
+    dir: &Path,
+    template: &str,
+    options: ExportOptions,
+) -> std::io::Result<()> {
+    let (data, ext) = export_audio(bank, sample, options)?;
+    let path = dir.join(export_file_name(template, sample, ext)?);
+    std::fs::write(&path, &data)?;
+    write_txth_sidecar(bank, sample, &path)
+}
+
+// Peaks across all channels, or None when the sample exports to something
+// that can't be decoded here
+fn waveform_peaks(bank: &FsbBank, sample: &Sample) -> Option<Peaks> {
+    let (data, ext) = bank.export_sample(sample.index).ok()?;
+    let pcm: Vec<f32> = match ext {
+        "ogg" => cums_sekiro::audio::decode_to_pcm(&data).ok()?.0,
+        "mp3" | "wav" => Decoder::new(Cursor::new(data))
+            .ok()?
+            .convert_samples::<f32>()
+            .collect(),
+        _ => return None,
+    };
+    if pcm.is_empty() {
+        return None;
+    }
+    let bin = pcm.len().div_ceil(WAVEFORM_BINS);
+    Some(
+        pcm.chunks(bin)
+            .map(|c| {
+                c.iter()
+                    .fold((0.0f32, 0.0f32), |(lo, hi), &s| (lo.min(s), hi.max(s)))
+            })
+            .collect(),
+    )
+}
+
+fn original_audio(bank: &FsbBank, sample: &Sample) -> std::io::Result<Vec<u8>> {
+    match bank.export_sample(sample.index)? {
+        (data, "ogg" | "mp3" | "wav") => Ok(data),
+        (_, ext) => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!(
+                "{:?} audio exports as .{} and can't be previewed",
+                bank.codec, ext
+            ),
+        )),
+    }
+}
+
+fn format_size(bytes: i64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.0} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
 }
 
 pub struct CumsApp {
@@ -71,7 +377,17 @@ pub struct CumsApp {
     next_id: usize,
     selected_file: Option<usize>,
     editing_sound: Option<usize>,
+    selected_sounds: BTreeSet<usize>,
+    selection_anchor: Option<usize>,
+    // Keyboard cursor, moved with the arrow keys and played with Space/Enter
+    selected_sound: Option<usize>,
     search_query: String,
+    missing_headers_only: bool,
+    group_by_crc: bool,
+    collapsed_groups: BTreeSet<Option<u32>>,
+    search_all_files: bool,
+    global_search: Option<GlobalSearch>,
+    scroll_to_sound: Option<usize>,
     file_search_query: String,
     status: String,
     fsbankcl_path: PathBuf,
@@ -79,36 +395,73 @@ pub struct CumsApp {
     handle: Option<OutputStreamHandle>,
     sink: Option<Sink>,
     playing: Option<(usize, usize)>,
+    playing_replacement: bool,
     playback_volume: f32,
+    loop_playback: bool,
+    // File id, changes, and warnings from the target game's rules
+    save_review: Option<(usize, Vec<String>, Vec<String>)>,
+    self_test_report: Option<(String, Vec<String>)>,
+    layout_report: Option<(String, Vec<LayoutRow>)>,
+    config: AppConfig,
+    show_settings: bool,
+    // Settings last tuned this session, reused for new replacements when sticky
+    last_settings: Option<AudioSettings>,
+    header_crc_input: String,
+    pending_close: Option<Vec<usize>>,
+    coverage: Option<(PathBuf, Vec<CoverageRow>)>,
+    coverage_sort: (usize, bool),
+    pending_alias: Option<(usize, usize, PathBuf, Vec<usize>)>,
+    // Export All or Save running on a worker; the UI is locked until it ends
+    task: Option<Task<Finish>>,
+    // Keyed by file id and sound index; None marks audio that can't be drawn
+    waveforms: HashMap<(usize, usize), Option<Peaks>>,
 }
 
 impl CumsApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(_cc: &eframe::CreationContext<'_>, config: AppConfig) -> Self {
         let cwd = std::env::current_dir().unwrap_or_default();
         let pf86 = std::env::var("ProgramFiles(x86)").unwrap_or_default();
 
-        let fsbankcl_path = [
-            cwd.join("lib/fmod/fsbankcl.exe"),
-            cwd.join("examples/Dark Souls Sound Inserter/fsbankcl.exe"),
-            PathBuf::from(&pf86).join(
-                "FMOD SoundSystem/FMOD Studio API Universal Windows Platform/bin/fsbankcl.exe",
-            ),
-        ]
-        .into_iter()
-        .find(|p| p.exists())
-        .unwrap_or_else(|| cwd.join("fsbankcl.exe"));
+        let fsbankcl_path = std::env::var_os("CUMS_FSBANKCL")
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| config.fsbankcl_path.clone().filter(|p| p.is_file()))
+            .or_else(|| {
+                [
+                    cwd.join("lib/fmod"),
+                    cwd.join("examples/Dark Souls Sound Inserter"),
+                    PathBuf::from(&pf86)
+                        .join("FMOD SoundSystem/FMOD Studio API Universal Windows Platform/bin"),
+                ]
+                .into_iter()
+                .flat_map(|dir| [dir.join("fsbankcl.exe"), dir.join("fsbankcl")])
+                .find(|p| p.is_file())
+            })
+            .unwrap_or_else(|| cwd.join("fsbankcl.exe"));
 
-        let (stream, handle) = OutputStream::try_default()
-            .ok()
-            .map(|(s, h)| (Some(s), Some(h)))
-            .unwrap_or((None, None));
+        let (stream, handle) = match OutputStream::try_default() {
+            Ok((s, h)) => (Some(s), Some(h)),
+            Err(e) => {
+                tracing::warn!("No audio output, playback disabled: {}", e);
+                (None, None)
+            }
+        };
 
-        Self {
+        let mut app = Self {
             files: Vec::new(),
             next_id: 0,
             selected_file: None,
             editing_sound: None,
+            selected_sounds: BTreeSet::new(),
+            selection_anchor: None,
+            selected_sound: None,
             search_query: String::new(),
+            missing_headers_only: false,
+            group_by_crc: false,
+            collapsed_groups: BTreeSet::new(),
+            search_all_files: false,
+            global_search: None,
+            scroll_to_sound: None,
             file_search_query: String::new(),
             status: "Ready".into(),
             fsbankcl_path,
@@ -116,23 +469,131 @@ impl CumsApp {
             handle,
             sink: None,
             playing: None,
-            playback_volume: 0.5,
+            playing_replacement: false,
+            playback_volume: config.playback_volume.clamp(0.0, 1.0),
+            loop_playback: config.loop_playback,
+            save_review: None,
+            self_test_report: None,
+            layout_report: None,
+            config,
+            show_settings: false,
+            last_settings: None,
+            header_crc_input: String::new(),
+            pending_close: None,
+            coverage: None,
+            coverage_sort: (4, true),
+            pending_alias: None,
+            task: None,
+            waveforms: HashMap::new(),
+        };
+        for path in app.config.session_files.clone() {
+            if path.is_file() {
+                app.load_file(path);
+            }
         }
+        app.status = "Ready".into();
+        app
     }
 
-    fn open_files(&mut self) {
-        if let Some(paths) = rfd::FileDialog::new()
-            .add_filter("FSB", &["fsb"])
-            .pick_files()
-        {
+    // Surfaces a failed step in the status line and the log instead of
+    // dropping it, so the user always learns why nothing happened
+    fn report<T, E: std::fmt::Display>(
+        &mut self,
+        context: &str,
+        result: Result<T, E>,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                tracing::warn!("{}: {}", context, e);
+                self.status = format!("{}: {}", context, e);
+                None
+            }
+        }
+    }
+
+    fn save_session(&mut self) {
+        let paths: Vec<PathBuf> = self.files.iter().map(|f| f.path.clone()).collect();
+        if paths != self.config.session_files {
+            self.config.session_files = paths;
+            let saved = self.config.save();
+            self.report("Could not save settings", saved);
+        }
+    }
+
+    // Dropping onto an entry takes its place, pushing it down or up depending
+    // on which direction the dragged file came from
+    fn move_file(&mut self, id: usize, onto: usize) {
+        let from = self.files.iter().position(|f| f.id == id);
+        let to = self.files.iter().position(|f| f.id == onto);
+        let (Some(from), Some(to)) = (from, to) else {
+            return;
+        };
+        let file = self.files.remove(from);
+        self.files.insert(to, file);
+        self.save_session();
+    }
+
+    // Starts in the folder of the last file opened or picked as a replacement
+    fn file_dialog(&self) -> rfd::FileDialog {
+        let dialog = rfd::FileDialog::new();
+        match self.config.last_dir.as_ref().filter(|d| d.is_dir()) {
+            Some(dir) => dialog.set_directory(dir),
+            None => dialog,
+        }
+    }
+
+    fn remember_dir(&mut self, path: &std::path::Path) {
+        let dir = if path.is_dir() {
+            Some(path)
+        } else {
+            path.parent()
+        };
+        self.config.last_dir = dir.map(PathBuf::from);
+    }
+
+    // FSB5 replacements are encoded by fsbankcl, so saving them needs it
+    fn fsbankcl_available(&self) -> bool {
+        self.fsbankcl_path.exists()
+    }
+
+    fn locate_fsbankcl(&mut self) {
+        let Some(path) = self
+            .file_dialog()
+            .add_filter("fsbankcl", &["exe", ""])
+            .pick_file()
+        else {
+            return;
+        };
+        self.status = format!("Using {}", path.display());
+        self.fsbankcl_path = path;
+        self.save_config();
+    }
+
+    // Settings changed outside the settings window are written on exit
+    fn save_config(&mut self) {
+        self.config.playback_volume = self.playback_volume;
+        self.config.loop_playback = self.loop_playback;
+        self.config.fsbankcl_path = Some(self.fsbankcl_path.clone());
+        if let Err(e) = self.config.save() {
+            tracing::warn!("Could not save settings: {}", e);
+        }
+    }
+
+    fn open_files(&mut self, forced: Option<(Version, Encryption)>) {
+        if let Some(paths) = self.file_dialog().add_filter("FSB", &["fsb"]).pick_files() {
+            if let Some(first) = paths.first() {
+                self.remember_dir(first);
+            }
             for p in paths {
-                self.load_file(p);
+                self.load_file_as(p, forced);
             }
         }
     }
 
     fn open_folder(&mut self) {
-        if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+        if let Some(folder) = self.file_dialog().pick_folder() {
+            self.remember_dir(&folder);
             let mut n = 0;
             if let Ok(entries) = std::fs::read_dir(&folder) {
                 for e in entries.flatten() {
@@ -151,8 +612,107 @@ impl CumsApp {
         }
     }
 
+    fn scan_coverage(&mut self) {
+        let Some(folder) = rfd::FileDialog::new().pick_folder() else {
+            return;
+        };
+        let mut paths = Vec::new();
+        find_fsb_files(&folder, &mut paths);
+        paths.sort();
+
+        let mut rows = Vec::new();
+        for path in paths {
+            let bank = path
+                .strip_prefix(&folder)
+                .unwrap_or(&path)
+                .display()
+                .to_string();
+            match FsbBank::load(&path) {
+                Ok(fsb) if fsb.codec == Codec::Vorbis => {
+                    let (known, total) = fsb.crc_coverage();
+                    rows.push(CoverageRow {
+                        bank,
+                        encryption: Some(fsb.encryption),
+                        known,
+                        total,
+                        error: None,
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => rows.push(CoverageRow {
+                    bank,
+                    encryption: None,
+                    known: 0,
+                    total: 0,
+                    error: Some(e.to_string()),
+                }),
+            }
+        }
+        self.status = format!("Scanned {} Vorbis banks", rows.len());
+        self.coverage = Some((folder, rows));
+        self.sort_coverage();
+    }
+
+    fn sort_coverage(&mut self) {
+        let (column, ascending) = self.coverage_sort;
+        let Some((_, rows)) = &mut self.coverage else {
+            return;
+        };
+        rows.sort_by(|a, b| {
+            let ord = match column {
+                0 => a.bank.cmp(&b.bank),
+                1 => format!("{:?}", a.encryption).cmp(&format!("{:?}", b.encryption)),
+                2 => a.total.cmp(&b.total),
+                3 => a.known.cmp(&b.known),
+                _ => a.percent().total_cmp(&b.percent()),
+            };
+            if ascending {
+                ord
+            } else {
+                ord.reverse()
+            }
+        });
+    }
+
+    fn export_coverage_csv(&mut self) {
+        let Some((_, rows)) = &self.coverage else {
+            return;
+        };
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("CSV", &["csv"])
+            .set_file_name("crc_coverage.csv")
+            .save_file()
+        else {
+            return;
+        };
+        let mut csv =
+            String::from("bank,encryption,vorbis_samples,known_headers,covered_percent,error\n");
+        for row in rows {
+            csv += &format!(
+                "\"{}\",{},{},{},{:.1},\"{}\"\n",
+                row.bank.replace('"', "\"\""),
+                row.encryption
+                    .map(|e| format!("{:?}", e))
+                    .unwrap_or_default(),
+                row.total,
+                row.known,
+                row.percent(),
+                row.error.as_deref().unwrap_or("").replace('"', "\"\"")
+            );
+        }
+        self.status = match std::fs::write(&path, csv) {
+            Ok(_) => format!("Wrote {}", path.display()),
+            Err(e) => format!("Error: {}", e),
+        };
+    }
+
     fn load_file(&mut self, path: PathBuf) {
-        if self.files.iter().any(|f| f.path == path) {
+        self.load_file_as(path, None);
+    }
+
+    fn load_file_as(&mut self, path: PathBuf, forced: Option<(Version, Encryption)>) {
+        let existing = self.files.iter().position(|f| f.path == path);
+        if existing.is_some() && forced.is_none() {
             return;
         }
         let name = path
@@ -160,34 +720,180 @@ impl CumsApp {
             .map(|n| n.to_string_lossy().to_string())
             .unwrap_or_default();
 
-        match FsbBank::load(&path) {
-            Ok(bank) => {
+        let result = match forced {
+            Some((version, encryption)) => std::fs::read(&path)
+                .and_then(|data| FsbBank::from_bytes_as(data, version, encryption)),
+            None => FsbBank::load(&path),
+        };
+
+        if let Ok(bank) = &result {
+            let names: Vec<String> = bank.samples.iter().map(|s| s.display_name()).collect();
+            if self.config.prune_pins(&path.display().to_string(), &names) {
+                if let Err(e) = self.config.save() {
+                    tracing::warn!("Could not save settings: {}", e);
+                }
+            }
+        }
+
+        match (result, existing) {
+            (Ok(bank), Some(pos)) => {
+                let file = &mut self.files[pos];
+                if self.playing.map(|(f, _)| f) == Some(file.id) {
+                    if let Some(sink) = self.sink.take() {
+                        sink.stop();
+                    }
+                    self.playing = None;
+                }
+                file.bank = bank;
+                file.replacements.clear();
+                let id = file.id;
+                self.forget_waveforms(id);
+                self.editing_sound = None;
+                self.status = format!("Reopened {}", name);
+            }
+            (Ok(bank), None) => {
                 let id = self.next_id;
                 self.next_id += 1;
+                let disk_size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
                 self.files.push(OpenFile {
                     id,
                     path,
                     bank,
+                    disk_size,
                     replacements: Vec::new(),
+                    swapped: Vec::new(),
                 });
                 if self.selected_file.is_none() {
                     self.selected_file = Some(id);
                 }
                 self.status = format!("Opened {}", name);
+                self.save_session();
             }
-            Err(e) => {
-                self.status = format!("Failed to load {}: {}", name, e);
+            (Err(e), _) => {
+                let fsb_error = e
+                    .get_ref()
+                    .and_then(|inner| inner.downcast_ref::<FsbError>());
+                self.status = match fsb_error {
+                    Some(FsbError::Truncated { expected, actual }) => format!(
+                        "{} appears incomplete (expected {} bytes, got {})",
+                        name, expected, actual
+                    ),
+                    Some(err) => format!("Failed to load {}: {}", name, err),
+                    None if forced.is_none() => format!(
+                        "Failed to load {}: {} (right-click Open Files to force a format)",
+                        name, e
+                    ),
+                    None => format!("Failed to load {}: {}", name, e),
+                };
+            }
+        }
+    }
+
+    fn request_close(&mut self, ids: Vec<usize>) {
+        let unsaved = self
+            .files
+            .iter()
+            .any(|f| ids.contains(&f.id) && f.has_changes());
+        if unsaved {
+            self.pending_close = Some(ids);
+        } else {
+            for id in ids {
+                self.close_file(id);
             }
         }
     }
 
+    fn forget_waveforms(&mut self, file_id: usize) {
+        self.waveforms.retain(|&(id, _), _| id != file_id);
+    }
+
     fn close_file(&mut self, id: usize) {
         if self.playing.map(|(f, _)| f) == Some(id) {
             self.stop();
         }
         self.files.retain(|f| f.id != id);
+        self.forget_waveforms(id);
         if self.selected_file == Some(id) {
             self.selected_file = self.files.first().map(|f| f.id);
+            self.clear_selection();
+        }
+        self.save_session();
+    }
+
+    // Scans up to SEARCH_SAMPLES_PER_FRAME more samples; true once every open
+    // bank has been searched. Editing the query or the open files restarts it.
+    fn step_global_search(&mut self) -> bool {
+        let query = self.search_query.to_lowercase();
+        let ids: Vec<usize> = self.files.iter().map(|f| f.id).collect();
+        let search = self.global_search.get_or_insert_with(|| GlobalSearch {
+            query: String::new(),
+            files: Vec::new(),
+            cursor: (0, 0),
+            hits: Vec::new(),
+        });
+        if search.query != query || search.files != ids {
+            *search = GlobalSearch {
+                query,
+                files: ids,
+                cursor: (0, 0),
+                hits: Vec::new(),
+            };
+        }
+
+        let mut budget = SEARCH_SAMPLES_PER_FRAME;
+        while let Some(file) = self.files.get(search.cursor.0) {
+            let start = search.cursor.1;
+            let end = file.bank.samples.len().min(start + budget);
+            for sample in &file.bank.samples[start..end] {
+                let name = sample.display_name();
+                if name.to_lowercase().contains(&search.query) {
+                    search.hits.push((file.id, sample.index, name));
+                }
+            }
+            budget -= end - start;
+            if end < file.bank.samples.len() {
+                search.cursor.1 = end;
+                return false;
+            }
+            search.cursor = (search.cursor.0 + 1, 0);
+        }
+        true
+    }
+
+    fn jump_to_sound(&mut self, file_id: usize, sound_idx: usize) {
+        let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
+            return;
+        };
+        if let Some(sample) = file.bank.samples.get(sound_idx) {
+            self.collapsed_groups.remove(&sample.vorbis_crc);
+        }
+        self.selected_file = Some(file_id);
+        self.editing_sound = None;
+        self.search_all_files = false;
+        self.missing_headers_only = false;
+        self.clear_selection();
+        self.selected_sounds.insert(sound_idx);
+        self.selection_anchor = Some(sound_idx);
+        self.scroll_to_sound = Some(sound_idx);
+    }
+
+    fn clear_selection(&mut self) {
+        self.selected_sounds.clear();
+        self.selection_anchor = None;
+    }
+
+    fn toggle_selection(&mut self, sound_idx: usize, extend: bool) {
+        match self.selection_anchor {
+            Some(anchor) if extend => {
+                let (lo, hi) = (anchor.min(sound_idx), anchor.max(sound_idx));
+                self.selected_sounds.extend(lo..=hi);
+            }
+            _ => {
+                if !self.selected_sounds.remove(&sound_idx) {
+                    self.selected_sounds.insert(sound_idx);
+                }
+                self.selection_anchor = Some(sound_idx);
+            }
         }
     }
 
@@ -198,28 +904,90 @@ impl CumsApp {
         }
         self.stop();
 
-        let Some(handle) = &self.handle else { return };
         let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
             return;
         };
         let sample = &file.bank.samples[sound_idx];
+        let name = sample.display_name();
+        let region = loop_region(sample);
+        let audio = original_audio(&file.bank, sample);
+        let Some(data) = self.report(&format!("Can't play {}", name), audio) else {
+            return;
+        };
+        if self
+            .report(&format!("{} failed to decode", name), probe_decode(&data))
+            .is_none()
+        {
+            return;
+        }
+        let played = self.start_playback(data, file_id, sound_idx, false, region);
+        self.report(&format!("Can't play {}", name), played);
+    }
+
+    fn toggle_ab(&mut self, file_id: usize, sound_idx: usize) {
+        let replacement = self.playing == Some((file_id, sound_idx)) && !self.playing_replacement;
+        self.preview(file_id, sound_idx, replacement);
+    }
+
+    fn preview(&mut self, file_id: usize, sound_idx: usize, replacement: bool) {
+        self.stop();
+
+        let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
+            return;
+        };
+        let sample = &file.bank.samples[sound_idx];
+        let name = sample.display_name();
+        let (audio, region) = if replacement {
+            let Some(repl) = file.replacements.iter().find(|r| r.sound_idx == sound_idx) else {
+                return;
+            };
+            (
+                render_preview(&repl.path, &repl.settings),
+                repl.loop_override,
+            )
+        } else {
+            (original_audio(&file.bank, sample), loop_region(sample))
+        };
 
-        let audio: Option<Vec<u8>> = match file.bank.codec {
-            Codec::Vorbis => rebuild_ogg(&file.bank, sample).ok(),
-            Codec::Mpeg => extract_mp3(&file.bank, sample).ok(),
-            _ => None,
+        let Some(data) = self.report(&format!("Can't preview {}", name), audio) else {
+            return;
         };
+        if self
+            .report(&format!("{} failed to decode", name), probe_decode(&data))
+            .is_none()
+        {
+            return;
+        }
+        let played = self.start_playback(data, file_id, sound_idx, replacement, region);
+        self.report(&format!("Can't preview {}", name), played);
+    }
 
-        if let Some(data) = audio {
-            if let Ok(decoder) = Decoder::new(Cursor::new(data)) {
-                if let Ok(sink) = Sink::try_new(handle) {
-                    sink.set_volume(self.playback_volume);
-                    sink.append(decoder);
-                    self.sink = Some(sink);
-                    self.playing = Some((file_id, sound_idx));
-                }
+    fn start_playback(
+        &mut self,
+        data: Vec<u8>,
+        file_id: usize,
+        sound_idx: usize,
+        replacement: bool,
+        region: Option<(u32, u32)>,
+    ) -> Result<(), String> {
+        let handle = self.handle.as_ref().ok_or("no audio output device")?;
+        let sink = Sink::try_new(handle).map_err(|e| e.to_string())?;
+        sink.set_volume(self.playback_volume);
+        match (self.loop_playback, region) {
+            (true, Some(region)) => sink.append(loop_source(data, region)?.repeat_infinite()),
+            (true, None) => {
+                let decoder = Decoder::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+                sink.append(decoder.repeat_infinite());
+            }
+            (false, _) => {
+                let decoder = Decoder::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+                sink.append(decoder);
             }
         }
+        self.sink = Some(sink);
+        self.playing = Some((file_id, sound_idx));
+        self.playing_replacement = replacement;
+        Ok(())
     }
 
     fn set_playback_volume(&mut self, volume: f32) {
@@ -229,6 +997,19 @@ impl CumsApp {
         }
     }
 
+    // Restarts whatever is playing so a loop mode change takes effect
+    fn set_loop_playback(&mut self, enabled: bool) {
+        self.loop_playback = enabled;
+        if let Some((file_id, sound_idx)) = self.playing.filter(|_| self.is_playing()) {
+            if self.playing_replacement {
+                self.preview(file_id, sound_idx, true);
+            } else {
+                self.stop();
+                self.play(file_id, sound_idx);
+            }
+        }
+    }
+
     fn stop(&mut self) {
         if let Some(sink) = self.sink.take() {
             sink.stop();
@@ -241,24 +1022,60 @@ impl CumsApp {
     }
 
     fn replace(&mut self, file_id: usize, sound_idx: usize) {
-        if let Some(path) = rfd::FileDialog::new()
+        let Some(path) = self
+            .file_dialog()
             .add_filter("Audio", &["wav", "mp3", "ogg", "flac"])
             .pick_file()
-        {
-            if let Some(file) = self.files.iter_mut().find(|f| f.id == file_id) {
-                file.replacements.retain(|r| r.sound_idx != sound_idx);
-                file.replacements.push(Replacement {
-                    sound_idx,
-                    path: path.clone(),
-                    settings: AudioSettings::default(),
-                });
-                self.editing_sound = Some(sound_idx);
-                self.status = format!(
-                    "Added: {}",
-                    path.file_name().unwrap_or_default().to_string_lossy()
-                );
-            }
+        else {
+            return;
+        };
+        self.remember_dir(&path);
+        let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
+            return;
+        };
+        let aliases = file.bank.aliases_of(sound_idx);
+        if aliases.is_empty() {
+            self.add_replacement(file_id, sound_idx, path, Vec::new());
+        } else {
+            self.pending_alias = Some((file_id, sound_idx, path, aliases));
+        }
+    }
+
+    fn add_replacement(
+        &mut self,
+        file_id: usize,
+        sound_idx: usize,
+        path: PathBuf,
+        linked: Vec<usize>,
+    ) {
+        let Some(file) = self.files.iter_mut().find(|f| f.id == file_id) else {
+            return;
+        };
+        file.replacements
+            .retain(|r| r.sound_idx != sound_idx && !linked.contains(&r.sound_idx));
+        for r in &mut file.replacements {
+            r.linked.retain(|i| *i != sound_idx && !linked.contains(i));
         }
+        let settings = match &self.last_settings {
+            Some(last) if self.config.sticky_settings => last.clone(),
+            _ => AudioSettings::default(),
+        };
+        file.replacements.push(Replacement {
+            sound_idx,
+            path: path.clone(),
+            settings,
+            linked,
+            loop_override: None,
+        });
+        self.editing_sound = Some(sound_idx);
+        self.status = format!(
+            "Added: {}",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+    }
+
+    fn export(&self, bank: &FsbBank, sample: &Sample) -> std::io::Result<(Vec<u8>, &'static str)> {
+        export_audio(bank, sample, ExportOptions::from_config(&self.config))
     }
 
     fn extract(&mut self, file_id: usize, sound_idx: usize) {
@@ -266,27 +1083,19 @@ impl CumsApp {
             return;
         };
         let sample = &file.bank.samples[sound_idx];
-        let name = sample
-            .name
-            .clone()
-            .unwrap_or_else(|| format!("sound_{}", sound_idx));
-
-        let (ext, data): (&str, Option<Vec<u8>>) = match file.bank.codec {
-            Codec::Vorbis => ("ogg", rebuild_ogg(&file.bank, sample).ok()),
-            Codec::Mpeg => ("mp3", extract_mp3(&file.bank, sample).ok()),
-            _ => (
-                "bin",
-                file.bank.sample_data(sound_idx).ok().map(|d| d.to_vec()),
-            ),
-        };
+        let context = format!("Can't export {}", sample.display_name());
 
-        if let Some(data) = data {
-            let fname = format!("{}.{}", name, ext);
-            if let Some(path) = rfd::FileDialog::new().set_file_name(&fname).save_file() {
-                if std::fs::write(&path, &data).is_ok() {
-                    self.status = format!("Exported {}", fname);
-                }
-            }
+        let exported = self.export(&file.bank, sample).and_then(|(data, ext)| {
+            let fname = export_file_name(&self.config.export_template, sample, ext)?;
+            let Some(path) = rfd::FileDialog::new().set_file_name(&fname).save_file() else {
+                return Ok(None);
+            };
+            std::fs::write(&path, &data)?;
+            write_txth_sidecar(&file.bank, sample, &path)?;
+            Ok(Some(fname))
+        });
+        if let Some(Some(fname)) = self.report(&context, exported) {
+            self.status = format!("Exported {}", fname);
         }
     }
 
@@ -298,35 +1107,281 @@ impl CumsApp {
             return;
         };
 
-        let ext = match file.bank.codec {
-            Codec::Vorbis => "ogg",
-            Codec::Mpeg => "mp3",
-            _ => "bin",
-        };
-        let mut count = 0;
-
-        for sample in &file.bank.samples {
-            let data: Option<Vec<u8>> = match file.bank.codec {
-                Codec::Vorbis => rebuild_ogg(&file.bank, sample).ok(),
-                Codec::Mpeg => extract_mp3(&file.bank, sample).ok(),
-                _ => file.bank.sample_data(sample.index).ok().map(|d| d.to_vec()),
-            };
-            if let Some(data) = data {
-                let name = sample
-                    .name
-                    .clone()
-                    .unwrap_or_else(|| format!("sound_{}", sample.index));
-                if std::fs::write(folder.join(format!("{}.{}", name, ext)), &data).is_ok() {
-                    count += 1;
+        // The worker gets its own copy so the bank stays usable while it runs
+        let bank = file.bank.clone();
+        let template = self.config.export_template.clone();
+        let options = ExportOptions::from_config(&self.config);
+        self.task = Some(Task::spawn("Export All", move |ctx| {
+            ctx.start_phase("Exporting sounds", bank.samples.len());
+            let mut count = 0;
+            let mut empty = 0;
+            let mut failures = Vec::new();
+            for sample in &bank.samples {
+                if ctx.cancelled() {
+                    break;
+                }
+                if sample.is_empty() {
+                    empty += 1;
+                    ctx.advance();
+                    continue;
+                }
+                match export_into(&bank, sample, &folder, &template, options) {
+                    Ok(_) => count += 1,
+                    Err(e) => {
+                        tracing::warn!("Can't export {}: {}", sample.display_name(), e);
+                        failures.push(format!("{}: {}", sample.display_name(), e));
+                    }
                 }
+                ctx.advance();
             }
-        }
-        self.status = format!("Exported {} sounds", count);
+            let cancelled = ctx.cancelled();
+            Box::new(move |app: &mut CumsApp| {
+                app.status = match failures.first() {
+                    None => format!("Exported {} sounds", count),
+                    Some(first) => format!(
+                        "Exported {} sounds, {} failed (first: {})",
+                        count,
+                        failures.len(),
+                        first
+                    ),
+                };
+                if empty > 0 {
+                    app.status += &format!(", skipped {} empty", empty);
+                }
+                if cancelled {
+                    app.status += " before being cancelled";
+                }
+            }) as Finish
+        }));
     }
 
-    fn save(&mut self, file_id: usize) {
-        let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
-            return;
+    fn swap_selected(&mut self, file_id: usize, include_names: bool) {
+        let &[a, b] = self
+            .selected_sounds
+            .iter()
+            .copied()
+            .collect::<Vec<_>>()
+            .as_slice()
+        else {
+            return;
+        };
+        let Some(file) = self.files.iter_mut().find(|f| f.id == file_id) else {
+            return;
+        };
+        self.status = match file.bank.swap_samples(a, b, include_names) {
+            Ok(_) => {
+                for idx in [a, b] {
+                    if !file.swapped.contains(&idx) {
+                        file.swapped.push(idx);
+                    }
+                    self.waveforms.remove(&(file_id, idx));
+                }
+                format!(
+                    "Swapped {} and {}",
+                    file.bank.samples[a].display_name(),
+                    file.bank.samples[b].display_name()
+                )
+            }
+            Err(e) => format!("Swap failed: {}", e),
+        };
+    }
+
+    fn export_for_audacity(&mut self, file_id: usize) {
+        let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
+            return;
+        };
+        let stem = file.path.file_stem().unwrap_or_default().to_string_lossy();
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("WAV", &["wav"])
+            .set_file_name(format!("{}.wav", stem))
+            .save_file()
+        else {
+            return;
+        };
+
+        let temp = std::env::temp_dir().join("cums");
+        self.status = match export_audacity(&file.bank, &path, &temp) {
+            Ok((rate, count)) => format!(
+                "Exported {} sounds at {} Hz with Audacity labels",
+                count, rate
+            ),
+            Err(e) => format!("Export failed: {}", e),
+        };
+    }
+
+    fn extract_selected(&mut self, file_id: usize) {
+        let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
+            return;
+        };
+        let Some(folder) = rfd::FileDialog::new().pick_folder() else {
+            return;
+        };
+
+        let mut runs: Vec<std::ops::Range<usize>> = Vec::new();
+        for &idx in &self.selected_sounds {
+            match runs.last_mut() {
+                Some(run) if run.end == idx => run.end += 1,
+                _ => runs.push(idx..idx + 1),
+            }
+        }
+
+        let (mut extracted, mut requested) = (0, 0);
+        for run in runs {
+            match file
+                .bank
+                .extract_range_with_template(run, &folder, &self.config.export_template)
+            {
+                Ok((done, wanted)) => {
+                    extracted += done;
+                    requested += wanted;
+                }
+                Err(e) => {
+                    self.status = format!("Export failed: {}", e);
+                    return;
+                }
+            }
+        }
+        self.status = format!("Exported {} of {} selected sounds", extracted, requested);
+    }
+
+    fn dump_vorbis_header(&mut self) {
+        let input = self.header_crc_input.trim();
+        let crc = match input
+            .strip_prefix("0x")
+            .or_else(|| input.strip_prefix("0X"))
+        {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => input.parse(),
+        };
+        let Ok(crc) = crc else {
+            self.status = format!("Invalid CRC: {}", input);
+            return;
+        };
+        let Some(json) = export_vorbis_header_json(crc) else {
+            self.status = format!("No setup header known for CRC {}", crc);
+            return;
+        };
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .set_file_name(format!("vorbis_header_{}.json", crc))
+            .save_file()
+        {
+            self.status = match std::fs::write(&path, json) {
+                Ok(_) => format!("Dumped header for CRC {}", crc),
+                Err(e) => format!("Error: {}", e),
+            };
+        }
+    }
+
+    fn run_self_test(&mut self, file_id: usize) {
+        let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
+            return;
+        };
+        let lines = file
+            .bank
+            .self_test()
+            .into_iter()
+            .map(|issue| match issue.kind {
+                IssueKind::DataOutOfBounds => {
+                    format!("{}: audio data is out of bounds", issue.name)
+                }
+                IssueKind::BadAudioPacket => {
+                    format!("{}: audio does not start with a valid packet", issue.name)
+                }
+                IssueKind::ChannelMismatch { declared, actual } => format!(
+                    "{}: header says {} channel(s), audio is encoded for {}",
+                    issue.name, declared, actual
+                ),
+                IssueKind::LengthMismatch { declared, actual } => format!(
+                    "{}: header says {} samples, audio decodes to {}",
+                    issue.name, declared, actual
+                ),
+            })
+            .collect();
+        self.self_test_report = Some((file.name(), lines));
+    }
+
+    fn show_layout(&mut self, file_id: usize) {
+        let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
+            return;
+        };
+        let rows = file
+            .bank
+            .layout_table()
+            .into_iter()
+            .zip(file.bank.planned_layout_table())
+            .map(
+                |((idx, offset, size), (_, planned_offset, planned_size))| LayoutRow {
+                    name: file.bank.samples[idx].display_name(),
+                    now: (offset, size),
+                    saved: (planned_offset, planned_size),
+                },
+            )
+            .collect();
+        self.layout_report = Some((file.name(), rows));
+    }
+
+    fn review_save(&mut self, file_id: usize) {
+        let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
+            return;
+        };
+
+        let mut lines = Vec::new();
+        match FsbBank::load(&file.path) {
+            Ok(original) => {
+                for d in original.diff(&file.bank) {
+                    lines.push(match d.kind {
+                        DiffKind::Added => format!("{}: added", d.name),
+                        DiffKind::Removed => format!("{}: removed", d.name),
+                        DiffKind::Changed(fields) => {
+                            format!("{}: {} changed", d.name, fields.join(", "))
+                        }
+                    });
+                }
+            }
+            Err(e) => lines.push(format!("Could not reload original: {}", e)),
+        }
+
+        for r in &file.replacements {
+            let name = file
+                .bank
+                .samples
+                .get(r.sound_idx)
+                .map(|s| s.display_name())
+                .unwrap_or_else(|| format!("sound_{}", r.sound_idx));
+            let size = std::fs::metadata(&r.path)
+                .map(|m| format_size(m.len() as i64))
+                .unwrap_or_else(|_| "missing".into());
+            let mut line = format!(
+                "{}: will be replaced by {} ({})",
+                name,
+                r.path.file_name().unwrap_or_default().to_string_lossy(),
+                size
+            );
+            if r.settings.needs_processing() {
+                line.push_str(" with audio settings");
+            }
+            lines.push(line);
+        }
+
+        let game = self.target_game();
+        let warnings = game
+            .map(|game| file.bank.lint_for_game(game))
+            .unwrap_or_default()
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect();
+        self.save_review = Some((file_id, lines, warnings));
+    }
+
+    fn target_game(&self) -> Option<GameProfile> {
+        let name = self.config.target_game.as_deref()?;
+        GameProfile::ALL.into_iter().find(|g| g.name() == name)
+    }
+
+    fn save(&mut self, file_id: usize) {
+        let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
+            return;
         };
         if !file.has_changes() {
             return;
@@ -341,66 +1396,198 @@ impl CumsApp {
             return;
         };
 
+        let backup = match self.config.backup(&out_path) {
+            Ok(backup) => backup,
+            Err(e) => {
+                self.status = format!("Backup failed, nothing saved: {}", e);
+                return;
+            }
+        };
+
         let temp = std::env::temp_dir().join("cums");
         let _ = std::fs::create_dir_all(&temp);
         let fmod = self.fsbankcl_path.clone();
 
-        let file = self.files.iter_mut().find(|f| f.id == file_id).unwrap();
+        let file = self.files.iter().find(|f| f.id == file_id).unwrap();
         let mods: Vec<_> = file
             .replacements
             .iter()
-            .map(|r| (r.sound_idx, r.path.clone(), r.settings.clone()))
+            .map(|r| {
+                (
+                    r.sound_idx,
+                    r.path.clone(),
+                    r.settings.clone(),
+                    r.linked.clone(),
+                    r.loop_override,
+                )
+            })
             .collect();
 
-        let result: Result<(), String> = match file.bank.version {
-            Version::Fsb5 => {
-                let mut err = None;
-                for (idx, path, settings) in &mods {
-                    if let Err(e) =
-                        replace_sample(&mut file.bank, *idx, path, &fmod, &temp, settings)
-                    {
-                        err = Some(e.to_string());
-                        break;
+        // Replacements are applied to a copy that only takes the open bank's
+        // place once the whole save succeeded
+        let mut bank = file.bank.clone();
+        self.task = Some(Task::spawn("Save", move |ctx| {
+            let mut fit_notes = Vec::new();
+            let result: Result<(), String> = match bank.version {
+                Version::Fsb5 => {
+                    let edits: Vec<_> = mods
+                        .iter()
+                        .map(|(idx, path, settings, ..)| (*idx, path.clone(), settings.clone()))
+                        .collect();
+                    ctx.start_phase("Encoding replacements", 1);
+                    match bank.replace_samples(&edits, &fmod, &temp) {
+                        Ok(results) => {
+                            ctx.advance();
+                            for ((idx, _, settings, linked, loop_override), (quality, fits)) in
+                                mods.iter().zip(results)
+                            {
+                                if let Some(points) = loop_override {
+                                    apply_loop_override(&mut bank.samples[*idx], *points);
+                                }
+                                for &member in linked {
+                                    if let Err(e) = bank.link_alias(member, *idx) {
+                                        tracing::warn!("Could not relink sound {}: {}", member, e);
+                                    }
+                                }
+                                if settings.keep_size {
+                                    let name = bank.samples[*idx].display_name();
+                                    fit_notes.push(if fits {
+                                        format!("{} fit at quality {}", name, quality)
+                                    } else {
+                                        format!("{} did not fit even at quality {}", name, quality)
+                                    });
+                                }
+                            }
+                            Ok(())
+                        }
+                        Err(e) => Err(e.to_string()),
                     }
                 }
-                err.map(Err).unwrap_or_else(|| {
-                    file.bank
-                        .save(
-                            &out_path,
-                            file.bank.encryption != cums_sekiro::Encryption::None,
-                        )
-                        .map_err(|e| e.to_string())
-                })
-            }
-            Version::Fsb4 => {
-                let mut err = None;
-                for (idx, path, _) in &mods {
-                    if let Err(e) = file.bank.replace_sample(*idx, path, &temp) {
-                        err = Some(e.to_string());
-                        break;
+                Version::Fsb3 | Version::Fsb4 => {
+                    ctx.start_phase("Encoding replacements", mods.len());
+                    let mut result = Ok(());
+                    for (idx, path, settings, _, loop_override) in &mods {
+                        if ctx.cancelled() {
+                            break;
+                        }
+                        if let Err(e) =
+                            bank.replace_sample_with_settings(*idx, path, &temp, settings)
+                        {
+                            result = Err(e.to_string());
+                            break;
+                        }
+                        if let Some(points) = loop_override {
+                            apply_loop_override(&mut bank.samples[*idx], *points);
+                        }
+                        ctx.advance();
                     }
+                    result
                 }
-                err.map(Err)
-                    .unwrap_or_else(|| file.bank.save(&out_path, false).map_err(|e| e.to_string()))
+            };
+            if result.is_ok() && ctx.cancelled() {
+                return Box::new(|app: &mut CumsApp| {
+                    app.status = "Save cancelled, nothing was written".into();
+                }) as Finish;
             }
+            let result = result.and_then(|_| {
+                ctx.start_phase("Writing bank", 1);
+                let encrypt = bank.encryption != Encryption::None;
+                bank.save(&out_path, encrypt).map_err(|e| e.to_string())
+            });
+
+            Box::new(move |app: &mut CumsApp| match result {
+                Ok(_) => {
+                    if let Some(file) = app.files.iter_mut().find(|f| f.id == file_id) {
+                        file.bank = bank;
+                        file.replacements.clear();
+                        file.swapped.clear();
+                    }
+                    app.forget_waveforms(file_id);
+                    app.editing_sound = None;
+                    app.status = format!(
+                        "Saved to {}",
+                        out_path.file_name().unwrap_or_default().to_string_lossy()
+                    );
+                    if let Some(backup) = backup {
+                        app.status += &format!(
+                            " (backup: {})",
+                            backup.file_name().unwrap_or_default().to_string_lossy()
+                        );
+                    }
+                    if !fit_notes.is_empty() {
+                        app.status += &format!("; {}", fit_notes.join(", "));
+                    }
+                }
+                Err(e) => app.status = format!("Error: {}", e),
+            }) as Finish
+        }));
+    }
+
+    // Format conversion only: writes the bank as loaded (plus swaps), without
+    // applying pending replacements
+    fn save_as(&mut self, file_id: usize, encryption: Encryption) {
+        let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
+            return;
+        };
+        let Some(out_path) = rfd::FileDialog::new()
+            .add_filter("FSB", &["fsb"])
+            .set_file_name(file.name())
+            .save_file()
+        else {
+            return;
         };
 
-        match result {
-            Ok(_) => {
-                file.replacements.clear();
-                self.editing_sound = None;
-                self.status = format!(
-                    "Saved to {}",
-                    out_path.file_name().unwrap_or_default().to_string_lossy()
-                );
+        let backup = match self.config.backup(&out_path) {
+            Ok(backup) => backup,
+            Err(e) => {
+                self.status = format!("Backup failed, nothing saved: {}", e);
+                return;
             }
-            Err(e) => self.status = format!("Error: {}", e),
+        };
+        let file = self.files.iter().find(|f| f.id == file_id).unwrap();
+        let result = file
+            .bank
+            .save_as(&out_path, encryption)
+            .and_then(|_| FsbBank::load(&out_path));
+        let unchanged = !file.has_changes();
+        let identical = result
+            .as_ref()
+            .is_ok_and(|saved| saved.data == file.bank.data);
+        let Some(_) = self.report("Save failed", result) else {
+            return;
+        };
+
+        self.status = format!(
+            "Saved {:?} copy to {}",
+            encryption,
+            out_path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        if let Some(backup) = backup {
+            self.status += &format!(
+                " (backup: {})",
+                backup.file_name().unwrap_or_default().to_string_lossy()
+            );
+        }
+        if unchanged && !identical {
+            tracing::warn!(
+                "{} differs from the original after re-saving",
+                out_path.display()
+            );
+            self.status +=
+                "; note: not byte-identical to the original, alignment or headers were normalized";
         }
     }
 }
 
 impl eframe::App for CumsApp {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.save_config();
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if let Some(rect) = ctx.input(|i| i.viewport().inner_rect) {
+            self.config.window_size = Some([rect.width(), rect.height()]);
+        }
         let mut style = (*ctx.style()).clone();
         style.visuals.window_rounding = Rounding::same(12.0);
         style.visuals.widgets.noninteractive.rounding = Rounding::same(8.0);
@@ -426,6 +1613,21 @@ impl eframe::App for CumsApp {
             self.playing = None;
         }
 
+        if let Some(task) = &mut self.task {
+            match task.try_finish() {
+                Some(Ok(finish)) => {
+                    self.task = None;
+                    finish(self);
+                }
+                Some(Err(e)) => {
+                    self.status = format!("{} failed: {}", task.title, e);
+                    self.task = None;
+                }
+                None => ctx.request_repaint_after(Duration::from_millis(50)),
+            }
+        }
+        let busy = self.task.is_some();
+
         ctx.input(|i| {
             for f in &i.raw.dropped_files {
                 if let Some(p) = &f.path {
@@ -449,6 +1651,9 @@ impl eframe::App for CumsApp {
             .exact_width(280.0)
             .frame(egui::Frame::none().fill(bg_panel).inner_margin(16.0))
             .show(ctx, |ui| {
+                if busy {
+                    ui.disable();
+                }
                 ui.horizontal(|ui| {
                     ui.heading(RichText::new("CUMS").size(28.0).color(accent).strong());
                 });
@@ -459,25 +1664,44 @@ impl eframe::App for CumsApp {
                 );
                 ui.add_space(24.0);
 
-                if ui
-                    .add_sized(
-                        [ui.available_width(), 36.0],
-                        egui::Button::new(RichText::new("Open Files").color(text)).fill(bg_card),
-                    )
-                    .clicked()
-                {
-                    self.open_files();
+                let open_resp = ui.add_sized(
+                    [ui.available_width(), 36.0],
+                    egui::Button::new(RichText::new("Open Files").color(text)).fill(bg_card),
+                );
+                if open_resp.clicked() {
+                    self.open_files(None);
+                }
+                let mut open_as = None;
+                open_resp.context_menu(|ui| {
+                    ui.label(RichText::new("Open as...").size(11.0).color(text_dim));
+                    for (label, version, encryption) in OPEN_AS {
+                        if ui.button(label).clicked() {
+                            open_as = Some((version, encryption));
+                            ui.close_menu();
+                        }
+                    }
+                });
+                if open_as.is_some() {
+                    self.open_files(open_as);
                 }
                 ui.add_space(4.0);
-                if ui
-                    .add_sized(
-                        [ui.available_width(), 36.0],
-                        egui::Button::new(RichText::new("Open Folder").color(text)).fill(bg_card),
-                    )
-                    .clicked()
-                {
+                let folder_resp = ui.add_sized(
+                    [ui.available_width(), 36.0],
+                    egui::Button::new(RichText::new("Open Folder").color(text)).fill(bg_card),
+                );
+                if folder_resp.clicked() {
                     self.open_folder();
                 }
+                let mut scan = false;
+                folder_resp.context_menu(|ui| {
+                    if ui.button("Scan CRC coverage...").clicked() {
+                        scan = true;
+                        ui.close_menu();
+                    }
+                });
+                if scan {
+                    self.scan_coverage();
+                }
 
                 ui.add_space(24.0);
                 ui.separator();
@@ -511,11 +1735,14 @@ impl eframe::App for CumsApp {
                     ui.add_space(8.0);
                 }
 
-                let mut close_id = None;
+                let mut close_ids: Vec<usize> = Vec::new();
                 let mut select_id = None;
+                let mut reopen: Option<(PathBuf, Version, Encryption)> = None;
+                let mut dropped: Option<(usize, usize)> = None;
                 let bottom_height = 120.0;
                 let available = (ui.available_height() - bottom_height).max(100.0);
                 let file_query = self.file_search_query.to_lowercase();
+                let all_ids: Vec<usize> = self.files.iter().map(|f| f.id).collect();
 
                 egui::ScrollArea::vertical()
                     .max_height(available)
@@ -554,22 +1781,75 @@ impl eframe::App for CumsApp {
                                             egui::Layout::right_to_left(egui::Align::Center),
                                             |ui| {
                                                 if ui.small_button("X").clicked() {
-                                                    close_id = Some(file.id);
+                                                    close_ids = vec![file.id];
                                                 }
                                             },
                                         );
                                     });
                                 });
-                            if resp.response.interact(egui::Sense::click()).clicked() {
+                            let entry = resp.response.interact(egui::Sense::click_and_drag());
+                            if entry.clicked() {
                                 select_id = Some(file.id);
                             }
+                            entry.dnd_set_drag_payload(file.id);
+                            if entry
+                                .dnd_hover_payload::<usize>()
+                                .is_some_and(|id| *id != file.id)
+                            {
+                                ui.painter()
+                                    .rect_stroke(entry.rect, 8.0, Stroke::new(2.0, accent));
+                            }
+                            if let Some(id) = entry.dnd_release_payload::<usize>() {
+                                dropped = Some((*id, file.id));
+                            }
+                            if entry.middle_clicked() {
+                                close_ids = vec![file.id];
+                            }
+                            entry.context_menu(|ui| {
+                                if ui.button("Close").clicked() {
+                                    close_ids = vec![file.id];
+                                    ui.close_menu();
+                                }
+                                if ui.button("Close others").clicked() {
+                                    close_ids = all_ids
+                                        .iter()
+                                        .copied()
+                                        .filter(|&id| id != file.id)
+                                        .collect();
+                                    ui.close_menu();
+                                }
+                                if ui.button("Close all").clicked() {
+                                    close_ids = all_ids.clone();
+                                    ui.close_menu();
+                                }
+                                ui.separator();
+                                ui.menu_button("Reopen as", |ui| {
+                                    for (label, version, encryption) in OPEN_AS {
+                                        if ui.button(label).clicked() {
+                                            reopen = Some((file.path.clone(), version, encryption));
+                                            ui.close_menu();
+                                        }
+                                    }
+                                });
+                            });
                         }
                     });
 
-                if let Some(id) = close_id {
-                    self.close_file(id);
+                if let Some((id, onto)) = dropped.filter(|(id, onto)| id != onto) {
+                    self.move_file(id, onto);
+                }
+
+                if let Some((path, version, encryption)) = reopen {
+                    self.load_file_as(path, Some((version, encryption)));
+                }
+
+                if !close_ids.is_empty() {
+                    self.request_close(close_ids);
                 }
                 if let Some(id) = select_id {
+                    if self.selected_file != Some(id) {
+                        self.clear_selection();
+                    }
                     self.selected_file = Some(id);
                     self.editing_sound = None;
                 }
@@ -607,15 +1887,54 @@ impl eframe::App for CumsApp {
                                         .monospace(),
                                 );
                             });
+                            let mut looping = self.loop_playback;
+                            if ui
+                                .checkbox(&mut looping, "Loop")
+                                .on_hover_text("Repeat previews, using the sample's loop points")
+                                .changed()
+                            {
+                                self.set_loop_playback(looping);
+                            }
                         });
                     ui.add_space(8.0);
                     ui.label(RichText::new("VOLUME").size(10.0).color(text_dim));
+                    ui.add_space(8.0);
+                    if ui
+                        .add_sized(
+                            [ui.available_width(), 28.0],
+                            egui::Button::new(RichText::new("Settings").color(text)).fill(bg_card),
+                        )
+                        .clicked()
+                    {
+                        self.show_settings = !self.show_settings;
+                    }
+                    let located = self.fsbankcl_available();
+                    if ui
+                        .add_sized(
+                            [ui.available_width(), 28.0],
+                            egui::Button::new(
+                                RichText::new("Locate fsbankcl...").color(if located {
+                                    text
+                                } else {
+                                    warning
+                                }),
+                            )
+                            .fill(bg_card),
+                        )
+                        .on_hover_text(self.fsbankcl_path.display().to_string())
+                        .clicked()
+                    {
+                        self.locate_fsbankcl();
+                    }
                 });
             });
 
         egui::CentralPanel::default()
             .frame(egui::Frame::none().fill(bg_dark).inner_margin(24.0))
             .show(ctx, |ui| {
+                if busy {
+                    ui.disable();
+                }
                 if self.files.is_empty() {
                     ui.centered_and_justified(|ui| {
                         ui.vertical_centered(|ui| {
@@ -623,7 +1942,7 @@ impl eframe::App for CumsApp {
                             ui.label(RichText::new("Drop FSB files here").size(24.0).color(text));
                             ui.add_space(8.0);
                             ui.label(
-                                RichText::new("Supports Sekiro, Dark Souls 1/2/3")
+                                RichText::new("FSB4 and FSB5 banks; the game is detected on load")
                                     .size(12.0)
                                     .color(text_dim),
                             );
@@ -635,49 +1954,172 @@ impl eframe::App for CumsApp {
                 let Some(file_id) = self.selected_file else {
                     return;
                 };
-                let (has_changes, sounds, replacements, file_name) = {
+                let (
+                    has_changes,
+                    mut sounds,
+                    replacements,
+                    file_name,
+                    size_summary,
+                    mode_summary,
+                    game_summary,
+                ) = {
                     let Some(file) = self.files.iter().find(|f| f.id == file_id) else {
                         return;
                     };
-                    let repl: Vec<(usize, f32, f32, f32)> = file
+                    let repl: Vec<_> = file
                         .replacements
                         .iter()
-                        .map(|r| {
-                            (
-                                r.sound_idx,
-                                r.settings.volume_db,
-                                r.settings.pitch_semitones,
-                                r.settings.speed,
-                            )
-                        })
+                        .map(|r| (r.sound_idx, r.settings.clone(), r.loop_override))
                         .collect();
-                    (file.has_changes(), file.sounds(), repl, file.name())
+                    (
+                        file.has_changes(),
+                        file.sounds(),
+                        repl,
+                        file.name(),
+                        file.size_summary(),
+                        file.mode_summary(),
+                        (
+                            file.bank.guess_game().label(),
+                            file.bank.encryption_scheme(),
+                        ),
+                    )
                 };
 
+                let pin_key = self
+                    .files
+                    .iter()
+                    .find(|f| f.id == file_id)
+                    .map(|f| f.pin_key())
+                    .unwrap_or_default();
+                for sound in &mut sounds {
+                    sound.pinned = self.config.is_pinned(&pin_key, &sound.name);
+                }
+
                 let playing = self.playing;
+                let playing_replacement = self.playing_replacement;
                 let is_playing = self.is_playing();
                 let editing_sound = self.editing_sound;
+                let selection_len = self.selected_sounds.len();
 
+                let missing_headers = sounds.iter().filter(|s| s.missing_header).count();
                 let mut do_extract_all = false;
+                let mut do_extract_selected = false;
+                let mut do_audacity = false;
+                let mut do_self_test = false;
+                let mut do_layout = false;
+                let mut do_swap: Option<bool> = None;
                 let mut do_save = false;
+                let mut do_save_as: Option<Encryption> = None;
+                let version = self
+                    .files
+                    .iter()
+                    .find(|f| f.id == file_id)
+                    .map(|f| f.bank.version);
+                let truncated = self
+                    .files
+                    .iter()
+                    .find(|f| f.id == file_id)
+                    .is_some_and(|f| f.bank.truncated);
+                // Swaps alone don't re-encode anything, and FSB3/FSB4 use ffmpeg
+                let save_blocked = version == Some(Version::Fsb5)
+                    && !replacements.is_empty()
+                    && !self.fsbankcl_available();
 
                 ui.horizontal(|ui| {
                     ui.label(RichText::new(&file_name).size(20.0).color(text).strong());
                     ui.label(RichText::new(format!("({} sounds)", sounds.len())).color(text_dim));
+                    if missing_headers > 0 {
+                        ui.label(
+                            RichText::new(format!("{} missing headers", missing_headers))
+                                .size(12.0)
+                                .color(warning),
+                        )
+                        .on_hover_text("Vorbis sounds whose setup header isn't in the database");
+                    }
+                    if truncated {
+                        ui.label(RichText::new("Truncated").size(12.0).color(warning))
+                            .on_hover_text("The file ends inside the sample headers; only the sounds before the cut were loaded");
+                    }
+                    ui.label(RichText::new(&size_summary).size(12.0).color(text_dim));
+                    if let Some((label, detail)) = &mode_summary {
+                        ui.label(RichText::new(label).size(12.0).color(text_dim))
+                            .on_hover_text(detail);
+                    }
+                    let (game, scheme) = &game_summary;
+                    ui.label(RichText::new(game).size(12.0).color(text_dim))
+                        .on_hover_text(format!("Detected from format, codec and encryption\nEncryption: {}", scheme));
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         if has_changes
                             && ui
-                                .add(
+                                .add_enabled(
+                                    !save_blocked,
                                     egui::Button::new(RichText::new("Save").color(Color32::WHITE))
                                         .fill(accent),
                                 )
+                                .on_disabled_hover_text("fsbankcl not found — set CUMS_FSBANKCL")
                                 .clicked()
                         {
                             do_save = true;
                         }
+                        ui.menu_button("Save As...", |ui| {
+                            for (label, v, encryption) in OPEN_AS {
+                                if Some(v) == version && ui.button(label).clicked() {
+                                    do_save_as = Some(encryption);
+                                    ui.close_menu();
+                                }
+                            }
+                        })
+                        .response
+                        .on_hover_text("Write a copy with the chosen encryption, pending replacements not included");
+                        if ui
+                            .button("Layout")
+                            .on_hover_text("Compare sample offsets now and after saving")
+                            .clicked()
+                        {
+                            do_layout = true;
+                        }
+                        if ui
+                            .button("Self Test")
+                            .on_hover_text("Check each sound's audio against its header")
+                            .clicked()
+                        {
+                            do_self_test = true;
+                        }
+                        if ui
+                            .button("Export for Audacity")
+                            .on_hover_text("One WAV of the whole bank plus a label track")
+                            .clicked()
+                        {
+                            do_audacity = true;
+                        }
                         if ui.button("Export All").clicked() {
                             do_extract_all = true;
                         }
+                        if selection_len > 0
+                            && ui
+                                .button(format!("Export Selected ({})", selection_len))
+                                .clicked()
+                        {
+                            do_extract_selected = true;
+                        }
+                        if selection_len == 2 {
+                            let swap = ui
+                                .button("Swap")
+                                .on_hover_text("Swap the audio of the two selected sounds");
+                            if swap.clicked() {
+                                do_swap = Some(false);
+                            }
+                            swap.context_menu(|ui| {
+                                if ui.button("Swap audio only").clicked() {
+                                    do_swap = Some(false);
+                                    ui.close_menu();
+                                }
+                                if ui.button("Swap including names").clicked() {
+                                    do_swap = Some(true);
+                                    ui.close_menu();
+                                }
+                            });
+                        }
                     });
                 });
 
@@ -688,34 +2130,195 @@ impl eframe::App for CumsApp {
                             .hint_text("Search sounds...")
                             .desired_width(300.0),
                     );
+                    if self.files.len() > 1 || self.search_all_files {
+                        ui.checkbox(&mut self.search_all_files, "All files")
+                            .on_hover_text("Search sound names in every open bank");
+                    }
+                    if missing_headers > 0 || self.missing_headers_only {
+                        ui.checkbox(&mut self.missing_headers_only, "Missing headers only")
+                            .on_hover_text("Show only sounds that can't be rebuilt to OGG");
+                    }
+                    ui.checkbox(&mut self.group_by_crc, "Group by setup header")
+                        .on_hover_text("Cluster sounds that share a Vorbis setup header");
                 });
                 ui.add_space(16.0);
 
+                if self.search_all_files && !self.search_query.is_empty() {
+                    let done = self.step_global_search();
+                    if !done {
+                        ui.ctx().request_repaint();
+                    }
+                    let Some(search) = &self.global_search else {
+                        return;
+                    };
+                    let mut banks: Vec<usize> = search.hits.iter().map(|h| h.0).collect();
+                    banks.dedup();
+                    let mut summary = format!(
+                        "{} matches in {} of {} files",
+                        search.hits.len(),
+                        banks.len(),
+                        self.files.len()
+                    );
+                    if search.hits.len() > SEARCH_HITS_SHOWN {
+                        summary += &format!(", showing the first {}", SEARCH_HITS_SHOWN);
+                    }
+                    if !done {
+                        summary += " (searching...)";
+                    }
+                    ui.label(RichText::new(summary).size(12.0).color(text_dim));
+                    ui.add_space(8.0);
+
+                    let mut jump = None;
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        let shown = &search.hits[..search.hits.len().min(SEARCH_HITS_SHOWN)];
+                        for (i, (id, index, name)) in shown.iter().enumerate() {
+                            if i == 0 || shown[i - 1].0 != *id {
+                                let bank = self
+                                    .files
+                                    .iter()
+                                    .find(|f| f.id == *id)
+                                    .map(|f| f.name())
+                                    .unwrap_or_default();
+                                ui.add_space(8.0);
+                                ui.label(RichText::new(bank).size(11.0).color(text_dim).strong());
+                            }
+                            let hit = ui
+                                .add(
+                                    egui::Label::new(
+                                        RichText::new(format!("#{}  {}", index, name)).color(text),
+                                    )
+                                    .sense(egui::Sense::click()),
+                                )
+                                .on_hover_text("Show in its bank");
+                            if hit.clicked() {
+                                jump = Some((*id, *index));
+                            }
+                        }
+                    });
+                    if let Some((id, index)) = jump {
+                        self.jump_to_sound(id, index);
+                    }
+                    return;
+                }
+
                 let mut action: Option<(usize, &str)> = None;
-                let mut settings_change: Option<(usize, f32, f32, f32)> = None;
+                let mut settings_change: Option<(usize, AudioSettings)> = None;
+                let mut loop_change: Option<(usize, Option<(u32, u32)>)> = None;
+                let mut toggle_group: Option<Option<u32>> = None;
+                let mut visible_order: Vec<usize> = Vec::new();
+                let mut missing_waveforms: Vec<usize> = Vec::new();
+
+                // Sounds per setup header across the whole bank, search aside
+                let (codec, group_sizes) = {
+                    let bank = &self.files.iter().find(|f| f.id == file_id).unwrap().bank;
+                    let mut sizes = std::collections::BTreeMap::new();
+                    for sound in &sounds {
+                        sizes.entry(sound.crc).or_insert_with(|| match sound.crc {
+                            Some(crc) => bank.samples_with_crc(crc).len(),
+                            None => sounds.iter().filter(|s| s.crc.is_none()).count(),
+                        });
+                    }
+                    (bank.codec, sizes)
+                };
 
+                let scroll_to = self.scroll_to_sound.take();
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     let query = self.search_query.to_lowercase();
-                    for sound in sounds
+                    let mut visible: Vec<&SoundInfo> = sounds
                         .iter()
                         .filter(|s| query.is_empty() || s.name.to_lowercase().contains(&query))
-                    {
-                        let is_playing_this = playing == Some((file_id, sound.index)) && is_playing;
-                        let is_editing = editing_sound == Some(sound.index);
-                        let card_bg = if is_playing_this { bg_hover } else { bg_card };
-
-                        egui::Frame::none()
-                            .fill(card_bg)
-                            .rounding(12.0)
-                            .stroke(if sound.modified {
-                                Stroke::new(1.0, warning)
-                            } else {
-                                Stroke::NONE
-                            })
-                            .inner_margin(16.0)
+                        .filter(|s| !self.missing_headers_only || s.missing_header)
+                        .collect();
+                    if self.group_by_crc {
+                        visible.sort_by_key(|s| s.crc);
+                    } else {
+                        visible.sort_by_key(|s| !s.pinned);
+                    }
+                    let has_pinned = visible.first().is_some_and(|s| s.pinned);
+                    for (i, sound) in visible.iter().enumerate() {
+                        if self.group_by_crc {
+                            let collapsed = self.collapsed_groups.contains(&sound.crc);
+                            if i == 0 || visible[i - 1].crc != sound.crc {
+                                let arrow = if collapsed { ">" } else { "v" };
+                                let (title, color) = match sound.crc {
+                                    Some(crc) if has_vorbis_setup_header(crc) => {
+                                        (format!("Setup 0x{:08X}, known", crc), success)
+                                    }
+                                    Some(crc) => {
+                                        (format!("Setup 0x{:08X}, unknown", crc), warning)
+                                    }
+                                    None if codec == Codec::Vorbis => {
+                                        ("No setup CRC".to_string(), warning)
+                                    }
+                                    None => (format!("All sounds ({:?})", codec), text_dim),
+                                };
+                                ui.add_space(4.0);
+                                let header = ui.add(
+                                    egui::Label::new(
+                                        RichText::new(format!(
+                                            "{} {} ({} sounds)",
+                                            arrow,
+                                            title,
+                                            group_sizes.get(&sound.crc).copied().unwrap_or(0)
+                                        ))
+                                        .size(12.0)
+                                        .color(color),
+                                    )
+                                    .sense(egui::Sense::click()),
+                                );
+                                if header.clicked() {
+                                    toggle_group = Some(sound.crc);
+                                }
+                            }
+                            if collapsed {
+                                continue;
+                            }
+                        } else if has_pinned
+                            && (i == 0 || (!sound.pinned && visible[i - 1].pinned))
+                        {
+                            ui.add_space(4.0);
+                            ui.label(
+                                RichText::new(if sound.pinned { "PINNED" } else { "ALL SOUNDS" })
+                                    .size(11.0)
+                                    .color(text_dim),
+                            );
+                        }
+                        visible_order.push(sound.index);
+                        if scroll_to == Some(sound.index) {
+                            ui.scroll_to_cursor(Some(egui::Align::Center));
+                        }
+                        let is_playing_this = playing == Some((file_id, sound.index)) && is_playing;
+                        let is_editing = editing_sound == Some(sound.index);
+                        let is_selected = self.selected_sounds.contains(&sound.index);
+                        let is_cursor = self.selected_sound == Some(sound.index);
+                        let card_bg = if is_playing_this || is_selected {
+                            bg_hover
+                        } else {
+                            bg_card
+                        };
+
+                        egui::Frame::none()
+                            .fill(card_bg)
+                            .rounding(12.0)
+                            .stroke(if is_cursor {
+                                Stroke::new(1.0, accent)
+                            } else if sound.modified {
+                                Stroke::new(1.0, warning)
+                            } else {
+                                Stroke::NONE
+                            })
+                            .inner_margin(16.0)
                             .outer_margin(egui::Margin::symmetric(0.0, 4.0))
                             .show(ui, |ui| {
                                 ui.horizontal(|ui| {
+                                    let mut checked = is_selected;
+                                    if ui
+                                        .checkbox(&mut checked, "")
+                                        .on_hover_text("Select (Shift+click for a range)")
+                                        .clicked()
+                                    {
+                                        action = Some((sound.index, "select"));
+                                    }
                                     let play_icon = if is_playing_this { "Stop" } else { "Play" };
                                     let play_color = if is_playing_this { success } else { accent };
                                     if ui
@@ -744,6 +2347,30 @@ impl eframe::App for CumsApp {
                                                         .color(warning),
                                                 );
                                             }
+                                            if sound.empty {
+                                                ui.label(
+                                                    RichText::new("Empty")
+                                                        .size(10.0)
+                                                        .color(text_dim),
+                                                )
+                                                .on_hover_text(
+                                                    "No audio data; exports skip this sound",
+                                                );
+                                            }
+                                            if !sound.aliases.is_empty() {
+                                                ui.label(
+                                                    RichText::new(format!(
+                                                        "Alias x{}",
+                                                        sound.aliases.len() + 1
+                                                    ))
+                                                    .size(10.0)
+                                                    .color(accent),
+                                                )
+                                                .on_hover_text(format!(
+                                                    "Shares audio with: {}",
+                                                    sound.aliases.join(", ")
+                                                ));
+                                            }
                                         });
                                         let mins = sound.duration_secs as u32 / 60;
                                         let secs = sound.duration_secs as u32 % 60;
@@ -762,12 +2389,49 @@ impl eframe::App for CumsApp {
                                         );
                                     });
 
+                                    ui.add_space(12.0);
+                                    let (rect, _) = ui.allocate_exact_size(
+                                        Vec2::new(WAVEFORM_BINS as f32, 28.0),
+                                        egui::Sense::hover(),
+                                    );
+                                    match self.waveforms.get(&(file_id, sound.index)) {
+                                        Some(Some(peaks)) => {
+                                            let mid = rect.center().y;
+                                            let half = rect.height() / 2.0;
+                                            let step = rect.width() / peaks.len() as f32;
+                                            for (i, &(lo, hi)) in peaks.iter().enumerate() {
+                                                let x = rect.left() + (i as f32 + 0.5) * step;
+                                                let top = mid - hi.clamp(0.0, 1.0) * half;
+                                                let bottom = mid - lo.clamp(-1.0, 0.0) * half;
+                                                ui.painter().line_segment(
+                                                    [
+                                                        egui::pos2(x, top.min(mid - 0.5)),
+                                                        egui::pos2(x, bottom.max(mid + 0.5)),
+                                                    ],
+                                                    Stroke::new(1.0, text_dim),
+                                                );
+                                            }
+                                        }
+                                        Some(None) => {}
+                                        None => {
+                                            if ui.is_rect_visible(rect) {
+                                                missing_waveforms.push(sound.index);
+                                            }
+                                        }
+                                    }
+
                                     ui.with_layout(
                                         egui::Layout::right_to_left(egui::Align::Center),
                                         |ui| {
                                             if ui.button("Export").clicked() {
                                                 action = Some((sound.index, "extract"));
                                             }
+                                            if ui
+                                                .button(if sound.pinned { "Unpin" } else { "Pin" })
+                                                .clicked()
+                                            {
+                                                action = Some((sound.index, "toggle_pin"));
+                                            }
                                             if ui
                                                 .add(egui::Button::new("Replace").fill(accent_dim))
                                                 .clicked()
@@ -799,10 +2463,9 @@ impl eframe::App for CumsApp {
                                             if let Some(repl) =
                                                 replacements.iter().find(|r| r.0 == sound.index)
                                             {
-                                                let (_, vol, pitch, spd) = *repl;
-                                                let mut new_vol = vol;
-                                                let mut new_pitch = pitch;
-                                                let mut new_speed = spd;
+                                                let (_, current, current_loop) = repl;
+                                                let mut edited = current.clone();
+                                                let mut edited_loop = *current_loop;
 
                                                 ui.horizontal(|ui| {
                                                     ui.label(
@@ -817,9 +2480,32 @@ impl eframe::App for CumsApp {
                                                         ),
                                                         |ui| {
                                                             if ui.button("Reset").clicked() {
-                                                                new_vol = 0.0;
-                                                                new_pitch = 0.0;
-                                                                new_speed = 1.0;
+                                                                edited = AudioSettings::default();
+                                                                edited_loop = None;
+                                                            }
+                                                            let ab_label = match (
+                                                                is_playing_this,
+                                                                playing_replacement,
+                                                            ) {
+                                                                (false, _) => "A/B",
+                                                                (true, false) => {
+                                                                    "A/B: Original"
+                                                                }
+                                                                (true, true) => {
+                                                                    "A/B: Replacement"
+                                                                }
+                                                            };
+                                                            if ui
+                                                                .button(ab_label)
+                                                                .on_hover_text(
+                                                                    "Switch between the original and the processed replacement",
+                                                                )
+                                                                .clicked()
+                                                            {
+                                                                action = Some((
+                                                                    sound.index,
+                                                                    "toggle_ab",
+                                                                ));
                                                             }
                                                         },
                                                     );
@@ -837,7 +2523,7 @@ impl eframe::App for CumsApp {
                                                         );
                                                         ui.add(
                                                             egui::Slider::new(
-                                                                &mut new_vol,
+                                                                &mut edited.volume_db,
                                                                 -20.0..=20.0,
                                                             )
                                                             .suffix(" dB")
@@ -852,7 +2538,7 @@ impl eframe::App for CumsApp {
                                                         );
                                                         ui.add(
                                                             egui::Slider::new(
-                                                                &mut new_pitch,
+                                                                &mut edited.pitch_semitones,
                                                                 -12.0..=12.0,
                                                             )
                                                             .suffix(" st")
@@ -867,24 +2553,148 @@ impl eframe::App for CumsApp {
                                                         );
                                                         ui.add(
                                                             egui::Slider::new(
-                                                                &mut new_speed,
+                                                                &mut edited.speed,
                                                                 0.5..=2.0,
                                                             )
                                                             .step_by(0.05),
                                                         );
                                                         ui.end_row();
+
+                                                        ui.label(
+                                                            RichText::new("Fade In")
+                                                                .color(text)
+                                                                .size(12.0),
+                                                        );
+                                                        ui.add(
+                                                            egui::Slider::new(
+                                                                &mut edited.fade_in_ms,
+                                                                0.0..=2000.0,
+                                                            )
+                                                            .suffix(" ms")
+                                                            .step_by(10.0),
+                                                        );
+                                                        ui.end_row();
+
+                                                        ui.label(
+                                                            RichText::new("Fade Out")
+                                                                .color(text)
+                                                                .size(12.0),
+                                                        );
+                                                        ui.add(
+                                                            egui::Slider::new(
+                                                                &mut edited.fade_out_ms,
+                                                                0.0..=2000.0,
+                                                            )
+                                                            .suffix(" ms")
+                                                            .step_by(10.0),
+                                                        );
+                                                        ui.end_row();
+
+                                                        ui.label(
+                                                            RichText::new("Quality")
+                                                                .color(text)
+                                                                .size(12.0),
+                                                        );
+                                                        ui.add(egui::Slider::new(
+                                                            &mut edited.quality,
+                                                            1..=100,
+                                                        ));
+                                                        ui.end_row();
+
+                                                        ui.label(
+                                                            RichText::new("Format")
+                                                                .color(text)
+                                                                .size(12.0),
+                                                        );
+                                                        let format_name = |codec: Option<Codec>| match codec {
+                                                            None => "Same as bank",
+                                                            Some(Codec::Vorbis) => "Vorbis",
+                                                            Some(Codec::Pcm16) => "PCM16",
+                                                            Some(Codec::ImaAdpcm) => "IMA ADPCM",
+                                                            Some(_) => "Other",
+                                                        };
+                                                        egui::ComboBox::from_id_salt("target_codec")
+                                                            .selected_text(format_name(edited.target_codec))
+                                                            .show_ui(ui, |ui| {
+                                                                for codec in [
+                                                                    None,
+                                                                    Some(Codec::Vorbis),
+                                                                    Some(Codec::Pcm16),
+                                                                    Some(Codec::ImaAdpcm),
+                                                                ] {
+                                                                    ui.selectable_value(
+                                                                        &mut edited.target_codec,
+                                                                        codec,
+                                                                        format_name(codec),
+                                                                    );
+                                                                }
+                                                            })
+                                                            .response
+                                                            .on_hover_text(
+                                                                "Only banks with a single sound can change format",
+                                                            );
+                                                        ui.end_row();
+
+                                                        ui.label(
+                                                            RichText::new("Size")
+                                                                .color(text)
+                                                                .size(12.0),
+                                                        );
+                                                        ui.checkbox(
+                                                            &mut edited.keep_size,
+                                                            "Keep original size",
+                                                        )
+                                                        .on_hover_text(
+                                                            "Lower the Vorbis quality until the sample fits its original slot",
+                                                        );
+                                                        ui.end_row();
+
+                                                        ui.label(
+                                                            RichText::new("Loop")
+                                                                .color(text)
+                                                                .size(12.0),
+                                                        );
+                                                        let mut looped = edited_loop.is_some();
+                                                        if ui
+                                                            .checkbox(&mut looped, "Override loop points")
+                                                            .on_hover_text(
+                                                                "Points past the end of the replacement are clamped on save",
+                                                            )
+                                                            .changed()
+                                                        {
+                                                            edited_loop = looped.then_some((
+                                                                0,
+                                                                sound.samples as u32,
+                                                            ));
+                                                        }
+                                                        ui.end_row();
+
+                                                        if let Some((start, end)) = &mut edited_loop {
+                                                            let max = sound.samples as u32;
+                                                            ui.label(
+                                                                RichText::new("Loop Start")
+                                                                    .color(text)
+                                                                    .size(12.0),
+                                                            );
+                                                            ui.add(egui::Slider::new(start, 0..=max));
+                                                            ui.end_row();
+
+                                                            ui.label(
+                                                                RichText::new("Loop End")
+                                                                    .color(text)
+                                                                    .size(12.0),
+                                                            );
+                                                            ui.add(egui::Slider::new(end, 0..=max));
+                                                            ui.end_row();
+                                                            *end = (*end).max(*start);
+                                                        }
                                                     });
 
-                                                if (new_vol - vol).abs() > 0.01
-                                                    || (new_pitch - pitch).abs() > 0.01
-                                                    || (new_speed - spd).abs() > 0.01
-                                                {
-                                                    settings_change = Some((
-                                                        sound.index,
-                                                        new_vol,
-                                                        new_pitch,
-                                                        new_speed,
-                                                    ));
+                                                if edited != *current {
+                                                    settings_change = Some((sound.index, edited));
+                                                }
+                                                if edited_loop != *current_loop {
+                                                    loop_change = Some((sound.index, edited_loop));
                                                 }
                                             }
                                         });
@@ -894,10 +2704,24 @@ impl eframe::App for CumsApp {
                 });
 
                 if let Some((idx, act)) = action {
+                    self.selected_sound = Some(idx);
                     match act {
                         "play" => self.play(file_id, idx),
+                        "toggle_ab" => self.toggle_ab(file_id, idx),
+                        "toggle_pin" => {
+                            if let Some(sound) = sounds.iter().find(|s| s.index == idx) {
+                                self.config.toggle_pin(&pin_key, &sound.name);
+                                if let Err(e) = self.config.save() {
+                                    self.status = format!("Could not save settings: {}", e);
+                                }
+                            }
+                        }
                         "replace" => self.replace(file_id, idx),
                         "extract" => self.extract(file_id, idx),
+                        "select" => {
+                            let extend = ctx.input(|i| i.modifiers.shift);
+                            self.toggle_selection(idx, extend);
+                        }
                         "toggle_settings" => {
                             if self.editing_sound == Some(idx) {
                                 self.editing_sound = None;
@@ -909,14 +2733,79 @@ impl eframe::App for CumsApp {
                     }
                 }
 
-                if let Some((idx, vol, pitch, speed)) = settings_change {
+                if !missing_waveforms.is_empty() {
+                    if let Some(file) = self.files.iter().find(|f| f.id == file_id) {
+                        for &idx in missing_waveforms.iter().take(WAVEFORMS_PER_FRAME) {
+                            let peaks = waveform_peaks(&file.bank, &file.bank.samples[idx]);
+                            self.waveforms.insert((file_id, idx), peaks);
+                        }
+                    }
+                    ctx.request_repaint();
+                }
+
+                if let Some(group) = toggle_group {
+                    if !self.collapsed_groups.remove(&group) {
+                        self.collapsed_groups.insert(group);
+                    }
+                }
+
+                // Shortcuts stay off while a text field has focus so typing a
+                // search doesn't play or move anything
+                if !busy && !ctx.wants_keyboard_input() {
+                    let (up, down, space, enter, save) = ctx.input_mut(|i| {
+                        (
+                            i.consume_key(Modifiers::NONE, Key::ArrowUp),
+                            i.consume_key(Modifiers::NONE, Key::ArrowDown),
+                            i.consume_key(Modifiers::NONE, Key::Space),
+                            i.consume_key(Modifiers::NONE, Key::Enter),
+                            i.consume_key(Modifiers::COMMAND, Key::S),
+                        )
+                    });
+                    let cursor = self
+                        .selected_sound
+                        .and_then(|s| visible_order.iter().position(|&v| v == s));
+                    if up || down {
+                        let next = match cursor {
+                            Some(pos) if down => (pos + 1).min(visible_order.len() - 1),
+                            Some(pos) => pos.saturating_sub(1),
+                            None => 0,
+                        };
+                        if let Some(&idx) = visible_order.get(next) {
+                            self.selected_sound = Some(idx);
+                            self.scroll_to_sound = Some(idx);
+                            ctx.request_repaint();
+                        }
+                    }
+                    if let Some(&idx) = cursor.and_then(|pos| visible_order.get(pos)) {
+                        if space {
+                            self.play(file_id, idx);
+                        } else if enter {
+                            self.stop();
+                            self.play(file_id, idx);
+                        }
+                    }
+                    if save && has_changes && !save_blocked {
+                        do_save = true;
+                    }
+                }
+
+                if let Some((idx, settings)) = settings_change {
+                    if let Some(file) = self.files.iter_mut().find(|f| f.id == file_id) {
+                        if let Some(repl) =
+                            file.replacements.iter_mut().find(|r| r.sound_idx == idx)
+                        {
+                            repl.settings = settings.clone();
+                        }
+                    }
+                    self.last_settings = Some(settings);
+                }
+
+                if let Some((idx, points)) = loop_change {
                     if let Some(file) = self.files.iter_mut().find(|f| f.id == file_id) {
                         if let Some(repl) =
                             file.replacements.iter_mut().find(|r| r.sound_idx == idx)
                         {
-                            repl.settings.volume_db = vol;
-                            repl.settings.pitch_semitones = pitch;
-                            repl.settings.speed = speed;
+                            repl.loop_override = points;
                         }
                     }
                 }
@@ -924,11 +2813,534 @@ impl eframe::App for CumsApp {
                 if do_extract_all {
                     self.extract_all(file_id);
                 }
+                if do_extract_selected {
+                    self.extract_selected(file_id);
+                }
+                if do_audacity {
+                    self.export_for_audacity(file_id);
+                }
+                if do_self_test {
+                    self.run_self_test(file_id);
+                }
+                if do_layout {
+                    self.show_layout(file_id);
+                }
+                if let Some(include_names) = do_swap {
+                    self.swap_selected(file_id, include_names);
+                }
                 if do_save {
-                    self.save(file_id);
+                    self.review_save(file_id);
+                }
+                if let Some(encryption) = do_save_as {
+                    self.save_as(file_id, encryption);
                 }
             });
 
+        if let Some(task) = &self.task {
+            let progress = task.progress();
+            let mut cancel = false;
+            egui::Window::new(&task.title)
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+                .show(ctx, |ui| {
+                    ui.label(RichText::new(&progress.phase).color(text));
+                    ui.add(
+                        egui::ProgressBar::new(progress.fraction())
+                            .desired_width(320.0)
+                            .text(format!("{} / {}", progress.current, progress.total)),
+                    );
+                    if task.is_cancelled() {
+                        ui.label(RichText::new("Cancelling...").color(text_dim));
+                    } else if ui.button("Cancel").clicked() {
+                        cancel = true;
+                    }
+                });
+            if cancel {
+                task.cancel();
+            }
+        }
+
+        if let Some((name, lines)) = &self.self_test_report {
+            let mut close = false;
+            egui::Window::new("Self test")
+                .collapsible(false)
+                .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+                .show(ctx, |ui| {
+                    ui.label(
+                        RichText::new(if lines.is_empty() {
+                            format!("{}: no problems found", name)
+                        } else {
+                            format!("{}: {} sound(s) need a closer look", name, lines.len())
+                        })
+                        .color(text),
+                    );
+                    ui.add_space(8.0);
+                    egui::ScrollArea::vertical()
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            for line in lines {
+                                ui.label(RichText::new(line).size(12.0).color(warning));
+                            }
+                        });
+                    ui.add_space(8.0);
+                    if ui.button("Close").clicked() {
+                        close = true;
+                    }
+                });
+            if close {
+                self.self_test_report = None;
+            }
+        }
+
+        if let Some((name, rows)) = &self.layout_report {
+            let mut open = true;
+            egui::Window::new(format!("Layout: {}", name))
+                .open(&mut open)
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    let moved = rows.iter().filter(|r| r.now != r.saved).count();
+                    ui.label(
+                        RichText::new(format!(
+                            "{} of {} sample(s) move on save",
+                            moved,
+                            rows.len()
+                        ))
+                        .color(text),
+                    );
+                    ui.add_space(8.0);
+                    egui::ScrollArea::vertical()
+                        .max_height(400.0)
+                        .show(ui, |ui| {
+                            egui::Grid::new("layout_grid").striped(true).show(ui, |ui| {
+                                for heading in
+                                    ["Sound", "Offset", "Size", "Saved offset", "Saved size"]
+                                {
+                                    ui.label(RichText::new(heading).color(text_dim));
+                                }
+                                ui.end_row();
+                                for row in rows {
+                                    let ((offset, size), (new_offset, new_size)) =
+                                        (row.now, row.saved);
+                                    let color = |same: bool| if same { text } else { warning };
+                                    ui.label(RichText::new(&row.name).color(text));
+                                    ui.label(RichText::new(format!("0x{:X}", offset)).color(text));
+                                    ui.label(RichText::new(size.to_string()).color(text));
+                                    ui.label(
+                                        RichText::new(format!("0x{:X}", new_offset))
+                                            .color(color(offset == new_offset)),
+                                    );
+                                    ui.label(
+                                        RichText::new(new_size.to_string())
+                                            .color(color(size == new_size)),
+                                    );
+                                    ui.end_row();
+                                }
+                            });
+                        });
+                });
+            if !open {
+                self.layout_report = None;
+            }
+        }
+
+        if let Some((file_id, lines, warnings)) = &self.save_review {
+            let file_id = *file_id;
+            let mut confirm = false;
+            let mut cancel = false;
+            egui::Window::new("Review changes")
+                .collapsible(false)
+                .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+                .show(ctx, |ui| {
+                    ui.label(
+                        RichText::new(format!(
+                            "{} change(s) compared to the file on disk",
+                            lines.len()
+                        ))
+                        .color(text),
+                    );
+                    for warning_line in warnings {
+                        ui.label(RichText::new(warning_line).color(warning));
+                    }
+                    ui.add_space(8.0);
+                    egui::ScrollArea::vertical()
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            for line in lines {
+                                ui.label(RichText::new(line).size(12.0).color(text_dim));
+                            }
+                        });
+                    ui.add_space(8.0);
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add(
+                                egui::Button::new(RichText::new("Save...").color(Color32::WHITE))
+                                    .fill(accent),
+                            )
+                            .clicked()
+                        {
+                            confirm = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            cancel = true;
+                        }
+                    });
+                });
+            if confirm {
+                self.save_review = None;
+                self.save(file_id);
+            } else if cancel {
+                self.save_review = None;
+            }
+        }
+
+        if let Some((file_id, _, _, aliases)) = &self.pending_alias {
+            let names: Vec<String> = self
+                .files
+                .iter()
+                .find(|f| f.id == *file_id)
+                .map(|f| {
+                    aliases
+                        .iter()
+                        .map(|&i| f.bank.samples[i].display_name())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let mut choice = None;
+            egui::Window::new("Shared audio")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+                .show(ctx, |ui| {
+                    ui.label(
+                        RichText::new(format!(
+                            "This sound shares its audio with {} other sound(s):",
+                            names.len()
+                        ))
+                        .color(text),
+                    );
+                    ui.add_space(8.0);
+                    for name in &names {
+                        ui.label(RichText::new(name).size(12.0).color(text_dim));
+                    }
+                    ui.add_space(8.0);
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add(
+                                egui::Button::new(
+                                    RichText::new("Replace all").color(Color32::WHITE),
+                                )
+                                .fill(accent),
+                            )
+                            .clicked()
+                        {
+                            choice = Some(true);
+                        }
+                        if ui.button("Only this one").clicked() {
+                            choice = Some(false);
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.pending_alias = None;
+                        }
+                    });
+                });
+            if let Some(all) = choice {
+                if let Some((file_id, sound_idx, path, aliases)) = self.pending_alias.take() {
+                    let linked = if all { aliases } else { Vec::new() };
+                    self.add_replacement(file_id, sound_idx, path, linked);
+                }
+            }
+        }
+
+        if let Some(ids) = &self.pending_close {
+            let unsaved: Vec<String> = self
+                .files
+                .iter()
+                .filter(|f| ids.contains(&f.id) && f.has_changes())
+                .map(|f| f.name())
+                .collect();
+            let mut confirm = false;
+            let mut cancel = false;
+            egui::Window::new("Unsaved changes")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+                .show(ctx, |ui| {
+                    ui.label(
+                        RichText::new(format!(
+                            "Discard unsaved changes in {} file(s)?",
+                            unsaved.len()
+                        ))
+                        .color(text),
+                    );
+                    ui.add_space(8.0);
+                    for name in &unsaved {
+                        ui.label(RichText::new(name).size(12.0).color(text_dim));
+                    }
+                    ui.add_space(8.0);
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add(
+                                egui::Button::new(RichText::new("Discard").color(Color32::WHITE))
+                                    .fill(accent),
+                            )
+                            .clicked()
+                        {
+                            confirm = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            cancel = true;
+                        }
+                    });
+                });
+            if confirm {
+                for id in self.pending_close.take().unwrap_or_default() {
+                    self.close_file(id);
+                }
+            } else if cancel {
+                self.pending_close = None;
+            }
+        }
+
+        if let Some((folder, rows)) = &self.coverage {
+            let mut open = true;
+            let mut sort_by = None;
+            let mut export = false;
+            let (sort_column, ascending) = self.coverage_sort;
+            egui::Window::new("CRC coverage")
+                .open(&mut open)
+                .default_width(560.0)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new(folder.display().to_string()).color(text_dim));
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.button("Export CSV...").clicked() {
+                                export = true;
+                            }
+                        });
+                    });
+                    ui.add_space(8.0);
+                    egui::ScrollArea::vertical()
+                        .max_height(400.0)
+                        .show(ui, |ui| {
+                            egui::Grid::new("coverage_table")
+                                .striped(true)
+                                .num_columns(COVERAGE_COLUMNS.len())
+                                .show(ui, |ui| {
+                                    for (i, title) in COVERAGE_COLUMNS.iter().enumerate() {
+                                        let arrow = match (i == sort_column, ascending) {
+                                            (false, _) => "",
+                                            (true, true) => " ^",
+                                            (true, false) => " v",
+                                        };
+                                        if ui
+                                            .selectable_label(
+                                                i == sort_column,
+                                                format!("{}{}", title, arrow),
+                                            )
+                                            .clicked()
+                                        {
+                                            sort_by = Some(i);
+                                        }
+                                    }
+                                    ui.end_row();
+
+                                    for row in rows {
+                                        ui.label(RichText::new(&row.bank).color(text));
+                                        if let Some(err) = &row.error {
+                                            ui.label(
+                                                RichText::new("failed to load").color(warning),
+                                            )
+                                            .on_hover_text(err);
+                                            ui.label("-");
+                                            ui.label("-");
+                                            ui.label("-");
+                                        } else {
+                                            ui.label(format!(
+                                                "{:?}",
+                                                row.encryption.unwrap_or(Encryption::None)
+                                            ));
+                                            ui.label(row.total.to_string());
+                                            ui.label(row.known.to_string());
+                                            let color = if row.known == row.total {
+                                                success
+                                            } else {
+                                                warning
+                                            };
+                                            ui.label(
+                                                RichText::new(format!("{:.0}%", row.percent()))
+                                                    .color(color),
+                                            );
+                                        }
+                                        ui.end_row();
+                                    }
+                                });
+                        });
+                });
+            if !open {
+                self.coverage = None;
+            }
+            if let Some(column) = sort_by {
+                self.coverage_sort = if column == sort_column {
+                    (column, !ascending)
+                } else {
+                    (column, true)
+                };
+                self.sort_coverage();
+            }
+            if export {
+                self.export_coverage_csv();
+            }
+        }
+
+        if self.show_settings {
+            let mut config = self.config.clone();
+            let preview_sample = self
+                .selected_file
+                .and_then(|id| self.files.iter().find(|f| f.id == id))
+                .and_then(|f| f.bank.samples.first().cloned())
+                .unwrap_or(Sample {
+                    index: 7,
+                    name: Some("footstep".into()),
+                    frequency: 44100,
+                    channels: 2,
+                    samples: 44100,
+                    data_offset: 0,
+                    data_size: 0,
+                    loop_start: None,
+                    loop_end: None,
+                    vorbis_crc: None,
+                    vorbis_seek_table: None,
+                    dsp_coeffs: None,
+                    at9_config: None,
+                    chunks: Vec::new(),
+                    mode: None,
+                });
+            let mut open = true;
+            let mut dump_header = false;
+            egui::Window::new("Settings")
+                .open(&mut open)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.checkbox(
+                        &mut config.backup_enabled,
+                        "Back up existing files before overwriting",
+                    );
+                    ui.add_enabled_ui(config.backup_enabled, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new("Location").color(text));
+                            ui.label(
+                                RichText::new(config.backup_location())
+                                    .size(12.0)
+                                    .color(text_dim),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            if ui.button("Choose folder...").clicked() {
+                                if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                                    config.backup_dir = Some(dir);
+                                }
+                            }
+                            if config.backup_dir.is_some() && ui.button("Next to file").clicked() {
+                                config.backup_dir = None;
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new("Keep").color(text));
+                            ui.add(egui::DragValue::new(&mut config.max_backups).range(1..=20));
+                            ui.label(RichText::new("backups per file").color(text));
+                        });
+                    });
+                    ui.separator();
+                    ui.checkbox(
+                        &mut config.replaygain,
+                        "Write ReplayGain tags to OGG exports",
+                    )
+                    .on_hover_text(
+                        "Measures loudness with FFmpeg; MP3 and raw exports are left untagged",
+                    );
+                    ui.checkbox(&mut config.raw_vorbis_export, "Raw extract")
+                        .on_hover_text(
+                            "Export Vorbis sounds as FMOD's packets (.fsbvorbis) instead of \
+                             rebuilding an OGG, for vgmstream and other tools",
+                        );
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new("Target game").color(text));
+                        egui::ComboBox::from_id_salt("target_game")
+                            .selected_text(config.target_game.as_deref().unwrap_or("Any"))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut config.target_game, None, "Any");
+                                for game in GameProfile::ALL {
+                                    ui.selectable_value(
+                                        &mut config.target_game,
+                                        Some(game.name().to_string()),
+                                        game.name(),
+                                    );
+                                }
+                            });
+                    })
+                    .response
+                    .on_hover_text(
+                        "Check format, codec and encryption against this game before saving",
+                    );
+                    ui.checkbox(
+                        &mut config.sticky_settings,
+                        "New replacements reuse the last tuned audio settings",
+                    )
+                    .on_hover_text("The first replacement of a session still starts from defaults");
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new("Export file names").color(text));
+                        ui.add(
+                            egui::TextEdit::singleline(&mut config.export_template)
+                                .hint_text(DEFAULT_NAME_TEMPLATE)
+                                .desired_width(220.0),
+                        )
+                        .on_hover_text(
+                            NAME_PLACEHOLDERS
+                                .iter()
+                                .map(|(key, help)| format!("{{{}}}  {}", key, help))
+                                .collect::<Vec<_>>()
+                                .join("\n")
+                                + "\nNumbers take a width, e.g. {index:04}",
+                        );
+                        if ui.button("Reset").clicked() {
+                            config.export_template = DEFAULT_NAME_TEMPLATE.into();
+                        }
+                    });
+                    match export_file_name(&config.export_template, &preview_sample, "ogg") {
+                        Ok(name) => ui.label(
+                            RichText::new(format!("Preview: {}", name))
+                                .size(12.0)
+                                .color(text_dim),
+                        ),
+                        Err(e) => ui.label(RichText::new(e.to_string()).size(12.0).color(warning)),
+                    };
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.header_crc_input)
+                                .hint_text("Vorbis CRC")
+                                .desired_width(120.0),
+                        );
+                        if ui.button("Dump header for CRC").clicked() {
+                            dump_header = true;
+                        }
+                    });
+                });
+            if dump_header {
+                self.dump_vorbis_header();
+            }
+            self.show_settings = open;
+            if config != self.config {
+                self.config = config;
+                if let Err(e) = self.config.save() {
+                    self.status = format!("Could not save settings: {}", e);
+                }
+            }
+        }
+
         if self.is_playing() {
             ctx.request_repaint();
         }
@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub backup_enabled: bool,
+    pub backup_dir: Option<PathBuf>,
+    pub max_backups: usize,
+    pub replaygain: bool,
+    // Vorbis exports keep FMOD's packet stream instead of a rebuilt OGG
+    pub raw_vorbis_export: bool,
+    pub pinned: BTreeMap<String, Vec<String>>,
+    pub session_files: Vec<PathBuf>,
+    pub export_template: String,
+    pub sticky_settings: bool,
+    // GameProfile name banks are checked against before saving
+    pub target_game: Option<String>,
+    pub playback_volume: f32,
+    pub loop_playback: bool,
+    pub fsbankcl_path: Option<PathBuf>,
+    // Folder the file dialogs start in, from the last one used
+    pub last_dir: Option<PathBuf>,
+    pub window_size: Option<[f32; 2]>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            backup_enabled: true,
+            backup_dir: None,
+            max_backups: 3,
+            replaygain: false,
+            raw_vorbis_export: false,
+            pinned: BTreeMap::new(),
+            session_files: Vec::new(),
+            export_template: cums_sekiro::DEFAULT_NAME_TEMPLATE.into(),
+            sticky_settings: false,
+            target_game: None,
+            playback_volume: 0.5,
+            loop_playback: false,
+            fsbankcl_path: None,
+            last_dir: None,
+            window_size: None,
+        }
+    }
+}
+
+impl AppConfig {
+    fn path() -> Option<PathBuf> {
+        let base = std::env::var_os("APPDATA")
+            .or_else(|| std::env::var_os("XDG_CONFIG_HOME"))
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+        Some(base.join("cums").join("config.json"))
+    }
+
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No config directory"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(path, json)
+    }
+
+    pub fn is_pinned(&self, file: &str, name: &str) -> bool {
+        self.pinned
+            .get(file)
+            .is_some_and(|names| names.iter().any(|n| n == name))
+    }
+
+    pub fn toggle_pin(&mut self, file: &str, name: &str) {
+        let names = self.pinned.entry(file.to_string()).or_default();
+        if let Some(pos) = names.iter().position(|n| n == name) {
+            names.remove(pos);
+        } else {
+            names.push(name.to_string());
+        }
+        if names.is_empty() {
+            self.pinned.remove(file);
+        }
+    }
+
+    // Drops pins for sounds that no longer exist, e.g. after a game patch.
+    pub fn prune_pins(&mut self, file: &str, existing: &[String]) -> bool {
+        let Some(names) = self.pinned.get_mut(file) else {
+            return false;
+        };
+        let before = names.len();
+        names.retain(|n| existing.contains(n));
+        let changed = names.len() != before;
+        if names.is_empty() {
+            self.pinned.remove(file);
+        }
+        changed
+    }
+
+    pub fn backup_location(&self) -> String {
+        match &self.backup_dir {
+            Some(dir) => dir.display().to_string(),
+            None => "Next to the original (.bak)".into(),
+        }
+    }
+
+    // Copies an existing file aside before it gets overwritten. Saving to a
+    // new path creates nothing. Only the newest `max_backups` are kept.
+    pub fn backup(&self, target: &Path) -> io::Result<Option<PathBuf>> {
+        if !self.backup_enabled || !target.is_file() {
+            return Ok(None);
+        }
+        let file_name = target.file_name().unwrap_or_default().to_string_lossy();
+        let keep = self.max_backups.max(1);
+
+        match &self.backup_dir {
+            None => {
+                let bak = |n: usize| {
+                    let suffix = if n == 0 {
+                        ".bak".to_string()
+                    } else {
+                        format!(".bak.{}", n)
+                    };
+                    target.with_file_name(format!("{}{}", file_name, suffix))
+                };
+                let _ = std::fs::remove_file(bak(keep - 1));
+                for n in (0..keep - 1).rev() {
+                    if bak(n).exists() {
+                        std::fs::rename(bak(n), bak(n + 1))?;
+                    }
+                }
+                std::fs::copy(target, bak(0))?;
+                Ok(Some(bak(0)))
+            }
+            Some(dir) => {
+                std::fs::create_dir_all(dir)?;
+                let mut stamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis())
+                    .unwrap_or_default();
+                let prefix = format!("{}.", file_name);
+                // Fixed-width stamps keep name order chronological; a taken
+                // name bumps the stamp rather than overwriting that backup.
+                let (dest, mut file) = loop {
+                    let dest = dir.join(format!("{}{:013}.bak", prefix, stamp));
+                    match std::fs::OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(&dest)
+                    {
+                        Ok(file) => break (dest, file),
+                        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => stamp += 1,
+                        Err(e) => return Err(e),
+                    }
+                };
+                io::copy(&mut std::fs::File::open(target)?, &mut file)?;
+
+                let mut existing: Vec<PathBuf> = std::fs::read_dir(dir)?
+                    .filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| {
+                        p.file_name()
+                            .map(|n| n.to_string_lossy())
+                            .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(".bak"))
+                    })
+                    .collect();
+                existing.sort();
+                let excess = existing.len().saturating_sub(keep);
+                for old in &existing[..excess] {
+                    let _ = std::fs::remove_file(old);
+                }
+                Ok(Some(dest))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_round_trips() {
+        let config = AppConfig {
+            playback_volume: 0.8,
+            loop_playback: true,
+            fsbankcl_path: Some(PathBuf::from("tools/fsbankcl.exe")),
+            last_dir: Some(PathBuf::from("sound")),
+            window_size: Some([1280.0, 720.0]),
+            ..Default::default()
+        };
+        let json = serde_json::to_string_pretty(&config).unwrap();
+        let loaded: AppConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, config);
+    }
+
+    #[test]
+    fn old_configs_get_defaults_for_new_fields() {
+        let json = r#"{ "backup_enabled": false, "max_backups": 5 }"#;
+        let loaded: AppConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            loaded,
+            AppConfig {
+                backup_enabled: false,
+                max_backups: 5,
+                ..Default::default()
+            }
+        );
+        assert_eq!(loaded.playback_volume, 0.5);
+        assert!(!loaded.loop_playback);
+        assert_eq!(loaded.fsbankcl_path, None);
+        assert_eq!(loaded.last_dir, None);
+        assert_eq!(loaded.window_size, None);
+    }
+
+    #[test]
+    fn backups_in_the_same_instant_are_all_kept() {
+        let root = std::env::temp_dir().join(format!("cums-backup-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let target = root.join("bank.fsb");
+        let config = AppConfig {
+            backup_dir: Some(root.join("backups")),
+            max_backups: 10,
+            ..Default::default()
+        };
+
+        let mut made = Vec::new();
+        for version in 0..3u8 {
+            std::fs::write(&target, [version]).unwrap();
+            made.push(config.backup(&target).unwrap().unwrap());
+        }
+        for (version, path) in made.iter().enumerate() {
+            assert_eq!(std::fs::read(path).unwrap(), [version as u8]);
+        }
+        let mut sorted = made.clone();
+        sorted.sort();
+        assert_eq!(sorted, made);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
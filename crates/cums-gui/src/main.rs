@@ -1,4 +1,6 @@
 mod app;
+mod config;
+mod progress;
 
 use eframe::NativeOptions;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -12,9 +14,10 @@ fn main() -> eframe::Result<()> {
         )
         .init();
 
+    let config = config::AppConfig::load();
     let options = NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([1000.0, 700.0])
+            .with_inner_size(config.window_size.unwrap_or([1000.0, 700.0]))
             .with_min_inner_size([600.0, 400.0]),
         ..Default::default()
     };
@@ -22,6 +25,6 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "CUMS - Sekiro Audio Modding",
         options,
-        Box::new(|cc| Ok(Box::new(app::CumsApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(app::CumsApp::new(cc, config)))),
     )
 }
@@ -0,0 +1,160 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Progress {
+    pub phase: String,
+    pub current: usize,
+    pub total: usize,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.current.min(self.total) as f32 / self.total as f32
+        }
+    }
+}
+
+// The worker's side of a task: reports progress and checks for cancellation
+// between steps
+#[derive(Clone)]
+pub struct TaskContext {
+    progress: Arc<Mutex<Progress>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl TaskContext {
+    pub fn start_phase(&self, phase: &str, total: usize) {
+        if let Ok(mut progress) = self.progress.lock() {
+            *progress = Progress {
+                phase: phase.into(),
+                current: 0,
+                total,
+            };
+        }
+    }
+
+    pub fn advance(&self) {
+        if let Ok(mut progress) = self.progress.lock() {
+            progress.current += 1;
+        }
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+// Work running on a background thread. The UI polls `progress` each frame
+// and takes the result with `try_finish` once the thread is done.
+pub struct Task<T> {
+    pub title: String,
+    context: TaskContext,
+    handle: Option<JoinHandle<T>>,
+}
+
+impl<T: Send + 'static> Task<T> {
+    pub fn spawn(title: &str, work: impl FnOnce(&TaskContext) -> T + Send + 'static) -> Self {
+        let context = TaskContext {
+            progress: Arc::default(),
+            cancel: Arc::default(),
+        };
+        let worker = context.clone();
+        Self {
+            title: title.into(),
+            context,
+            handle: Some(std::thread::spawn(move || work(&worker))),
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.context
+            .progress
+            .lock()
+            .map(|p| p.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn cancel(&self) {
+        self.context.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.context.cancelled()
+    }
+
+    // None while the worker is still running. A panicked worker gives an error.
+    pub fn try_finish(&mut self) -> Option<Result<T, String>> {
+        if !self.handle.as_ref()?.is_finished() {
+            return None;
+        }
+        let handle = self.handle.take()?;
+        Some(
+            handle
+                .join()
+                .map_err(|_| "the worker thread panicked".into()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(phase: &str, current: usize, total: usize) -> Progress {
+        Progress {
+            phase: phase.into(),
+            current,
+            total,
+        }
+    }
+
+    fn finish<T: Send + 'static>(task: &mut Task<T>) -> Result<T, String> {
+        loop {
+            if let Some(result) = task.try_finish() {
+                return result;
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn fraction_handles_empty_and_overrun() {
+        assert_eq!(progress("", 0, 0).fraction(), 0.0);
+        assert_eq!(progress("", 5, 0).fraction(), 0.0);
+        assert_eq!(progress("", 1, 4).fraction(), 0.25);
+        assert_eq!(progress("", 9, 4).fraction(), 1.0);
+    }
+
+    #[test]
+    fn phases_reset_the_count() {
+        let mut task = Task::spawn("test", |ctx| {
+            ctx.start_phase("first", 3);
+            ctx.advance();
+            ctx.advance();
+            ctx.start_phase("second", 2);
+            ctx.advance();
+        });
+        assert!(finish(&mut task).is_ok());
+        assert_eq!(task.progress(), progress("second", 1, 2));
+        assert!(task.try_finish().is_none());
+    }
+
+    #[test]
+    fn cancel_reaches_the_worker() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut task = Task::spawn("test", move |ctx| {
+            rx.recv().unwrap();
+            ctx.cancelled()
+        });
+        assert!(!task.is_cancelled());
+        task.cancel();
+        assert!(task.is_cancelled());
+        tx.send(()).unwrap();
+        assert_eq!(finish(&mut task), Ok(true));
+    }
+}
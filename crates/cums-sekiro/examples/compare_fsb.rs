@@ -2,8 +2,13 @@ use cums_sekiro::{Encryption, FsbBank};
 use std::fs;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let orig_path = r"G:\SteamLibrary\steamapps\common\Sekiro\sound\main.fsb";
-    let mod_path = r"G:\SteamLibrary\steamapps\common\Sekiro\mods\sound\main.fsb";
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        println!("Usage: {} <original_fsb> <modified_fsb>", args[0]);
+        return Ok(());
+    }
+    let orig_path = &args[1];
+    let mod_path = &args[2];
 
     println!("=== Comparing FSB files ===\n");
 
@@ -0,0 +1,78 @@
+// RIFF container for raw ATRAC9 superframes, laid out like at9tool output
+const AT9_SUBFORMAT: [u8; 16] = [
+    0xD2, 0x42, 0xE1, 0x47, 0xBA, 0x36, 0x8D, 0x4D, 0x88, 0xFC, 0x61, 0x65, 0x4F, 0x8C, 0x83, 0x6C,
+];
+const FRAME_SAMPLES_POWER: [u32; 16] = [6, 6, 7, 7, 7, 8, 8, 8, 6, 6, 7, 7, 7, 8, 8, 8];
+// Decoder priming FMOD leaves at the start of each sample
+const ENCODER_DELAY: u32 = 256;
+
+// The config word: 0xFE sync, sample rate index (4 bits), channel layout
+// (3), a validation bit, frame size - 1 (11) and frames per superframe as a
+// power of two (2)
+pub fn at9_block_info(config: [u8; 4]) -> Option<(u32, u32)> {
+    if config[0] != 0xFE {
+        return None;
+    }
+    let word = u32::from_be_bytes(config);
+    let rate_index = (word >> 20) & 0xF;
+    let frame_bytes = ((word >> 5) & 0x7FF) + 1;
+    let frames = 1 << ((word >> 3) & 0x3);
+    let samples = (1 << FRAME_SAMPLES_POWER[rate_index as usize]) * frames;
+    Some((frame_bytes * frames, samples))
+}
+
+pub fn wrap_at9(
+    raw: &[u8],
+    config: [u8; 4],
+    frequency: u32,
+    channels: u32,
+    samples: u64,
+) -> Vec<u8> {
+    let (block_align, block_samples) = at9_block_info(config).unwrap_or((0, 0));
+    let byte_rate = if block_samples > 0 {
+        (block_align as u64 * frequency as u64 / block_samples as u64) as u32
+    } else {
+        0
+    };
+    let channel_mask: u32 = match channels {
+        1 => 0x4,
+        2 => 0x3,
+        _ => 0,
+    };
+
+    let mut fmt = Vec::with_capacity(52);
+    fmt.extend_from_slice(&0xFFFEu16.to_le_bytes());
+    fmt.extend_from_slice(&(channels as u16).to_le_bytes());
+    fmt.extend_from_slice(&frequency.to_le_bytes());
+    fmt.extend_from_slice(&byte_rate.to_le_bytes());
+    fmt.extend_from_slice(&(block_align as u16).to_le_bytes());
+    fmt.extend_from_slice(&0u16.to_le_bytes());
+    fmt.extend_from_slice(&34u16.to_le_bytes());
+    fmt.extend_from_slice(&(block_samples as u16).to_le_bytes());
+    fmt.extend_from_slice(&channel_mask.to_le_bytes());
+    fmt.extend_from_slice(&AT9_SUBFORMAT);
+    fmt.extend_from_slice(&1u32.to_le_bytes());
+    fmt.extend_from_slice(&config);
+    fmt.extend_from_slice(&0u32.to_le_bytes());
+
+    let mut fact = Vec::with_capacity(12);
+    fact.extend_from_slice(&(samples as u32).to_le_bytes());
+    fact.extend_from_slice(&0u32.to_le_bytes());
+    fact.extend_from_slice(&ENCODER_DELAY.to_le_bytes());
+
+    let mut out = Vec::with_capacity(12 + 8 + fmt.len() + 8 + fact.len() + 8 + raw.len());
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    for (id, body) in [(b"fmt ", &fmt[..]), (b"fact", &fact[..]), (b"data", raw)] {
+        out.extend_from_slice(id);
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(body);
+    }
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    out
+}
@@ -0,0 +1,82 @@
+// Nintendo DSP ADPCM: 8-byte frames of a predictor/scale byte followed by 14
+// 4-bit samples. FSB stores the channels interleaved every 2 bytes.
+const FRAME_SIZE: usize = 8;
+const INTERLEAVE: usize = 2;
+
+// Bytes per channel in an FSB5 DSP coefficient chunk: sixteen big-endian
+// coefficients, then gain, initial predictor/scale, history and loop context
+pub const DSP_HEADER_SIZE: usize = 0x2E;
+
+pub fn parse_dsp_coeffs(chunk: &[u8], channels: u32) -> Option<Vec<[i16; 16]>> {
+    (0..channels as usize)
+        .map(|ch| {
+            let header = chunk.get(ch * DSP_HEADER_SIZE..(ch + 1) * DSP_HEADER_SIZE)?;
+            let mut coeffs = [0i16; 16];
+            for (i, c) in coeffs.iter_mut().enumerate() {
+                *c = i16::from_be_bytes([header[i * 2], header[i * 2 + 1]]);
+            }
+            Some(coeffs)
+        })
+        .collect()
+}
+
+fn decode_channel(data: &[u8], coeffs: &[i16; 16], frames: usize) -> Vec<i16> {
+    let mut out = Vec::with_capacity(frames);
+    let (mut hist1, mut hist2) = (0i32, 0i32);
+    for frame in data.chunks_exact(FRAME_SIZE) {
+        let predictor = ((frame[0] >> 4) & 0x7) as usize;
+        let scale = 1i32 << (frame[0] & 0xF);
+        let (coef1, coef2) = (
+            coeffs[predictor * 2] as i32,
+            coeffs[predictor * 2 + 1] as i32,
+        );
+        for byte in &frame[1..] {
+            for nibble in [byte >> 4, byte & 0xF] {
+                let delta = ((nibble << 4) as i8 >> 4) as i32;
+                let sample = (((delta * scale) << 11) + 1024 + coef1 * hist1 + coef2 * hist2) >> 11;
+                let sample = sample.clamp(i16::MIN as i32, i16::MAX as i32);
+                out.push(sample as i16);
+                hist2 = hist1;
+                hist1 = sample;
+            }
+        }
+        if out.len() >= frames {
+            break;
+        }
+    }
+    out.truncate(frames);
+    out
+}
+
+// Decodes to interleaved PCM16, stopping after `samples` frames per channel.
+// None if there is no coefficient table for every channel.
+pub fn decode_gcadpcm(
+    data: &[u8],
+    channels: u32,
+    samples: u64,
+    coeffs: &[[i16; 16]],
+) -> Option<Vec<u8>> {
+    let channels = channels as usize;
+    if channels == 0 || coeffs.len() < channels {
+        return None;
+    }
+
+    let mut streams = vec![Vec::with_capacity(data.len() / channels); channels];
+    for (i, block) in data.chunks(INTERLEAVE).enumerate() {
+        streams[i % channels].extend_from_slice(block);
+    }
+    let decoded: Vec<Vec<i16>> = streams
+        .iter()
+        .zip(coeffs)
+        .map(|(stream, coeffs)| decode_channel(stream, coeffs, samples as usize))
+        .collect();
+
+    let frames = decoded.iter().map(Vec::len).min().unwrap_or(0);
+    let mut pcm = Vec::with_capacity(frames * channels * 2);
+    for i in 0..frames {
+        for channel in &decoded {
+            pcm.extend_from_slice(&channel[i].to_le_bytes());
+        }
+    }
+    Some(pcm)
+}
@@ -0,0 +1,82 @@
+// Xbox-style IMA ADPCM as FMOD stores it: 36-byte blocks per channel, each a
+// 4-byte header (predictor i16, step index, reserved) and 64 4-bit samples.
+// Multichannel blocks put every channel's header first, then interleave the
+// channels' nibbles 4 bytes at a time.
+const BLOCK_SIZE: usize = 36;
+const HEADER_SIZE: usize = 4;
+const INTERLEAVE: usize = 4;
+
+const STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+const INDEX_TABLE: [i32; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+struct Channel {
+    predictor: i32,
+    index: i32,
+}
+
+impl Channel {
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = STEP_TABLE[self.index as usize];
+        let mut diff = step >> 3;
+        if nibble & 1 != 0 {
+            diff += step >> 2;
+        }
+        if nibble & 2 != 0 {
+            diff += step >> 1;
+        }
+        if nibble & 4 != 0 {
+            diff += step;
+        }
+        if nibble & 8 != 0 {
+            diff = -diff;
+        }
+        self.predictor = (self.predictor + diff).clamp(i16::MIN as i32, i16::MAX as i32);
+        self.index = (self.index + INDEX_TABLE[nibble as usize]).clamp(0, 88);
+        self.predictor as i16
+    }
+}
+
+// Decodes to interleaved PCM16. A trailing partial block is decoded as far
+// as it goes.
+pub fn decode_ima_adpcm(data: &[u8], channels: u32) -> Vec<i16> {
+    let channels = channels.max(1) as usize;
+    let mut out = Vec::with_capacity(data.len() * 2);
+    for block in data.chunks(BLOCK_SIZE * channels) {
+        let Some(headers) = block.get(..HEADER_SIZE * channels) else {
+            break;
+        };
+        let mut state: Vec<Channel> = headers
+            .chunks_exact(HEADER_SIZE)
+            .map(|h| Channel {
+                predictor: i16::from_le_bytes([h[0], h[1]]) as i32,
+                index: (h[2] as i32).min(88),
+            })
+            .collect();
+
+        // Each group of 4 bytes per channel gives 8 samples per channel
+        let body = &block[HEADER_SIZE * channels..];
+        let mut decoded = vec![[0i16; INTERLEAVE * 2]; channels];
+        for group in body.chunks_exact(INTERLEAVE * channels) {
+            for ((bytes, channel), samples) in group
+                .chunks_exact(INTERLEAVE)
+                .zip(&mut state)
+                .zip(&mut decoded)
+            {
+                for (i, byte) in bytes.iter().enumerate() {
+                    samples[i * 2] = channel.decode(byte & 0xF);
+                    samples[i * 2 + 1] = channel.decode(byte >> 4);
+                }
+            }
+            for i in 0..INTERLEAVE * 2 {
+                out.extend(decoded.iter().map(|samples| samples[i]));
+            }
+        }
+    }
+    out
+}
@@ -1,5 +1,13 @@
+pub mod at9;
+pub mod gcadpcm;
+pub mod ima_adpcm;
 pub mod mp3;
 pub mod vorbis;
+pub mod wav;
 
+pub use at9::*;
+pub use gcadpcm::*;
+pub use ima_adpcm::*;
 pub use mp3::*;
 pub use vorbis::*;
+pub use wav::*;
@@ -7,6 +7,8 @@ const BITRATES_V1_L3: [u32; 16] = [
 const BITRATES_V2_L3: [u32; 16] = [
     0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160, 0,
 ];
+// The header's layer bits count down: 1 is Layer III, 3 is Layer I
+const LAYER_III: u8 = 1;
 const SAMPLE_RATES: [[u32; 4]; 4] = [
     [11025, 12000, 8000, 0],
     [0, 0, 0, 0],
@@ -53,12 +55,12 @@ impl Mp3FrameHeader {
 
         let bitrate = if version == 3 {
             match layer {
-                3 => BITRATES_V1_L3[bitrate_index as usize],
+                LAYER_III => BITRATES_V1_L3[bitrate_index as usize],
                 _ => return None,
             }
         } else {
             match layer {
-                3 => BITRATES_V2_L3[bitrate_index as usize],
+                LAYER_III => BITRATES_V2_L3[bitrate_index as usize],
                 _ => return None,
             }
         };
@@ -68,7 +70,7 @@ impl Mp3FrameHeader {
             return None;
         }
 
-        let frame_size = if layer == 3 {
+        let frame_size = if layer == LAYER_III {
             let coefficient = if version == 3 { 144 } else { 72 };
             (coefficient * bitrate * 1000 / sample_rate + if padding { 1 } else { 0 }) as usize
         } else {
@@ -89,6 +91,14 @@ impl Mp3FrameHeader {
         })
     }
 
+    pub fn samples_per_frame(&self) -> u64 {
+        if self.version == 3 {
+            1152
+        } else {
+            576
+        }
+    }
+
     pub fn encode(&self) -> u32 {
         let mut header: u32 = 0x7FF << 21;
         header |= (self.version as u32) << 19;
@@ -174,6 +184,39 @@ pub fn has_valid_mp3_frames(data: &[u8]) -> bool {
     Mp3FrameHeader::parse(header).is_some()
 }
 
+// Total samples per channel and the sample rate of the first frame, walking
+// every frame. Layer III frames hold 1152 samples, or 576 for MPEG-2/2.5.
+pub fn count_mp3_samples(data: &[u8], channels: u32) -> (u64, u32) {
+    let mut total = 0u64;
+    let mut mono_frames = 0u64;
+    let mut sample_rate = 0;
+    let mut pos = 0;
+
+    while pos + 4 <= data.len() {
+        let header = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]);
+        match Mp3FrameHeader::parse(header) {
+            Some(frame) if frame.frame_size > 4 && pos + frame.frame_size <= data.len() => {
+                total += frame.samples_per_frame();
+                if sample_rate == 0 {
+                    sample_rate = frame.sample_rate;
+                }
+                if frame.channel_mode == 3 {
+                    mono_frames += 1;
+                }
+                pos += frame.frame_size;
+            }
+            Some(_) => break,
+            None => pos += 1,
+        }
+    }
+
+    // Multichannel FSB4 streams interleave one mono frame per channel
+    if channels > 1 && mono_frames > 0 {
+        total /= channels as u64;
+    }
+    (total, sample_rate)
+}
+
 pub fn get_mp3_info(data: &[u8]) -> Option<(u32, u32, u32)> {
     if data.len() < 4 {
         return None;
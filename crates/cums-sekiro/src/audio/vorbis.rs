@@ -1,3 +1,7 @@
+use lewton::inside_ogg::OggStreamReader;
+use lewton::samples::InterleavedSamples;
+use std::io::{self, Cursor};
+
 #[derive(Debug, Clone)]
 pub struct VorbisHeaders {
     pub id_header: Vec<u8>,
@@ -21,15 +25,12 @@ pub fn generate_id_header(sample_rate: u32, channels: u8) -> Vec<u8> {
 }
 
 pub fn generate_comment_header() -> Vec<u8> {
-    let mut header = Vec::new();
-    header.push(0x03);
-    header.extend_from_slice(b"vorbis");
-    let vendor = b"CUMS";
-    header.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
-    header.extend_from_slice(vendor);
-    header.extend_from_slice(&0u32.to_le_bytes());
-    header.push(0x01);
-    header
+    generate_comment_header_with_options(&crate::OggRebuildOptions::default())
+}
+
+// Comment header carrying the vendor string from `options`
+pub fn generate_comment_header_with_options(options: &crate::OggRebuildOptions) -> Vec<u8> {
+    crate::generate_vorbis_comment_header(&options.vendor, &[])
 }
 
 pub struct VorbisPacketIterator<'a> {
@@ -66,71 +67,69 @@ impl<'a> Iterator for VorbisPacketIterator<'a> {
     }
 }
 
+// Muxes through OggStream with the default options, so pages end where
+// rebuild_ogg would end them. A non-zero `sample_count` caps the final
+// granule so decoders trim the last packet.
 pub fn build_ogg_file(
     headers: &VorbisHeaders,
     raw_data: &[u8],
-    _sample_count: u64,
+    sample_count: u64,
+) -> Result<Vec<u8>, String> {
+    build_ogg_file_with_options(
+        headers,
+        raw_data,
+        sample_count,
+        crate::OggRebuildOptions::default(),
+    )
+}
+
+// Like build_ogg_file, with the page flushing and serial from `options`. The
+// vendor is whatever `headers.comment_header` already holds.
+pub fn build_ogg_file_with_options(
+    headers: &VorbisHeaders,
+    raw_data: &[u8],
+    sample_count: u64,
+    options: crate::OggRebuildOptions,
 ) -> Result<Vec<u8>, String> {
-    use ogg::writing::PacketWriter;
+    use std::io::Read;
 
     let mut output = Vec::new();
-    let serial = 0x12345678u32;
+    crate::OggStream::new(
+        headers.id_header.clone(),
+        headers.comment_header.clone(),
+        headers.setup_header.clone(),
+        raw_data,
+        sample_count,
+        options,
+    )
+    .and_then(|mut stream| stream.read_to_end(&mut output))
+    .map_err(|e| format!("Failed to write OGG: {}", e))?;
+    Ok(output)
+}
+
+// FMOD keys setup headers by the plain CRC32 (zlib polynomial) of the whole
+// setup packet, "\x05vorbis" prefix included
+pub fn fmod_setup_crc(setup_header: &[u8]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    crc.update(setup_header);
+    crc.sum()
+}
 
+// Decodes a complete OGG (as built by rebuild_ogg) to interleaved samples in
+// -1.0..1.0, for drawing waveforms
+pub fn decode_to_pcm(ogg: &[u8]) -> io::Result<(Vec<f32>, u32, u16)> {
+    let invalid = |e: lewton::VorbisError| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut reader = OggStreamReader::new(Cursor::new(ogg)).map_err(invalid)?;
+    let rate = reader.ident_hdr.audio_sample_rate;
+    let channels = reader.ident_hdr.audio_channels as u16;
+    let mut pcm = Vec::new();
+    while let Some(packet) = reader
+        .read_dec_packet_generic::<InterleavedSamples<f32>>()
+        .map_err(invalid)?
     {
-        let mut writer = PacketWriter::new(&mut output);
-
-        writer
-            .write_packet(
-                headers.id_header.clone(),
-                serial,
-                ogg::writing::PacketWriteEndInfo::EndPage,
-                0,
-            )
-            .map_err(|e| format!("Failed to write id header: {}", e))?;
-
-        writer
-            .write_packet(
-                headers.comment_header.clone(),
-                serial,
-                ogg::writing::PacketWriteEndInfo::NormalPacket,
-                0,
-            )
-            .map_err(|e| format!("Failed to write comment header: {}", e))?;
-
-        writer
-            .write_packet(
-                headers.setup_header.clone(),
-                serial,
-                ogg::writing::PacketWriteEndInfo::EndPage,
-                0,
-            )
-            .map_err(|e| format!("Failed to write setup header: {}", e))?;
-
-        let mut granule_pos = 0u64;
-        let mut packet_count = 0u32;
-        let packets: Vec<_> = VorbisPacketIterator::new(raw_data).collect();
-        let total_packets = packets.len();
-
-        for (i, packet) in packets.into_iter().enumerate() {
-            granule_pos += 1024;
-            packet_count += 1;
-
-            let is_last = i == total_packets - 1;
-            let end_info = if is_last {
-                ogg::writing::PacketWriteEndInfo::EndStream
-            } else if packet_count.is_multiple_of(10) {
-                ogg::writing::PacketWriteEndInfo::EndPage
-            } else {
-                ogg::writing::PacketWriteEndInfo::NormalPacket
-            };
-
-            writer
-                .write_packet(packet.to_vec(), serial, end_info, granule_pos)
-                .map_err(|e| format!("Failed to write audio packet: {}", e))?;
-        }
+        pcm.extend_from_slice(&packet.samples);
     }
-
-    Ok(output)
+    Ok((pcm, rate, channels))
 }
 
 pub fn get_vorbis_info(_data: &[u8]) -> Option<(u32, u32)> {
@@ -144,3 +143,230 @@ pub fn has_valid_vorbis_packets(data: &[u8]) -> bool {
     let size = u16::from_le_bytes([data[0], data[1]]) as usize;
     size > 0 && size + 2 <= data.len()
 }
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, bits: u32) -> Option<u32> {
+        let mut value = 0u32;
+        for i in 0..bits {
+            let byte = *self.data.get(self.bit / 8)?;
+            value |= (((byte >> (self.bit % 8)) & 1) as u32) << i;
+            self.bit += 1;
+        }
+        Some(value)
+    }
+
+    fn skip(&mut self, bits: u64) -> Option<()> {
+        self.bit = self.bit.checked_add(bits as usize)?;
+        (self.bit <= self.data.len() * 8).then_some(())
+    }
+}
+
+fn ilog(x: u32) -> u32 {
+    32 - x.leading_zeros()
+}
+
+fn lookup1_values(entries: u32, dimensions: u32) -> u64 {
+    let mut r = (entries as f64).powf(1.0 / dimensions as f64).floor() as u64;
+    while (r + 1)
+        .checked_pow(dimensions)
+        .is_some_and(|v| v <= entries as u64)
+    {
+        r += 1;
+    }
+    while r > 0 && r.checked_pow(dimensions).is_none_or(|v| v > entries as u64) {
+        r -= 1;
+    }
+    r
+}
+
+// Walks a setup header as a decoder configured for `channels` would and
+// returns the block flag of each mode. Coupling steps are encoded with a
+// channel-dependent width, so a header built for a different channel count
+// fails to parse.
+pub fn parse_setup_modes(setup: &[u8], channels: u32) -> Option<Vec<bool>> {
+    let body = setup.strip_prefix(b"\x05vorbis").unwrap_or(setup);
+    let mut r = BitReader { data: body, bit: 0 };
+    if channels == 0 {
+        return None;
+    }
+
+    let codebooks = r.read(8)? + 1;
+    for _ in 0..codebooks {
+        if r.read(24)? != 0x564342 {
+            return None;
+        }
+        let dimensions = r.read(16)?;
+        let entries = r.read(24)?;
+        if r.read(1)? == 1 {
+            let mut current = 0;
+            r.read(5)?;
+            while current < entries {
+                current += r.read(ilog(entries - current))?;
+            }
+            if current > entries {
+                return None;
+            }
+        } else {
+            let sparse = r.read(1)? == 1;
+            for _ in 0..entries {
+                if !sparse || r.read(1)? == 1 {
+                    r.read(5)?;
+                }
+            }
+        }
+        match r.read(4)? {
+            0 => {}
+            lookup @ (1 | 2) => {
+                r.skip(64)?;
+                let value_bits = r.read(4)? + 1;
+                r.read(1)?;
+                let values = if lookup == 1 {
+                    lookup1_values(entries, dimensions)
+                } else {
+                    entries as u64 * dimensions as u64
+                };
+                r.skip(values * value_bits as u64)?;
+            }
+            _ => return None,
+        }
+    }
+
+    let times = r.read(6)? + 1;
+    for _ in 0..times {
+        if r.read(16)? != 0 {
+            return None;
+        }
+    }
+
+    let floors = r.read(6)? + 1;
+    for _ in 0..floors {
+        match r.read(16)? {
+            0 => {
+                r.skip(8 + 16 + 16 + 6 + 8)?;
+                let books = r.read(4)? + 1;
+                r.skip(books as u64 * 8)?;
+            }
+            1 => {
+                let partitions = r.read(5)?;
+                let mut classes = Vec::with_capacity(partitions as usize);
+                for _ in 0..partitions {
+                    classes.push(r.read(4)?);
+                }
+                let max_class = classes.iter().copied().max().map_or(0, |m| m + 1);
+                let mut dimensions = Vec::with_capacity(max_class as usize);
+                for _ in 0..max_class {
+                    dimensions.push(r.read(3)? + 1);
+                    let subclasses = r.read(2)?;
+                    if subclasses > 0 {
+                        r.read(8)?;
+                    }
+                    r.skip((1u64 << subclasses) * 8)?;
+                }
+                r.read(2)?;
+                let range_bits = r.read(4)?;
+                for class in classes {
+                    r.skip(dimensions[class as usize] as u64 * range_bits as u64)?;
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    let residues = r.read(6)? + 1;
+    for _ in 0..residues {
+        if r.read(16)? > 2 {
+            return None;
+        }
+        r.skip(24 + 24 + 24)?;
+        let classifications = r.read(6)? + 1;
+        r.read(8)?;
+        let mut cascades = Vec::with_capacity(classifications as usize);
+        for _ in 0..classifications {
+            let low = r.read(3)?;
+            let high = if r.read(1)? == 1 { r.read(5)? } else { 0 };
+            cascades.push(high * 8 + low);
+        }
+        for cascade in cascades {
+            r.skip(cascade.count_ones() as u64 * 8)?;
+        }
+    }
+
+    let mappings = r.read(6)? + 1;
+    for _ in 0..mappings {
+        if r.read(16)? != 0 {
+            return None;
+        }
+        let submaps = if r.read(1)? == 1 { r.read(4)? + 1 } else { 1 };
+        if r.read(1)? == 1 {
+            let steps = r.read(8)? + 1;
+            let width = ilog(channels - 1);
+            for _ in 0..steps {
+                let magnitude = r.read(width)?;
+                let angle = r.read(width)?;
+                if magnitude == angle || magnitude >= channels || angle >= channels {
+                    return None;
+                }
+            }
+        }
+        if r.read(2)? != 0 {
+            return None;
+        }
+        if submaps > 1 {
+            for _ in 0..channels {
+                if r.read(4)? >= submaps {
+                    return None;
+                }
+            }
+        }
+        for _ in 0..submaps {
+            r.read(8)?;
+            if r.read(8)? >= floors || r.read(8)? >= residues {
+                return None;
+            }
+        }
+    }
+
+    let mode_count = r.read(6)? + 1;
+    let mut modes = Vec::with_capacity(mode_count as usize);
+    for _ in 0..mode_count {
+        let long_block = r.read(1)? == 1;
+        if r.read(16)? != 0 || r.read(16)? != 0 || r.read(8)? >= mappings {
+            return None;
+        }
+        modes.push(long_block);
+    }
+
+    (r.read(1)? == 1).then_some(modes)
+}
+
+// Number of PCM frames the packets decode to, using the 256/2048 block sizes
+// of the id header we generate. None if a packet isn't a valid audio packet.
+pub fn framed_sample_count(data: &[u8], modes: &[bool]) -> Option<u64> {
+    let mode_bits = ilog(modes.len().saturating_sub(1) as u32);
+    let mut total = 0u64;
+    let mut previous: Option<u64> = None;
+    for packet in VorbisPacketIterator::new(data) {
+        let mut r = BitReader {
+            data: packet,
+            bit: 0,
+        };
+        if r.read(1)? != 0 {
+            return None;
+        }
+        let block = if *modes.get(r.read(mode_bits)? as usize)? {
+            2048
+        } else {
+            256
+        };
+        if let Some(prev) = previous {
+            total += prev / 4 + block / 4;
+        }
+        previous = Some(block);
+    }
+    Some(total)
+}
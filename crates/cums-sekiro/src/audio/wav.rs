@@ -0,0 +1,86 @@
+use std::io;
+
+pub const WAVE_FORMAT_PCM: u16 = 1;
+pub const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+pub fn create_wav_header(
+    pcm_data: &[u8],
+    sample_rate: u32,
+    channels: u16,
+    bits_per_sample: u16,
+    format_tag: u16,
+) -> Vec<u8> {
+    let byte_rate = sample_rate * channels as u32 * (bits_per_sample as u32 / 8);
+    let block_align = channels * (bits_per_sample / 8);
+    let data_size = pcm_data.len() as u32;
+    let file_size = 36 + data_size;
+
+    let mut wav = Vec::with_capacity(44 + pcm_data.len());
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&file_size.to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&format_tag.to_le_bytes());
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&byte_rate.to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&bits_per_sample.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    wav.extend_from_slice(pcm_data);
+    wav
+}
+
+// Integer PCM as stored by the Pcm8..Pcm32 codecs, e.g. from sample_data
+pub fn write_wav(pcm: &[u8], sample_rate: u32, channels: u16, bits: u16) -> Vec<u8> {
+    create_wav_header(pcm, sample_rate, channels, bits, WAVE_FORMAT_PCM)
+}
+
+// 32-bit little-endian float PCM, as stored by the PcmFloat codec
+pub fn write_float_wav(pcm: &[u8], sample_rate: u32, channels: u16) -> Vec<u8> {
+    create_wav_header(pcm, sample_rate, channels, 32, WAVE_FORMAT_IEEE_FLOAT)
+}
+
+// Returns (sample rate, channels, bits per sample, PCM bytes) of an
+// uncompressed WAV. Chunks other than fmt and data are skipped.
+pub fn parse_wav(wav: &[u8]) -> io::Result<(u32, u16, u16, Vec<u8>)> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    if wav.len() < 12 || &wav[0..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        return Err(invalid("Not a WAV file"));
+    }
+    let mut format = None;
+    let mut pos = 12;
+    while pos + 8 <= wav.len() {
+        let id = &wav[pos..pos + 4];
+        let size = u32::from_le_bytes(wav[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let body = &wav[pos + 8..(pos + 8 + size).min(wav.len())];
+        match id {
+            b"fmt " => {
+                if body.len() < 16 {
+                    return Err(invalid("WAV fmt chunk is too short"));
+                }
+                let u16_at = |i: usize| u16::from_le_bytes([body[i], body[i + 1]]);
+                // 1 is plain PCM; 0xFFFE is WAVE_FORMAT_EXTENSIBLE, which
+                // FFmpeg writes for anything above stereo
+                if !matches!(u16_at(0), 1 | 0xFFFE) {
+                    return Err(invalid("WAV is not uncompressed PCM"));
+                }
+                let rate = u32::from_le_bytes(body[4..8].try_into().unwrap());
+                format = Some((rate, u16_at(2), u16_at(14)));
+            }
+            b"data" => {
+                let (rate, channels, bits) =
+                    format.ok_or_else(|| invalid("WAV data chunk comes before fmt"))?;
+                if channels == 0 || rate == 0 {
+                    return Err(invalid("WAV has no channels or sample rate"));
+                }
+                return Ok((rate, channels, bits, body.to_vec()));
+            }
+            _ => {}
+        }
+        pos += 8 + size + size % 2;
+    }
+    Err(invalid("WAV file has no data chunk"))
+}
@@ -0,0 +1,43 @@
+use crate::fsb::{Codec, FsbBank};
+use once_cell::sync::Lazy;
+use std::io;
+use std::sync::{Arc, RwLock};
+
+pub trait CodecHandler: Send + Sync {
+    fn can_handle(&self, codec: Codec) -> bool;
+    fn extract(&self, bank: &FsbBank, index: usize) -> io::Result<(Vec<u8>, &'static str)>;
+}
+
+static HANDLERS: Lazy<RwLock<Vec<Arc<dyn CodecHandler>>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+// Later registrations win, so a handler can also override a built-in codec.
+pub fn register_codec_handler(handler: Box<dyn CodecHandler>) {
+    HANDLERS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Arc::from(handler));
+}
+
+pub fn has_codec_handler(codec: Codec) -> bool {
+    HANDLERS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .any(|h| h.can_handle(codec))
+}
+
+pub(crate) fn extract_registered(
+    bank: &FsbBank,
+    index: usize,
+) -> Option<io::Result<(Vec<u8>, &'static str)>> {
+    // Clone the handler out so the lock is released before it runs; a
+    // handler that registers another one would otherwise deadlock
+    let handler = HANDLERS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .rev()
+        .find(|h| h.can_handle(bank.codec))
+        .cloned()?;
+    Some(handler.extract(bank, index))
+}
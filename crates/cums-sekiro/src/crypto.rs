@@ -19,26 +19,16 @@ pub fn encrypt_aes_block(data: &mut [u8], key: &[u8; 32]) {
     }
 }
 
+// The data section is encrypted in whole blocks only and a trailing partial
+// block (data_size not a multiple of 16) is left untouched in both
+// directions. That mirrors what the block helpers always did; it's assumed,
+// not checked against a retail bank, that FMOD leaves the tail in the clear.
 pub fn decrypt_aes_data(data: &mut [u8], key: &[u8; 32]) {
-    let cipher = Aes256::new(GenericArray::from_slice(key));
-    let block_count = data.len() / 16;
-    for i in 0..block_count {
-        let start = i * 16;
-        let chunk = &mut data[start..start + 16];
-        let block = GenericArray::from_mut_slice(chunk);
-        cipher.decrypt_block(block);
-    }
+    decrypt_aes_block(data, key);
 }
 
 pub fn encrypt_aes_data(data: &mut [u8], key: &[u8; 32]) {
-    let cipher = Aes256::new(GenericArray::from_slice(key));
-    let block_count = data.len() / 16;
-    for i in 0..block_count {
-        let start = i * 16;
-        let chunk = &mut data[start..start + 16];
-        let block = GenericArray::from_mut_slice(chunk);
-        cipher.encrypt_block(block);
-    }
+    encrypt_aes_block(data, key);
 }
 
 fn fsbdec_byte(t: u8) -> u8 {
@@ -0,0 +1,95 @@
+use crate::fsb::Sample;
+use std::io::{self, Cursor};
+use std::num::{NonZeroU32, NonZeroU8};
+use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoderBuilder};
+
+const BLOCK_FRAMES: usize = 4096;
+
+// Encodes interleaved PCM into FMOD's layout: the three Vorbis headers are
+// dropped and each audio packet is prefixed with its u16 length. The setup
+// header is registered under its CRC so the sample can be rebuilt afterwards.
+pub fn encode_vorbis(
+    pcm: &[f32],
+    channels: u32,
+    rate: u32,
+    quality: f32,
+) -> io::Result<(Vec<u8>, Sample)> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+    if !(1..=2).contains(&channels) {
+        return Err(invalid("Native encoding supports mono and stereo only"));
+    }
+    let rate_nz = NonZeroU32::new(rate).ok_or_else(|| invalid("Invalid sample rate"))?;
+    let channels_nz = NonZeroU8::new(channels as u8).ok_or_else(|| invalid("Invalid channels"))?;
+
+    let ch = channels as usize;
+    let frames = pcm.len() / ch;
+    let planar: Vec<Vec<f32>> = (0..ch)
+        .map(|c| {
+            pcm.iter()
+                .skip(c)
+                .step_by(ch)
+                .take(frames)
+                .copied()
+                .collect()
+        })
+        .collect();
+
+    let mut ogg = Vec::new();
+    {
+        let mut encoder = VorbisEncoderBuilder::new_with_serial(rate_nz, channels_nz, &mut ogg, 0)
+            .bitrate_management_strategy(VorbisBitrateManagementStrategy::QualityVbr {
+                target_quality: quality,
+            })
+            .build()
+            .map_err(io::Error::other)?;
+        for start in (0..frames).step_by(BLOCK_FRAMES) {
+            let end = (start + BLOCK_FRAMES).min(frames);
+            let block: Vec<&[f32]> = planar.iter().map(|c| &c[start..end]).collect();
+            encoder
+                .encode_audio_block(&block)
+                .map_err(io::Error::other)?;
+        }
+        encoder.finish().map_err(io::Error::other)?;
+    }
+
+    let mut reader = ogg::PacketReader::new(Cursor::new(ogg));
+    let mut packets = Vec::new();
+    while let Some(packet) = reader.read_packet().map_err(io::Error::other)? {
+        packets.push(packet.data);
+    }
+    if packets.len() < 3 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Encoder produced no Vorbis headers",
+        ));
+    }
+
+    let setup = &packets[2];
+    let crc = crate::audio::fmod_setup_crc(setup);
+    crate::register_vorbis_setup_header(crc, setup.clone());
+
+    let mut data = Vec::new();
+    for packet in &packets[3..] {
+        data.extend_from_slice(&(packet.len() as u16).to_le_bytes());
+        data.extend_from_slice(packet);
+    }
+
+    let sample = Sample {
+        index: 0,
+        name: None,
+        frequency: rate,
+        channels,
+        samples: frames as u64,
+        data_offset: 0,
+        data_size: data.len() as u64,
+        loop_start: None,
+        loop_end: None,
+        vorbis_crc: Some(crc),
+        vorbis_seek_table: None,
+        dsp_coeffs: None,
+        at9_config: None,
+        chunks: Vec::new(),
+        mode: None,
+    };
+    Ok((data, sample))
+}
@@ -0,0 +1,77 @@
+use crate::fsb::{Codec, Version};
+use std::io;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FsbError {
+    #[error("File too small")]
+    TooSmall,
+    #[error("Unknown format")]
+    UnknownFormat,
+    #[error("Unknown codec {0}")]
+    UnknownCodec(u32),
+    #[error("File appears incomplete (expected {expected} bytes, got {actual})")]
+    Truncated { expected: u64, actual: u64 },
+    #[error("Sample {index} starts before the previous sample (offset {offset}); the bank exceeds the 4 GB FSB5 offset range")]
+    OffsetOverflow { index: usize, offset: u64 },
+    #[error("Sample {index}'s chunk list runs past the end of the sample headers")]
+    ChunkOverrun { index: usize },
+    #[error("Sample {index} has more than {limit} chunks; its more-chunks flag is likely corrupt")]
+    TooManyChunks { index: usize, limit: usize },
+    #[error("Sample {index} not found")]
+    SampleNotFound { index: usize },
+    #[error("Sample {index}'s data is out of bounds")]
+    SampleOutOfBounds { index: usize },
+    #[error("Sample {index} has no audio data")]
+    EmptySample { index: usize },
+    #[error("{0:?} banks aren't supported here; use FsbBank::replace_sample for FSB4")]
+    UnsupportedVersion(Version),
+    #[error("This bank is {bank:?}; only single-sample banks can be converted to {target:?}")]
+    CodecMismatch { bank: Codec, target: Codec },
+    #[error("fsbankcl produced {actual} samples for {expected} inputs")]
+    EncodedCountMismatch { expected: usize, actual: usize },
+    #[error("Not a Vorbis bank")]
+    NotVorbis,
+    #[error("Sample {index} has no Vorbis setup CRC")]
+    MissingVorbisCrc { index: usize },
+    #[error("Unknown CRC 0x{0:08X}")]
+    UnknownVorbisCrc(u32),
+    #[error("FFmpeg not found; install it to convert this audio")]
+    FfmpegMissing,
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+// An io::Error that already carries an FsbError gives it back, so errors
+// survive being passed through io::Result helpers
+impl From<io::Error> for FsbError {
+    fn from(err: io::Error) -> Self {
+        err.downcast::<FsbError>().unwrap_or_else(FsbError::Io)
+    }
+}
+
+impl From<FsbError> for io::Error {
+    fn from(err: FsbError) -> Self {
+        let kind = match err {
+            FsbError::Io(err) => return err,
+            FsbError::Truncated { .. } | FsbError::SampleOutOfBounds { .. } => {
+                io::ErrorKind::UnexpectedEof
+            }
+            FsbError::SampleNotFound { .. } | FsbError::FfmpegMissing => io::ErrorKind::NotFound,
+            FsbError::CodecMismatch { .. } => io::ErrorKind::InvalidInput,
+            FsbError::TooSmall
+            | FsbError::UnknownFormat
+            | FsbError::UnknownCodec(_)
+            | FsbError::OffsetOverflow { .. }
+            | FsbError::ChunkOverrun { .. }
+            | FsbError::TooManyChunks { .. }
+            | FsbError::EmptySample { .. }
+            | FsbError::UnsupportedVersion(_)
+            | FsbError::EncodedCountMismatch { .. }
+            | FsbError::NotVorbis
+            | FsbError::MissingVorbisCrc { .. }
+            | FsbError::UnknownVorbisCrc(_) => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
@@ -1,9 +1,14 @@
 use aes::cipher::{generic_array::GenericArray, BlockDecrypt, KeyInit};
 use aes::Aes128;
-use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
 use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
 
 const BHD5_MAGIC: &[u8; 4] = b"BHD5";
+const HEADER_SIZE: usize = 28;
+// BDT payloads start on AES block boundaries so ranges can be re-encrypted
+const BDT_ALIGNMENT: usize = 16;
 
 pub mod keys {
     pub const DS3_KEY: &[u8] = include_bytes!("../keys/ds3.pem");
@@ -171,6 +176,110 @@ impl Bhd5 {
         })
     }
 
+    pub fn write(&self) -> io::Result<Vec<u8>> {
+        let big_endian = self.big_endian;
+        macro_rules! write_u32 {
+            ($out:expr, $value:expr) => {
+                if big_endian {
+                    $out.write_u32::<BigEndian>($value)?
+                } else {
+                    $out.write_u32::<LittleEndian>($value)?
+                }
+            };
+        }
+        macro_rules! write_i32 {
+            ($out:expr, $value:expr) => {
+                if big_endian {
+                    $out.write_i32::<BigEndian>($value)?
+                } else {
+                    $out.write_i32::<LittleEndian>($value)?
+                }
+            };
+        }
+        macro_rules! write_u64 {
+            ($out:expr, $value:expr) => {
+                if big_endian {
+                    $out.write_u64::<BigEndian>($value)?
+                } else {
+                    $out.write_u64::<LittleEndian>($value)?
+                }
+            };
+        }
+        macro_rules! write_i64 {
+            ($out:expr, $value:expr) => {
+                if big_endian {
+                    $out.write_i64::<BigEndian>($value)?
+                } else {
+                    $out.write_i64::<LittleEndian>($value)?
+                }
+            };
+        }
+
+        let entry_size = if self.version >= 0x100 { 28 } else { 16 };
+        let buckets_offset = HEADER_SIZE + self.salt.len();
+        let entries_offset = buckets_offset + self.buckets.len() * 8;
+        let entry_count: usize = self.buckets.iter().map(|b| b.entries.len()).sum();
+        let keys_offset = entries_offset + entry_count * entry_size;
+
+        // Key blocks go after every entry table, in entry order
+        let mut keys = Vec::new();
+        let mut key_offsets = Vec::with_capacity(entry_count);
+        for entry in self.buckets.iter().flat_map(|b| b.entries.iter()) {
+            match &entry.aes_key {
+                Some(key) if self.version >= 0x100 => {
+                    if key.len() != 16 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "Invalid AES key length",
+                        ));
+                    }
+                    key_offsets.push((keys_offset + keys.len()) as u64);
+                    keys.extend_from_slice(key);
+                    write_i32!(keys, entry.aes_ranges.len() as i32);
+                    for &(start, end) in &entry.aes_ranges {
+                        write_i64!(keys, start);
+                        write_i64!(keys, end);
+                    }
+                }
+                _ => key_offsets.push(0),
+            }
+        }
+
+        let mut out = Vec::with_capacity(keys_offset + keys.len());
+        out.extend_from_slice(BHD5_MAGIC);
+        out.extend_from_slice(if big_endian {
+            &[0, 0, 0, 1]
+        } else {
+            &[0xFF, 0, 0, 1]
+        });
+        write_u32!(out, self.version);
+        write_u32!(out, (keys_offset + keys.len()) as u32);
+        write_u32!(out, self.buckets.len() as u32);
+        write_u32!(out, buckets_offset as u32);
+        write_u32!(out, self.salt.len() as u32);
+        out.extend_from_slice(&self.salt);
+
+        let mut offset = entries_offset;
+        for bucket in &self.buckets {
+            write_u32!(out, bucket.entries.len() as u32);
+            write_u32!(out, offset as u32);
+            offset += bucket.entries.len() * entry_size;
+        }
+
+        let entries = self.buckets.iter().flat_map(|b| b.entries.iter());
+        for (entry, key_offset) in entries.zip(key_offsets) {
+            write_u32!(out, entry.hash);
+            write_u32!(out, entry.size);
+            write_u64!(out, entry.offset);
+            if self.version >= 0x100 {
+                write_u32!(out, entry.padded_size);
+                write_u64!(out, key_offset);
+            }
+        }
+        out.extend_from_slice(&keys);
+        Ok(out)
+    }
+
     pub fn get_entry(&self, hash: u32) -> Option<&Bhd5Entry> {
         let bucket_index = (hash % self.buckets.len() as u32) as usize;
         self.buckets
@@ -230,6 +339,64 @@ impl<'a> Bdt<'a> {
 
         Ok(data)
     }
+
+    // Entries are only stored by hash, so names come from a list of known
+    // paths. Anything not in the list is written as `<hash>.bin`.
+    pub fn extract_to<P: AsRef<Path>>(
+        &self,
+        bhd: &Bhd5,
+        names: &[String],
+        dir: P,
+        preserve_paths: bool,
+    ) -> io::Result<usize> {
+        let dir = dir.as_ref();
+        let known: HashMap<u32, &str> = names
+            .iter()
+            .map(|n| (Bhd5::hash_path(n, &bhd.salt), n.as_str()))
+            .collect();
+
+        let mut written = 0;
+        for entry in bhd.all_entries() {
+            let name = match known.get(&entry.hash) {
+                Some(name) => name.to_string(),
+                None => format!("{:08X}.bin", entry.hash),
+            };
+            let Some(path) = super::entry_output_path(dir, &name, preserve_paths) else {
+                continue;
+            };
+            super::write_entry(&path, &self.read_entry(entry)?)?;
+            written += 1;
+        }
+        Ok(written)
+    }
+}
+
+// Builds a BDT by appending payloads, each padded to a 16-byte boundary.
+// Entries are written in plaintext, so their AES key and ranges are cleared.
+#[derive(Debug, Default)]
+pub struct BdtWriter {
+    data: Vec<u8>,
+}
+
+impl BdtWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, entry: &mut Bhd5Entry, payload: &[u8]) {
+        let padded_size = payload.len().next_multiple_of(BDT_ALIGNMENT);
+        entry.size = payload.len() as u32;
+        entry.offset = self.data.len() as u64;
+        entry.padded_size = padded_size as u32;
+        entry.aes_key = None;
+        entry.aes_ranges.clear();
+        self.data.extend_from_slice(payload);
+        self.data.resize(entry.offset as usize + padded_size, 0);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
 }
 
 fn decrypt_aes128_ecb(data: &mut [u8], key: &[u8], ranges: &[(i64, i64)]) -> io::Result<()> {
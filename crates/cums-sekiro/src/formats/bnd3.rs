@@ -0,0 +1,294 @@
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const BND3_MAGIC: &[u8; 4] = b"BND3";
+const BND3_HEADER_SIZE: u64 = 0x20;
+
+// Format bits, after undoing the bit order the header stores them in
+const FORMAT_IDS: u8 = 0x02;
+const FORMAT_NAMES: u8 = 0x04 | 0x08;
+const FORMAT_LONG_OFFSETS: u8 = 0x10;
+const FORMAT_COMPRESSION: u8 = 0x20;
+
+#[derive(Debug, Clone)]
+pub struct Bnd3Entry {
+    pub flags: u8,
+    pub id: i32,
+    pub name: String,
+    // Only stored when the format has the compression bit; `data` is always
+    // the bytes as stored
+    pub uncompressed_size: u64,
+    pub data: Vec<u8>,
+}
+
+// The older binder used by Dark Souls. Its format byte decides which entry
+// fields exist; 0x74, the common one, has ids, names and uncompressed sizes
+// with 32-bit data offsets.
+#[derive(Debug)]
+pub struct Bnd3 {
+    pub version: String,
+    pub format: u8,
+    pub big_endian: bool,
+    pub bit_big_endian: bool,
+    pub unk18: i32,
+    pub entries: Vec<Bnd3Entry>,
+}
+
+fn read_i32(cursor: &mut Cursor<&[u8]>, be: bool) -> io::Result<i32> {
+    if be {
+        cursor.read_i32::<BigEndian>()
+    } else {
+        cursor.read_i32::<LittleEndian>()
+    }
+}
+
+fn read_u32(cursor: &mut Cursor<&[u8]>, be: bool) -> io::Result<u32> {
+    if be {
+        cursor.read_u32::<BigEndian>()
+    } else {
+        cursor.read_u32::<LittleEndian>()
+    }
+}
+
+fn read_u64(cursor: &mut Cursor<&[u8]>, be: bool) -> io::Result<u64> {
+    if be {
+        cursor.read_u64::<BigEndian>()
+    } else {
+        cursor.read_u64::<LittleEndian>()
+    }
+}
+
+fn write_i32(out: &mut Vec<u8>, value: i32, be: bool) -> io::Result<()> {
+    if be {
+        out.write_i32::<BigEndian>(value)
+    } else {
+        out.write_i32::<LittleEndian>(value)
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32, be: bool) -> io::Result<()> {
+    if be {
+        out.write_u32::<BigEndian>(value)
+    } else {
+        out.write_u32::<LittleEndian>(value)
+    }
+}
+
+fn write_u64(out: &mut Vec<u8>, value: u64, be: bool) -> io::Result<()> {
+    if be {
+        out.write_u64::<BigEndian>(value)
+    } else {
+        out.write_u64::<LittleEndian>(value)
+    }
+}
+
+impl Bnd3 {
+    pub fn read(data: &[u8]) -> io::Result<Self> {
+        let mut cursor = Cursor::new(data);
+
+        let mut magic = [0u8; 4];
+        cursor.read_exact(&mut magic)?;
+        if &magic != BND3_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid BND3 magic",
+            ));
+        }
+
+        let mut version_bytes = [0u8; 8];
+        cursor.read_exact(&mut version_bytes)?;
+        let version = String::from_utf8_lossy(&version_bytes)
+            .trim_end_matches('\0')
+            .to_string();
+
+        let format = cursor.read_u8()?;
+        let big_endian = cursor.read_u8()? != 0;
+        let bit_big_endian = cursor.read_u8()? != 0;
+        let _unk0f = cursor.read_u8()?;
+        let be = big_endian;
+
+        let entry_count = read_i32(&mut cursor, be)?;
+        let _file_headers_end = read_i32(&mut cursor, be)?;
+        let unk18 = read_i32(&mut cursor, be)?;
+        let _unk1c = read_i32(&mut cursor, be)?;
+
+        let mut bnd = Bnd3 {
+            version,
+            format,
+            big_endian,
+            bit_big_endian,
+            unk18,
+            entries: Vec::with_capacity(entry_count.max(0) as usize),
+        };
+        let fields = bnd.format_bits();
+
+        for _ in 0..entry_count {
+            let flags = cursor.read_u8()?;
+            let mut pad = [0u8; 3];
+            cursor.read_exact(&mut pad)?;
+            let size = read_i32(&mut cursor, be)? as usize;
+            let data_offset = if fields & FORMAT_LONG_OFFSETS != 0 {
+                read_u64(&mut cursor, be)?
+            } else {
+                read_u32(&mut cursor, be)? as u64
+            };
+            let id = if fields & FORMAT_IDS != 0 {
+                read_i32(&mut cursor, be)?
+            } else {
+                -1
+            };
+            let name_offset = if fields & FORMAT_NAMES != 0 {
+                Some(read_u32(&mut cursor, be)?)
+            } else {
+                None
+            };
+            let uncompressed_size = if fields & FORMAT_COMPRESSION != 0 {
+                read_i32(&mut cursor, be)? as u64
+            } else {
+                size as u64
+            };
+
+            let pos = cursor.position();
+            let name = match name_offset {
+                Some(offset) => {
+                    cursor.seek(SeekFrom::Start(offset as u64))?;
+                    super::read_string(&mut cursor)?
+                }
+                None => String::new(),
+            };
+            cursor.seek(SeekFrom::Start(data_offset))?;
+            let mut file_data = vec![0u8; size];
+            cursor.read_exact(&mut file_data)?;
+            cursor.seek(SeekFrom::Start(pos))?;
+
+            bnd.entries.push(Bnd3Entry {
+                flags,
+                id,
+                name,
+                uncompressed_size,
+                data: file_data,
+            });
+        }
+
+        Ok(bnd)
+    }
+
+    pub fn write(&self) -> io::Result<Vec<u8>> {
+        let be = self.big_endian;
+        let fields = self.format_bits();
+        let mut out = Vec::new();
+
+        out.write_all(BND3_MAGIC)?;
+        let mut version_bytes = [0u8; 8];
+        let version_src = self.version.as_bytes();
+        version_bytes[..version_src.len().min(8)]
+            .copy_from_slice(&version_src[..version_src.len().min(8)]);
+        out.write_all(&version_bytes)?;
+        out.write_u8(self.format)?;
+        out.write_u8(self.big_endian as u8)?;
+        out.write_u8(self.bit_big_endian as u8)?;
+        out.write_u8(0)?;
+
+        // Flags and size, the data offset, then a u32 for each optional field
+        let offset_size = if fields & FORMAT_LONG_OFFSETS != 0 {
+            8
+        } else {
+            4
+        };
+        let optional = [FORMAT_IDS, FORMAT_NAMES, FORMAT_COMPRESSION]
+            .iter()
+            .filter(|&&bit| fields & bit != 0)
+            .count() as u64;
+        let entry_header_size = 8 + offset_size + optional * 4;
+        let names_offset = BND3_HEADER_SIZE + self.entries.len() as u64 * entry_header_size;
+        let mut name_offsets = Vec::with_capacity(self.entries.len());
+        let mut headers_end = names_offset;
+        if fields & FORMAT_NAMES != 0 {
+            for entry in &self.entries {
+                name_offsets.push(headers_end);
+                headers_end += entry.name.len() as u64 + 1;
+            }
+        }
+
+        let data_start = (headers_end + 15) & !15;
+        let mut data_offsets = Vec::with_capacity(self.entries.len());
+        let mut end = data_start;
+        for entry in &self.entries {
+            data_offsets.push(end);
+            end = (end + entry.data.len() as u64 + 15) & !15;
+        }
+
+        write_i32(&mut out, self.entries.len() as i32, be)?;
+        write_i32(&mut out, headers_end as i32, be)?;
+        write_i32(&mut out, self.unk18, be)?;
+        write_i32(&mut out, 0, be)?;
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            out.write_u8(entry.flags)?;
+            out.write_all(&[0u8; 3])?;
+            write_i32(&mut out, entry.data.len() as i32, be)?;
+            if fields & FORMAT_LONG_OFFSETS != 0 {
+                write_u64(&mut out, data_offsets[i], be)?;
+            } else {
+                write_u32(&mut out, data_offsets[i] as u32, be)?;
+            }
+            if fields & FORMAT_IDS != 0 {
+                write_i32(&mut out, entry.id, be)?;
+            }
+            if fields & FORMAT_NAMES != 0 {
+                write_u32(&mut out, name_offsets[i] as u32, be)?;
+            }
+            if fields & FORMAT_COMPRESSION != 0 {
+                write_i32(&mut out, entry.uncompressed_size as i32, be)?;
+            }
+        }
+
+        if fields & FORMAT_NAMES != 0 {
+            for entry in &self.entries {
+                out.write_all(entry.name.as_bytes())?;
+                out.write_u8(0)?;
+            }
+        }
+
+        for (entry, &offset) in self.entries.iter().zip(&data_offsets) {
+            out.resize(offset as usize, 0);
+            out.write_all(&entry.data)?;
+        }
+        out.resize(end as usize, 0);
+
+        Ok(out)
+    }
+
+    // The format byte is stored bit-reversed unless the header says bits are
+    // big-endian or the byte already reads as a plain big-endian format
+    fn format_bits(&self) -> u8 {
+        let raw = self.format;
+        if self.bit_big_endian || (raw & 0x01 != 0 && raw & 0x80 == 0) {
+            raw
+        } else {
+            raw.reverse_bits()
+        }
+    }
+
+    pub fn get_entry(&self, name: &str) -> Option<&Bnd3Entry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    pub fn get_entry_mut(&mut self, name: &str) -> Option<&mut Bnd3Entry> {
+        self.entries.iter_mut().find(|e| e.name == name)
+    }
+
+    pub fn extract_to<P: AsRef<Path>>(&self, dir: P, preserve_paths: bool) -> io::Result<usize> {
+        let dir = dir.as_ref();
+        let mut written = 0;
+        for entry in &self.entries {
+            let Some(path) = super::entry_output_path(dir, &entry.name, preserve_paths) else {
+                continue;
+            };
+            super::write_entry(&path, &entry.data)?;
+            written += 1;
+        }
+        Ok(written)
+    }
+}
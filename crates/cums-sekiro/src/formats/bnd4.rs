@@ -1,5 +1,8 @@
+use super::{Dcx, DcxType};
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::borrow::Cow;
 use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
 
 const BND4_MAGIC: &[u8; 4] = b"BND4";
 
@@ -11,6 +14,39 @@ pub struct Bnd4Entry {
     pub uncompressed_size: u64,
     pub compressed_size: u64,
     pub data: Vec<u8>,
+    // When set, `write` DCX-compresses `data` and fills in both sizes and the
+    // compressed flag. Entries read from a file keep their stored bytes, so
+    // this is always None for them.
+    pub compression: Option<DcxType>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Dds,
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if data.starts_with(b"DDS ") {
+            Some(ImageFormat::Dds)
+        } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(ImageFormat::Jpeg)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Dds => "dds",
+            ImageFormat::Jpeg => "jpg",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -132,7 +168,7 @@ impl Bnd4 {
             let name = if unicode {
                 read_wide_string(&mut cursor)?
             } else {
-                read_string(&mut cursor)?
+                super::read_string(&mut cursor)?
             };
 
             cursor.seek(SeekFrom::Start(data_offset))?;
@@ -153,6 +189,7 @@ impl Bnd4 {
                 uncompressed_size,
                 compressed_size: compressed_size as u64,
                 data: file_data,
+                compression: None,
             });
         }
 
@@ -230,7 +267,7 @@ impl Bnd4 {
             .copy_from_slice(&version_src[..version_src.len().min(8)]);
         cursor.write_all(&version_bytes)?;
 
-        let entry_header_size = if self.extended == 0x10 { 36u64 } else { 24u64 };
+        let entry_header_size = if self.extended == 0x10 { 48u64 } else { 32u64 };
         write_u64!(cursor, entry_header_size, be);
 
         let data_offset_pos = cursor.position();
@@ -263,21 +300,54 @@ impl Bnd4 {
 
         let data_start = (current_name_offset + 15) & !15;
 
+        // (flags, stored bytes, compressed size, uncompressed size) per entry
+        let mut payloads = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            payloads.push(match entry.compression {
+                Some(compression) => {
+                    if self.extended != 0x10 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "{}: compressed entries need the extended entry header",
+                                entry.name
+                            ),
+                        ));
+                    }
+                    let packed = Dcx::compress(&entry.data, compression)?;
+                    let size = packed.len() as u64;
+                    (
+                        entry.flags | self.compressed_flag(),
+                        Cow::Owned(packed),
+                        size,
+                        entry.data.len() as u64,
+                    )
+                }
+                None => (
+                    entry.flags,
+                    Cow::Borrowed(entry.data.as_slice()),
+                    entry.compressed_size,
+                    entry.uncompressed_size,
+                ),
+            });
+        }
+
         let mut current_data_offset = data_start;
         let mut data_offsets = Vec::new();
-        for entry in &self.entries {
+        for (_, data, _, _) in &payloads {
             data_offsets.push(current_data_offset);
-            current_data_offset += entry.data.len() as u64;
+            current_data_offset += data.len() as u64;
             current_data_offset = (current_data_offset + 15) & !15;
         }
 
         for (i, entry) in self.entries.iter().enumerate() {
-            cursor.write_u8(entry.flags)?;
+            let (flags, _, compressed_size, uncompressed_size) = &payloads[i];
+            cursor.write_u8(*flags)?;
             cursor.write_all(&[0u8; 3])?;
             write_i32!(cursor, -1, be);
-            write_i64!(cursor, entry.compressed_size as i64, be);
+            write_i64!(cursor, *compressed_size as i64, be);
             if self.extended == 0x10 {
-                write_u64!(cursor, entry.uncompressed_size, be);
+                write_u64!(cursor, *uncompressed_size, be);
             }
             write_u64!(cursor, data_offsets[i], be);
             write_i32!(cursor, entry.id, be);
@@ -300,9 +370,9 @@ impl Bnd4 {
             cursor.write_u8(0)?;
         }
 
-        for (i, entry) in self.entries.iter().enumerate() {
+        for (i, (_, data, _, _)) in payloads.iter().enumerate() {
             cursor.seek(SeekFrom::Start(data_offsets[i]))?;
-            cursor.write_all(&entry.data)?;
+            cursor.write_all(data)?;
         }
 
         let total_size = cursor.position();
@@ -317,6 +387,26 @@ impl Bnd4 {
         Ok(output)
     }
 
+    // Entry flags are stored bit-reversed depending on the header's bit order
+    // and format byte, so the compressed bit lands on either end of the byte
+    fn compressed_flag(&self) -> u8 {
+        let raw = self.flags;
+        let format = if self.bit_big_endian || (raw & 0x01 != 0 && raw & 0x80 == 0) {
+            raw
+        } else {
+            raw.reverse_bits()
+        };
+        if self.bit_big_endian || (format & 0x20 != 0 && format & 0x80 == 0) {
+            0x80
+        } else {
+            0x01
+        }
+    }
+
+    pub fn is_compressed(&self, entry: &Bnd4Entry) -> bool {
+        entry.flags & self.compressed_flag() != 0
+    }
+
     pub fn get_entry(&self, name: &str) -> Option<&Bnd4Entry> {
         self.entries.iter().find(|e| e.name == name)
     }
@@ -324,18 +414,27 @@ impl Bnd4 {
     pub fn get_entry_mut(&mut self, name: &str) -> Option<&mut Bnd4Entry> {
         self.entries.iter_mut().find(|e| e.name == name)
     }
-}
 
-fn read_string(cursor: &mut Cursor<&[u8]>) -> io::Result<String> {
-    let mut bytes = Vec::new();
-    loop {
-        let b = cursor.read_u8()?;
-        if b == 0 {
-            break;
+    // Entries recognised by their magic, whatever their name says
+    pub fn image_entries(&self) -> Vec<(&Bnd4Entry, ImageFormat)> {
+        self.entries
+            .iter()
+            .filter_map(|e| ImageFormat::detect(&e.data).map(|f| (e, f)))
+            .collect()
+    }
+
+    pub fn extract_to<P: AsRef<Path>>(&self, dir: P, preserve_paths: bool) -> io::Result<usize> {
+        let dir = dir.as_ref();
+        let mut written = 0;
+        for entry in &self.entries {
+            let Some(path) = super::entry_output_path(dir, &entry.name, preserve_paths) else {
+                continue;
+            };
+            super::write_entry(&path, &entry.data)?;
+            written += 1;
         }
-        bytes.push(b);
+        Ok(written)
     }
-    Ok(String::from_utf8_lossy(&bytes).into_owned())
 }
 
 fn read_wide_string(cursor: &mut Cursor<&[u8]>) -> io::Result<String> {
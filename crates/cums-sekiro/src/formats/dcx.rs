@@ -2,7 +2,7 @@ use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
-use std::io::{self, Cursor, Read, Write};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
 
 const DCX_MAGIC: &[u8; 4] = b"DCX\0";
 const DCS_MAGIC: &[u8; 4] = b"DCS\0";
@@ -61,9 +61,10 @@ impl Dcx {
         cursor.read_exact(&mut magic)?;
 
         let _unk04 = cursor.read_u32::<BigEndian>()?;
-        let _dcs_offset = cursor.read_u32::<BigEndian>()?;
-        let _dcp_offset = cursor.read_u32::<BigEndian>()?;
+        let dcs_offset = cursor.read_u32::<BigEndian>()?;
+        let dcp_offset = cursor.read_u32::<BigEndian>()?;
 
+        cursor.seek(SeekFrom::Start(dcs_offset as u64))?;
         cursor.read_exact(&mut magic)?;
         if &magic != DCS_MAGIC {
             return Err(io::Error::new(
@@ -93,20 +94,21 @@ impl Dcx {
             )
         })?;
 
-        let _dcp_unk08 = cursor.read_u32::<BigEndian>()?;
-        let _dcp_unk0c = cursor.read_u32::<BigEndian>()?;
-        let _dcp_unk10 = cursor.read_u32::<BigEndian>()?;
-        let _dcp_unk14 = cursor.read_u32::<BigEndian>()?;
-
-        if compression == DcxType::Dflt || compression == DcxType::Zlib {
-            cursor.read_exact(&mut magic)?;
-            if &magic == DCA_MAGIC {
-                let _dca_size = cursor.read_u32::<BigEndian>()?;
-            }
-        }
-
-        let data_offset = cursor.position() as usize;
-        let compressed_data = &data[data_offset..data_offset + compressed_size as usize];
+        // The DCP block is always 0x20 bytes and is followed by the DCA block,
+        // whose size field covers its own header
+        let dca_offset = dcp_offset as u64 + 0x20;
+        cursor.seek(SeekFrom::Start(dca_offset))?;
+        cursor.read_exact(&mut magic)?;
+        let data_offset = if &magic == DCA_MAGIC {
+            dca_offset + cursor.read_u32::<BigEndian>()? as u64
+        } else {
+            dca_offset
+        } as usize;
+        let compressed_data = data
+            .get(data_offset..data_offset + compressed_size as usize)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "DCX data out of bounds")
+            })?;
 
         let decompressed = match compression {
             DcxType::Dflt | DcxType::Zlib => {
@@ -123,10 +125,15 @@ impl Dcx {
                     }
                 }
             }
+            #[cfg(feature = "oodle")]
+            DcxType::Kraken => {
+                super::oodle::decompress(compressed_data, uncompressed_size as usize)?
+            }
+            #[cfg(not(feature = "oodle"))]
             DcxType::Kraken => {
                 return Err(io::Error::new(
                     io::ErrorKind::Unsupported,
-                    "Kraken/Oodle compression not supported - requires oo2core_6_win64.dll",
+                    "Kraken/Oodle compression needs a build with the `oodle` feature and oo2core_6_win64.dll",
                 ));
             }
             DcxType::Edge => {
@@ -1,7 +1,51 @@
 pub mod bhd5;
+pub mod bnd3;
 pub mod bnd4;
 pub mod dcx;
+#[cfg(feature = "oodle")]
+mod oodle;
 
 pub use bhd5::*;
+pub use bnd3::*;
 pub use bnd4::*;
 pub use dcx::*;
+
+use byteorder::ReadBytesExt;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+// Maps an archive entry name to a file under `dir`. Game paths use backslashes
+// and drive prefixes like `N:\`; those, `..` and empty components are dropped
+// so an untrusted archive can't write outside `dir`.
+pub fn entry_output_path(dir: &Path, name: &str, preserve_paths: bool) -> Option<PathBuf> {
+    let parts: Vec<&str> = name
+        .split(['/', '\\'])
+        .filter(|p| !p.is_empty() && *p != "." && *p != ".." && !p.contains(':'))
+        .collect();
+    let (file, dirs) = parts.split_last()?;
+    let mut path = dir.to_path_buf();
+    if preserve_paths {
+        path.extend(dirs);
+    }
+    path.push(file);
+    Some(path)
+}
+
+fn read_string(cursor: &mut Cursor<&[u8]>) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    loop {
+        let b = cursor.read_u8()?;
+        if b == 0 {
+            break;
+        }
+        bytes.push(b);
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn write_entry(path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, data)
+}
@@ -0,0 +1,90 @@
+// Oodle can't be redistributed, so Kraken DCX is decompressed by loading the
+// DLL that ships with the game at runtime
+use libloading::Library;
+use once_cell::sync::Lazy;
+use std::ffi::c_void;
+use std::io;
+
+// Sekiro and DS3 ship version 6; Elden Ring and later ship 8 or 9
+const OODLE_DLLS: [&str; 3] = [
+    "oo2core_6_win64.dll",
+    "oo2core_8_win64.dll",
+    "oo2core_9_win64.dll",
+];
+
+type OodleLzDecompress = unsafe extern "C" fn(
+    comp: *const u8,
+    comp_len: isize,
+    raw: *mut u8,
+    raw_len: isize,
+    fuzz_safe: i32,
+    check_crc: i32,
+    verbosity: i32,
+    dec_buf_base: *mut u8,
+    dec_buf_size: isize,
+    callback: *mut c_void,
+    callback_data: *mut c_void,
+    decoder_memory: *mut c_void,
+    decoder_memory_size: isize,
+    thread_phase: i32,
+) -> isize;
+
+const THREAD_PHASE_ALL: i32 = 3;
+
+// Looks next to the executable first, then on the normal library search path
+static OODLE: Lazy<Option<Library>> = Lazy::new(|| {
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|d| d.to_path_buf()));
+    OODLE_DLLS.iter().find_map(|name| {
+        let local = exe_dir.as_ref().map(|d| d.join(name));
+        local
+            .and_then(|path| unsafe { Library::new(path) }.ok())
+            .or_else(|| unsafe { Library::new(name) }.ok())
+    })
+});
+
+pub fn decompress(compressed: &[u8], uncompressed_size: usize) -> io::Result<Vec<u8>> {
+    let lib = OODLE.as_ref().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "Kraken/Oodle compression needs {} (or a newer oo2core_*_win64.dll) next to the program",
+                OODLE_DLLS[0]
+            ),
+        )
+    })?;
+    let decompress = unsafe { lib.get::<OodleLzDecompress>(b"OodleLZ_Decompress\0") }
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut output = vec![0u8; uncompressed_size];
+    let written = unsafe {
+        decompress(
+            compressed.as_ptr(),
+            compressed.len() as isize,
+            output.as_mut_ptr(),
+            output.len() as isize,
+            1,
+            0,
+            0,
+            std::ptr::null_mut(),
+            0,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            0,
+            THREAD_PHASE_ALL,
+        )
+    };
+    if written as usize != uncompressed_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Oodle decompressed {} of {} bytes",
+                written.max(0),
+                uncompressed_size
+            ),
+        ));
+    }
+    Ok(output)
+}
@@ -1,19 +1,37 @@
 use crate::crypto::{self, FSB_KEY};
+use crate::error::FsbError;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
 use std::path::Path;
 use std::process::Command;
 
+const FSB3_MAGIC: &[u8; 4] = b"FSB3";
 const FSB4_MAGIC: &[u8; 4] = b"FSB4";
 const FSB5_MAGIC: &[u8; 4] = b"FSB5";
 const FSB5_HEADER_SIZE: usize = 60;
+const FSB3_HEADER_SIZE: usize = 24;
+// FMOD 3 sample mode bits
+const FSB3_LOOP_NORMAL: u32 = 0x00000002;
+const FSB3_LOOP_BIDI: u32 = 0x00000004;
+const FSB3_MPEG: u32 = 0x00000200;
+// Version 0 banks carry an extra u32 after the codec field
+const FSB5_V0_HEADER_SIZE: usize = 64;
+pub const FSB5_HEADER_VERSION: u32 = 1;
+// Real banks use two or three; anything near this means a stuck more-chunks bit
+const MAX_CHUNKS_PER_SAMPLE: usize = 32;
+// Sample offsets are stored in 16-byte units; many banks align to 32
+pub const FSB5_OFFSET_QUANTUM: u64 = 16;
+pub const DEFAULT_DATA_ALIGNMENT: u64 = 32;
 const FREQUENCY_TABLE: [u32; 16] = [
     4000, 8000, 11000, 11025, 16000, 22050, 24000, 32000, 44100, 48000, 96000, 192000, 0, 0, 0, 0,
 ];
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Version {
+    Fsb3,
     Fsb4,
     Fsb5,
 }
@@ -66,10 +84,24 @@ impl Codec {
         match self {
             Self::Mpeg => "mp3",
             Self::Vorbis => "ogg",
+            Self::At9 => "at9",
             Self::Pcm8 | Self::Pcm16 | Self::Pcm24 | Self::Pcm32 | Self::PcmFloat => "wav",
             _ => "bin",
         }
     }
+
+    // Bits per sample and WAV format tag of the uncompressed codecs. FMOD's
+    // 8-bit PCM is unsigned like WAV's, so every one is stored as-is.
+    pub fn wav_format(&self) -> Option<(u16, u16)> {
+        match self {
+            Self::Pcm8 => Some((8, crate::audio::WAVE_FORMAT_PCM)),
+            Self::Pcm16 => Some((16, crate::audio::WAVE_FORMAT_PCM)),
+            Self::Pcm24 => Some((24, crate::audio::WAVE_FORMAT_PCM)),
+            Self::Pcm32 => Some((32, crate::audio::WAVE_FORMAT_PCM)),
+            Self::PcmFloat => Some((32, crate::audio::WAVE_FORMAT_IEEE_FLOAT)),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -91,6 +123,36 @@ impl Fsb4Mode {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fsb5Mode(pub u32);
+
+impl Fsb5Mode {
+    // Only bit 0 is understood, everything else is written back untouched.
+    pub const BASIC_HEADERS: u32 = 0x00000001;
+
+    pub fn basic_headers(&self) -> bool {
+        self.0 & Self::BASIC_HEADERS != 0
+    }
+    pub fn unknown_bits(&self) -> u32 {
+        self.0 & !Self::BASIC_HEADERS
+    }
+}
+
+// Which FSB5 header field held the codec. Some banks leave the codec field
+// zero and put the codec in the low byte of the mode instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CodecSource {
+    #[default]
+    Field,
+    Mode,
+}
+
+// Encoder pre-skip that FSB5 Vorbis lengths can include
+pub const VORBIS_PRE_SKIP: u64 = 3840;
+
+// A sample's display name, exported bytes and file extension
+pub type ExportedSample = (String, Vec<u8>, &'static str);
+
 #[derive(Debug, Clone)]
 pub struct Sample {
     pub index: usize,
@@ -104,6 +166,13 @@ pub struct Sample {
     pub loop_end: Option<u32>,
     pub vorbis_crc: Option<u32>,
     pub vorbis_seek_table: Option<Vec<u32>>,
+    // GC ADPCM coefficient table per channel, from the FSB5 DSP chunk
+    pub dsp_coeffs: Option<Vec<[i16; 16]>>,
+    // ATRAC9 config word, from the FSB5 ATRAC9 chunk
+    pub at9_config: Option<[u8; 4]>,
+    // Every FSB5 chunk as (type, payload), including ones this crate doesn't
+    // interpret. Read-only: saving rebuilds chunks from the fields above.
+    pub chunks: Vec<(u8, Vec<u8>)>,
     pub mode: Option<Fsb4Mode>,
 }
 
@@ -115,9 +184,208 @@ impl Sample {
             0.0
         }
     }
+
+    // Length in frames for display. A Vorbis sample's length from the mode
+    // word sometimes counts the encoder's pre-skip, which is left out here.
+    // The header doesn't say when it does, so rebuilt streams keep `samples`
+    // as their final granule rather than risk cutting off the end.
+    pub fn effective_samples(&self) -> u64 {
+        if self.vorbis_crc.is_some() && self.samples > VORBIS_PRE_SKIP {
+            self.samples - VORBIS_PRE_SKIP
+        } else {
+            self.samples
+        }
+    }
+
+    // Zero-byte placeholders some banks carry; exports skip them
+    pub fn is_empty(&self) -> bool {
+        self.data_size == 0
+    }
+
+    pub fn display_name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("sound_{}", self.index))
+    }
+
+    pub fn chunk(&self, ty: u8) -> Option<&[u8]> {
+        self.chunks
+            .iter()
+            .find(|(t, _)| *t == ty)
+            .map(|(_, payload)| payload.as_slice())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Changed(Vec<&'static str>),
+}
+
+#[derive(Debug, Clone)]
+pub struct SampleDiff {
+    pub index: usize,
+    pub name: String,
+    pub kind: DiffKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssueKind {
+    DataOutOfBounds,
+    BadAudioPacket,
+    ChannelMismatch { declared: u32, actual: u32 },
+    LengthMismatch { declared: u64, actual: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct SampleIssue {
+    pub index: usize,
+    pub name: String,
+    pub kind: IssueKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameProfile {
+    Sekiro,
+    DarkSouls3,
+    DarkSouls2,
+    DarkSouls1,
+}
+
+impl GameProfile {
+    pub const ALL: [GameProfile; 4] = [
+        GameProfile::Sekiro,
+        GameProfile::DarkSouls3,
+        GameProfile::DarkSouls2,
+        GameProfile::DarkSouls1,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameProfile::Sekiro => "Sekiro",
+            GameProfile::DarkSouls3 => "Dark Souls 3",
+            GameProfile::DarkSouls2 => "Dark Souls 2",
+            GameProfile::DarkSouls1 => "Dark Souls 1",
+        }
+    }
+
+    // What the game's sound loader accepts, as listed in the README
+    pub fn expects(&self) -> (Version, Codec, Encryption) {
+        match self {
+            GameProfile::Sekiro | GameProfile::DarkSouls3 => {
+                (Version::Fsb5, Codec::Vorbis, Encryption::Aes)
+            }
+            GameProfile::DarkSouls2 => (Version::Fsb5, Codec::Vorbis, Encryption::None),
+            GameProfile::DarkSouls1 => (Version::Fsb4, Codec::Mpeg, Encryption::None),
+        }
+    }
+}
+
+// Which game a bank's container matches. Sekiro and Dark Souls 3 load the
+// same format, so those banks can't be told apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameGuess {
+    Game(GameProfile),
+    Either(GameProfile, GameProfile),
+    Unknown,
+}
+
+impl GameGuess {
+    pub fn label(&self) -> String {
+        match self {
+            GameGuess::Game(game) => game.name().to_string(),
+            GameGuess::Either(a, b) => format!("{} or {}", a.name(), b.name()),
+            GameGuess::Unknown => "Unknown game".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintIssue {
+    WrongVersion {
+        game: GameProfile,
+        expected: Version,
+        actual: Version,
+    },
+    WrongCodec {
+        game: GameProfile,
+        expected: Codec,
+        actual: Codec,
+    },
+    WrongEncryption {
+        game: GameProfile,
+        expected: Encryption,
+        actual: Encryption,
+    },
+    Empty,
+}
+
+impl std::fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LintIssue::WrongVersion {
+                game,
+                expected,
+                actual,
+            } => {
+                let name = |v: &Version| match v {
+                    Version::Fsb3 => "FSB3",
+                    Version::Fsb4 => "FSB4",
+                    Version::Fsb5 => "FSB5",
+                };
+                write!(
+                    f,
+                    "This bank is {} but {} only loads {}",
+                    name(actual),
+                    game.name(),
+                    name(expected)
+                )
+            }
+            LintIssue::WrongCodec {
+                game,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Codec is {:?} but {} only loads {:?}",
+                actual,
+                game.name(),
+                expected
+            ),
+            LintIssue::WrongEncryption { game, expected, .. } if *expected == Encryption::None => {
+                write!(
+                    f,
+                    "This bank is encrypted but {} expects plaintext",
+                    game.name()
+                )
+            }
+            LintIssue::WrongEncryption {
+                game,
+                actual: Encryption::None,
+                ..
+            } => write!(
+                f,
+                "This bank is plaintext but {} expects encryption",
+                game.name()
+            ),
+            LintIssue::WrongEncryption {
+                game,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "This bank uses {:?} encryption but {} expects {:?}",
+                actual,
+                game.name(),
+                expected
+            ),
+            LintIssue::Empty => write!(f, "This bank has no sounds"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct FsbBank {
     pub version: Version,
     pub codec: Codec,
@@ -129,36 +397,256 @@ pub struct FsbBank {
     pub name_table_size: u32,
     pub data_size: u32,
     pub flags: u32,
-    pub fsb5_mode: u32,
+    pub fsb5_mode: Fsb5Mode,
+    // Saving writes the codec back to the same field
+    pub codec_source: CodecSource,
+    // Boundary FSB5 sample data is padded to on save. Loading infers it from
+    // the original offsets so unmodified banks keep their layout.
+    pub data_alignment: u64,
+    // Version field of the FSB5 header, 0 for early banks with the longer
+    // header. Saving writes the same layout back.
+    pub fsb5_header_version: u32,
+    // The extra u32 version 0 headers carry after the codec field, written
+    // back as loaded. Zero for other versions.
+    pub fsb5_v0_extra: u32,
+    // The 24 FSB5 header bytes after the flags, as decrypted at load and
+    // written back unchanged. Zero for FSB4.
+    pub header_tail: [u8; 24],
+    // Set when the sample headers were cut off; only the samples before the
+    // cut were loaded
+    pub truncated: bool,
+}
+
+// A bank opened with FsbBank::open. Sample data is read from the file on
+// demand, so memory use stays at the headers plus one sample.
+#[derive(Debug)]
+pub struct FsbBankReader {
+    pub version: Version,
+    pub codec: Codec,
+    pub encryption: Encryption,
+    pub samples: Vec<Sample>,
+    file: File,
+    data: Range<u64>,
+}
+
+impl FsbBankReader {
+    pub fn read_sample(&mut self, index: usize) -> io::Result<Vec<u8>> {
+        let sample = self
+            .samples
+            .get(index)
+            .ok_or(FsbError::SampleNotFound { index })?;
+        let (start, end) = (sample.data_offset, sample.data_offset + sample.data_size);
+
+        // AES runs in 16-byte blocks counted from the start of the data
+        // section, with a trailing partial block left in the clear
+        let (from, to) = match self.encryption {
+            Encryption::Aes => {
+                let base = self.data.start;
+                let from = base + (start - base) / 16 * 16;
+                let to = base + (end - base).next_multiple_of(16);
+                (from, to.min(self.data.end).max(end))
+            }
+            _ => (start, end),
+        };
+        let file_len = self.file.metadata()?.len();
+        if to > file_len {
+            return Err(FsbError::SampleOutOfBounds { index }.into());
+        }
+
+        let mut buf = vec![0u8; (to - from) as usize];
+        self.file.seek(SeekFrom::Start(from))?;
+        self.file.read_exact(&mut buf)?;
+        match self.encryption {
+            Encryption::None => {}
+            Encryption::Aes => {
+                let whole = (self.data.end.min(to) - from) as usize;
+                crypto::decrypt_aes_data(&mut buf[..whole], FSB_KEY);
+            }
+            Encryption::Fsbext => {
+                let key: Vec<u8> = FSB_KEY
+                    .iter()
+                    .cycle()
+                    .skip(from as usize % FSB_KEY.len())
+                    .take(FSB_KEY.len())
+                    .copied()
+                    .collect();
+                crypto::fsbext_decrypt(&mut buf, &key);
+            }
+        }
+        buf.truncate((end - from) as usize);
+        buf.drain(..(start - from) as usize);
+        Ok(buf)
+    }
 }
 
 impl FsbBank {
     pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let mut file = File::open(path)?;
+        Self::from_reader_seek(File::open(path)?)
+    }
+
+    // Reads the rest of the stream into memory and parses it. The bank keeps
+    // the whole file buffered, so this is no cheaper than from_bytes.
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
         let mut data = Vec::new();
-        file.read_to_end(&mut data)?;
-        Self::from_bytes(data)
+        reader.read_to_end(&mut data)?;
+        Ok(Self::from_bytes(data)?)
+    }
+
+    // Like from_reader, but sizes the buffer up front from the stream length.
+    // Reading starts at the current position. Still fully buffered for now.
+    pub fn from_reader_seek<R: Read + Seek>(mut reader: R) -> io::Result<Self> {
+        let start = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(start))?;
+        let mut data = Vec::with_capacity(end.saturating_sub(start) as usize);
+        reader.read_to_end(&mut data)?;
+        Ok(Self::from_bytes(data)?)
     }
 
-    pub fn from_bytes(data: Vec<u8>) -> io::Result<Self> {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, FsbError> {
         if data.len() < 8 {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "File too small"));
+            return Err(FsbError::TooSmall);
         }
 
         let version = Self::detect_version(&data)?;
+        let bank = match version {
+            Version::Fsb3 => Self::parse_fsb3(data),
+            Version::Fsb4 => {
+                let encryption = detect_fsb4_encryption(&data);
+                Self::parse_fsb4_as(data, encryption)
+            }
+            Version::Fsb5 => {
+                let encryption = detect_fsb5_encryption(&data);
+                Self::parse_fsb5_as(data, encryption)
+            }
+        };
+        Ok(bank?)
+    }
+
+    // Parses only the header, sample headers and names; sample data stays on
+    // disk until FsbBankReader::read_sample asks for it
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FsbBankReader> {
+        let mut file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        if file_len < 8 {
+            return Err(FsbError::TooSmall.into());
+        }
+        let mut fixed = vec![0u8; (file_len as usize).min(FSB5_V0_HEADER_SIZE)];
+        file.read_exact(&mut fixed)?;
+
+        let version = Self::detect_version(&fixed)?;
+        let encryption = match version {
+            Version::Fsb3 => Encryption::None,
+            Version::Fsb4 => detect_fsb4_encryption(&fixed),
+            Version::Fsb5 => detect_fsb5_encryption(&fixed),
+        };
+        match encryption {
+            Encryption::None => {}
+            Encryption::Aes => crypto::decrypt_aes_block(&mut fixed[0..32], FSB_KEY),
+            Encryption::Fsbext => crypto::fsbext_decrypt(&mut fixed, FSB_KEY),
+        }
+        let field = |offset: usize| {
+            fixed
+                .get(offset..offset + 4)
+                .map_or(0, |b| u32::from_le_bytes(b.try_into().unwrap()) as u64)
+        };
+        let headers_len = match version {
+            Version::Fsb3 => FSB3_HEADER_SIZE as u64 + field(8),
+            Version::Fsb4 => 48 + field(8),
+            Version::Fsb5 => fsb5_header_size(field(4) as u32) as u64 + field(12) + field(16),
+        };
+        if headers_len > file_len {
+            return Err(FsbError::Truncated {
+                expected: headers_len,
+                actual: file_len,
+            }
+            .into());
+        }
+
+        let mut headers = vec![0u8; headers_len as usize];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut headers)?;
+        let bank = match version {
+            Version::Fsb3 => Self::parse_fsb3_sized(headers, file_len)?,
+            Version::Fsb4 => Self::parse_fsb4_sized(headers, encryption, file_len)?,
+            Version::Fsb5 => Self::parse_fsb5_sized(headers, encryption, file_len)?,
+        };
+        let data_offset = (bank.header_size + bank.sample_headers_size as usize) as u64
+            + bank.name_table_size as u64;
+        Ok(FsbBankReader {
+            version: bank.version,
+            codec: bank.codec,
+            encryption: bank.encryption,
+            samples: bank.samples,
+            file,
+            data: data_offset..data_offset + bank.data_size as u64,
+        })
+    }
+
+    pub fn from_bytes_as(
+        data: Vec<u8>,
+        version: Version,
+        encryption: Encryption,
+    ) -> io::Result<Self> {
+        if data.len() < 8 {
+            return Err(FsbError::TooSmall.into());
+        }
+
         match version {
-            Version::Fsb4 => Self::parse_fsb4(data),
-            Version::Fsb5 => Self::parse_fsb5(data),
+            Version::Fsb3 => {
+                if encryption != Encryption::None {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        format!("{:?} encryption is not supported for FSB3", encryption),
+                    ));
+                }
+                if &data[0..4] != FSB3_MAGIC {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Not an FSB3 file (magic mismatch)",
+                    ));
+                }
+                Self::parse_fsb3(data)
+            }
+            Version::Fsb4 => {
+                if encryption == Encryption::Aes {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "AES encryption is not supported for FSB4",
+                    ));
+                }
+                let mut magic = data[0..4].to_vec();
+                if encryption == Encryption::Fsbext {
+                    crypto::fsbext_decrypt(&mut magic, FSB_KEY);
+                }
+                if magic != FSB4_MAGIC {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Not an FSB4 file with {:?} encryption (magic mismatch)",
+                            encryption
+                        ),
+                    ));
+                }
+                Self::parse_fsb4_as(data, encryption)
+            }
+            Version::Fsb5 => Self::parse_fsb5_as(data, encryption),
         }
     }
 
-    fn detect_version(data: &[u8]) -> io::Result<Version> {
+    fn detect_version(data: &[u8]) -> Result<Version, FsbError> {
+        if &data[0..4] == FSB3_MAGIC {
+            return Ok(Version::Fsb3);
+        }
         if &data[0..4] == FSB4_MAGIC {
             return Ok(Version::Fsb4);
         }
         if &data[0..4] == FSB5_MAGIC {
             return Ok(Version::Fsb5);
         }
+        if data.len() < 32 {
+            return Err(FsbError::UnknownFormat);
+        }
 
         let mut test = data[0..32].to_vec();
         crypto::decrypt_aes_block(&mut test, FSB_KEY);
@@ -171,11 +659,126 @@ impl FsbBank {
         if &test2[0..4] == FSB5_MAGIC {
             return Ok(Version::Fsb5);
         }
+        if &test2[0..4] == FSB4_MAGIC {
+            return Ok(Version::Fsb4);
+        }
+
+        Err(FsbError::UnknownFormat)
+    }
+
+    fn parse_fsb3(data: Vec<u8>) -> io::Result<Self> {
+        let len = data.len() as u64;
+        Self::parse_fsb3_sized(data, len)
+    }
+
+    // Same idea as FSB4 with a shorter bank header. Sample headers are 68
+    // bytes in FSB 3.0 and 80 in 3.1, so each one is skipped by its size.
+    fn parse_fsb3_sized(data: Vec<u8>, file_len: u64) -> io::Result<Self> {
+        let mut cursor = Cursor::new(&data);
+        cursor.seek(SeekFrom::Start(4))?;
+
+        let sample_count = cursor.read_u32::<LittleEndian>()?;
+        let sample_headers_size = cursor.read_u32::<LittleEndian>()?;
+        let data_size = cursor.read_u32::<LittleEndian>()?;
+        let _version = cursor.read_u32::<LittleEndian>()?;
+        let flags = cursor.read_u32::<LittleEndian>()?;
+
+        let header_size = FSB3_HEADER_SIZE;
+        let data_offset = header_size + sample_headers_size as usize;
+        check_truncated(file_len, data_offset as u64 + data_size as u64)?;
+        let mut samples = Vec::with_capacity(sample_count as usize);
+        let mut current_data_offset = data_offset as u64;
+        let mut mpeg = false;
+
+        for i in 0..sample_count as usize {
+            let entry_start = cursor.position();
+            let entry_size = cursor.read_u16::<LittleEndian>()?;
+            if entry_size < 64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("FSB3 sample {} header is only {} bytes", i, entry_size),
+                ));
+            }
+            let mut name_bytes = [0u8; 30];
+            cursor.read_exact(&mut name_bytes)?;
+            let name = String::from_utf8_lossy(&name_bytes)
+                .trim_end_matches('\0')
+                .to_string();
+
+            let sample_count_field = cursor.read_u32::<LittleEndian>()?;
+            let compressed_size = cursor.read_u32::<LittleEndian>()?;
+            let loop_start = cursor.read_u32::<LittleEndian>()?;
+            let loop_end = cursor.read_u32::<LittleEndian>()?;
+            let mode = cursor.read_u32::<LittleEndian>()?;
+            let def_freq = cursor.read_u32::<LittleEndian>()?;
+            // Volume, pan and priority
+            cursor.seek(SeekFrom::Current(6))?;
+            let channels = cursor.read_u16::<LittleEndian>()?;
+            cursor.seek(SeekFrom::Start(entry_start + entry_size as u64))?;
+
+            mpeg |= mode & FSB3_MPEG != 0;
+            let looped = mode & (FSB3_LOOP_NORMAL | FSB3_LOOP_BIDI) != 0;
+            samples.push(Sample {
+                index: i,
+                name: Some(name),
+                frequency: if def_freq > 0 { def_freq } else { 44100 },
+                channels: channels.max(1) as u32,
+                samples: sample_count_field as u64,
+                data_offset: current_data_offset,
+                data_size: compressed_size as u64,
+                loop_start: looped.then_some(loop_start),
+                loop_end: looped.then_some(loop_end),
+                vorbis_crc: None,
+                vorbis_seek_table: None,
+                dsp_coeffs: None,
+                at9_config: None,
+                chunks: Vec::new(),
+                mode: None,
+            });
+            current_data_offset += compressed_size as u64;
+        }
+
+        let codec = if mpeg { Codec::Mpeg } else { Codec::Pcm16 };
+        if codec == Codec::Mpeg {
+            recount_mp3_samples(&mut samples, &data);
+        }
+
+        Ok(FsbBank {
+            version: Version::Fsb3,
+            codec,
+            samples,
+            encryption: Encryption::None,
+            data,
+            header_size,
+            sample_headers_size,
+            name_table_size: 0,
+            data_size,
+            flags,
+            fsb5_mode: Fsb5Mode::default(),
+            codec_source: CodecSource::Field,
+            data_alignment: DEFAULT_DATA_ALIGNMENT,
+            fsb5_header_version: FSB5_HEADER_VERSION,
+            fsb5_v0_extra: 0,
+            header_tail: [0; 24],
+            truncated: false,
+        })
+    }
 
-        Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown format"))
+    fn parse_fsb4_as(data: Vec<u8>, encryption: Encryption) -> io::Result<Self> {
+        let len = data.len() as u64;
+        Self::parse_fsb4_sized(data, encryption, len)
     }
 
-    fn parse_fsb4(data: Vec<u8>) -> io::Result<Self> {
+    // `data` holds at least the headers; `file_len` is the size of the whole
+    // bank, which may not all be in memory. Fsbext covers the whole file.
+    fn parse_fsb4_sized(
+        mut data: Vec<u8>,
+        encryption: Encryption,
+        file_len: u64,
+    ) -> io::Result<Self> {
+        if encryption == Encryption::Fsbext {
+            crypto::fsbext_decrypt(&mut data, FSB_KEY);
+        }
         let mut cursor = Cursor::new(&data);
         cursor.seek(SeekFrom::Start(4))?;
 
@@ -188,6 +791,7 @@ impl FsbBank {
 
         let header_size = 48usize;
         let data_offset = header_size + sample_headers_size as usize;
+        check_truncated(file_len, data_offset as u64 + data_size as u64)?;
         let mut samples = Vec::with_capacity(sample_count as usize);
         let mut current_data_offset = data_offset as u64;
 
@@ -227,6 +831,9 @@ impl FsbBank {
                 },
                 vorbis_crc: None,
                 vorbis_seek_table: None,
+                dsp_coeffs: None,
+                at9_config: None,
+                chunks: Vec::new(),
                 mode: Some(mode),
             });
             current_data_offset += compressed_size as u64;
@@ -238,168 +845,271 @@ impl FsbBank {
             Codec::Pcm16
         };
 
+        if codec == Codec::Mpeg {
+            recount_mp3_samples(&mut samples, &data);
+        }
+
         Ok(FsbBank {
             version: Version::Fsb4,
             codec,
             samples,
-            encryption: Encryption::None,
+            encryption,
             data,
             header_size,
             sample_headers_size,
             name_table_size: 0,
             data_size,
             flags,
-            fsb5_mode: 0,
+            fsb5_mode: Fsb5Mode::default(),
+            codec_source: CodecSource::Field,
+            data_alignment: DEFAULT_DATA_ALIGNMENT,
+            fsb5_header_version: FSB5_HEADER_VERSION,
+            fsb5_v0_extra: 0,
+            header_tail: [0; 24],
+            truncated: false,
         })
     }
 
-    fn parse_fsb5(mut data: Vec<u8>) -> io::Result<Self> {
-        let encryption = if &data[0..4] == FSB5_MAGIC {
-            Encryption::None
-        } else {
-            let mut test = data[0..32].to_vec();
-            crypto::decrypt_aes_block(&mut test, FSB_KEY);
-            if &test[0..4] == FSB5_MAGIC {
-                Encryption::Aes
-            } else {
-                Encryption::Fsbext
-            }
-        };
+    fn parse_fsb5_as(data: Vec<u8>, encryption: Encryption) -> io::Result<Self> {
+        let len = data.len() as u64;
+        Self::parse_fsb5_sized(data, encryption, len)
+    }
 
+    fn parse_fsb5_sized(
+        mut data: Vec<u8>,
+        encryption: Encryption,
+        file_len: u64,
+    ) -> io::Result<Self> {
         match encryption {
             Encryption::None => {}
-            Encryption::Aes => crypto::decrypt_aes_block(&mut data[0..32], FSB_KEY),
+            Encryption::Aes => {
+                if data.len() < 32 {
+                    return Err(FsbError::TooSmall.into());
+                }
+                crypto::decrypt_aes_block(&mut data[0..32], FSB_KEY)
+            }
             Encryption::Fsbext => crypto::fsbext_decrypt(&mut data, FSB_KEY),
         }
 
+        if &data[0..4] != FSB5_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Not an FSB5 file with {:?} encryption (magic mismatch after decryption)",
+                    encryption
+                ),
+            ));
+        }
+
         let (
+            header_version,
             sample_count,
             sample_headers_size,
             name_table_size,
             data_size,
             codec_raw,
+            v0_extra,
             fsb5_mode,
             flags,
         ) = {
             let mut cursor = Cursor::new(&data);
             cursor.seek(SeekFrom::Start(4))?;
-            let _version = cursor.read_u32::<LittleEndian>()?;
+            let header_version = cursor.read_u32::<LittleEndian>()?;
+            let sample_count = cursor.read_u32::<LittleEndian>()?;
+            let sample_headers_size = cursor.read_u32::<LittleEndian>()?;
+            let name_table_size = cursor.read_u32::<LittleEndian>()?;
+            let data_size = cursor.read_u32::<LittleEndian>()?;
+            let codec_raw = cursor.read_u32::<LittleEndian>()?;
+            let v0_extra = if header_version == 0 {
+                cursor.read_u32::<LittleEndian>()?
+            } else {
+                0
+            };
             (
-                cursor.read_u32::<LittleEndian>()?,
-                cursor.read_u32::<LittleEndian>()?,
-                cursor.read_u32::<LittleEndian>()?,
-                cursor.read_u32::<LittleEndian>()?,
-                cursor.read_u32::<LittleEndian>()?,
+                header_version,
+                sample_count,
+                sample_headers_size,
+                name_table_size,
+                data_size,
+                codec_raw,
+                v0_extra,
                 cursor.read_u32::<LittleEndian>()?,
                 cursor.read_u32::<LittleEndian>()?,
             )
         };
+        let header_size = fsb5_header_size(header_version);
+        let mut header_tail = [0u8; 24];
+        if let Some(tail) = data.get(header_size - 24..header_size) {
+            header_tail.copy_from_slice(tail);
+        }
 
-        let codec = Codec::from_u32(codec_raw)
-            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unknown codec"))?;
-
-        let data_offset =
-            FSB5_HEADER_SIZE as u64 + sample_headers_size as u64 + name_table_size as u64;
+        let (codec, codec_source) =
+            fsb5_codec(codec_raw, fsb5_mode).ok_or(FsbError::UnknownCodec(codec_raw))?;
+        if codec_source == CodecSource::Mode {
+            tracing::warn!(
+                "FSB5 codec field is {}; using codec {:?} from the mode 0x{:08X}",
+                codec_raw,
+                codec,
+                fsb5_mode
+            );
+        }
+        let fsb5_mode = Fsb5Mode(fsb5_mode);
+
+        let data_offset = header_size as u64 + sample_headers_size as u64 + name_table_size as u64;
+        check_truncated(data.len() as u64, header_size as u64)?;
+
+        // A short file or a bad data_size field would otherwise give the last
+        // sample a size that runs past the end of the bank
+        let available = file_len.saturating_sub(data_offset);
+        let data_size = if data_size as u64 > available {
+            tracing::warn!(
+                "FSB5 data section claims {} bytes but only {} remain, clamping",
+                data_size,
+                available
+            );
+            available as u32
+        } else {
+            data_size
+        };
+        let data_end = data_offset + data_size as u64;
 
         if encryption == Encryption::Aes {
             let start = data_offset as usize;
             let end = (start + data_size as usize).min(data.len());
-            crypto::decrypt_aes_data(&mut data[start..end], FSB_KEY);
+            if let Some(section) = data.get_mut(start..end) {
+                crypto::decrypt_aes_data(section, FSB_KEY);
+            }
         }
 
-        let mut cursor = Cursor::new(&data);
-        cursor.seek(SeekFrom::Start(FSB5_HEADER_SIZE as u64))?;
+        let headers_end = header_size as u64 + sample_headers_size as u64;
+        let mut cursor = Cursor::new(&data[..]);
+        cursor.seek(SeekFrom::Start(header_size as u64))?;
         let mut samples = Vec::with_capacity(sample_count as usize);
+        let mut truncated = false;
 
         for i in 0..sample_count as usize {
-            let mode = cursor.read_u64::<LittleEndian>()?;
+            let Ok(mode) = cursor.read_u64::<LittleEndian>() else {
+                truncated = true;
+                break;
+            };
             let has_chunks = (mode & 1) != 0;
             let freq_index = ((mode >> 1) & 0xF) as usize;
             let channels = if (mode >> 5) & 1 != 0 { 2 } else { 1 };
             let sample_data_offset = ((mode >> 6) & 0x0FFFFFFF) * 16;
             let sample_count_val = (mode >> 34) & 0x3FFFFFFF;
 
-            let frequency = FREQUENCY_TABLE.get(freq_index).copied().unwrap_or(44100);
-            let mut vorbis_crc = None;
-            let mut vorbis_seek_table = None;
-            let mut loop_start = None;
-            let mut loop_end = None;
-
-            if has_chunks {
-                loop {
-                    let chunk_header = cursor.read_u32::<LittleEndian>()?;
-                    let more_chunks = (chunk_header & 1) != 0;
-                    let chunk_size = ((chunk_header >> 1) & 0xFFFFFF) as usize;
-                    let chunk_type = (chunk_header >> 25) & 0x7F;
-                    let chunk_start = cursor.position();
-
-                    match chunk_type {
-                        3 => {
-                            loop_start = Some(cursor.read_u32::<LittleEndian>()?);
-                            loop_end = Some(cursor.read_u32::<LittleEndian>()?);
-                        }
-                        11 => {
-                            vorbis_crc = Some(cursor.read_u32::<LittleEndian>()?);
-                            let seek_count = (chunk_size - 4) / 4;
-                            let mut table = Vec::with_capacity(seek_count);
-                            for _ in 0..seek_count {
-                                table.push(cursor.read_u32::<LittleEndian>()?);
-                            }
-                            vorbis_seek_table = Some(table);
-                        }
-                        _ => {}
-                    }
-                    cursor.seek(SeekFrom::Start(chunk_start + chunk_size as u64))?;
-                    if !more_chunks {
-                        break;
-                    }
-                }
-            }
-
-            samples.push(Sample {
+            let mut sample = Sample {
                 index: i,
                 name: None,
-                frequency,
+                frequency: FREQUENCY_TABLE.get(freq_index).copied().unwrap_or(44100),
                 channels,
                 samples: sample_count_val,
                 data_offset: data_offset + sample_data_offset,
                 data_size: 0,
-                loop_start,
-                loop_end,
-                vorbis_crc,
-                vorbis_seek_table,
+                loop_start: None,
+                loop_end: None,
+                vorbis_crc: None,
+                vorbis_seek_table: None,
+                dsp_coeffs: None,
+                at9_config: None,
+                chunks: Vec::new(),
                 mode: None,
-            });
-        }
-
-        for i in 0..samples.len() {
-            let next_offset = if i + 1 < samples.len() {
-                samples[i + 1].data_offset
+            };
+            let parsed = if has_chunks {
+                parse_fsb5_chunks(&mut cursor, &mut sample, headers_end)
             } else {
-                data_offset + data_size as u64
+                Ok(())
             };
-            samples[i].data_size = next_offset.saturating_sub(samples[i].data_offset);
+            samples.push(sample);
+            match parsed {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    truncated = true;
+                    break;
+                }
+                parsed => parsed?,
+            }
+        }
+        if truncated {
+            if samples.is_empty() {
+                return Err(FsbError::Truncated {
+                    expected: data_offset,
+                    actual: data.len() as u64,
+                }
+                .into());
+            }
+            tracing::warn!(
+                "FSB5 sample headers end early, keeping {} of {} samples",
+                samples.len(),
+                sample_count
+            );
+        }
+
+        // The offset field is 28 bits of 16-byte units in every FSB5 revision,
+        // so data past 4 GB wraps around. Deduplicated samples point backwards
+        // too, but only ever at the start of an earlier sample.
+        let mut seen = HashSet::new();
+        let mut furthest = 0;
+        for sample in &samples {
+            if sample.data_offset < furthest && !seen.contains(&sample.data_offset) {
+                return Err(FsbError::OffsetOverflow {
+                    index: sample.index,
+                    offset: sample.data_offset - data_offset,
+                }
+                .into());
+            }
+            seen.insert(sample.data_offset);
+            furthest = furthest.max(sample.data_offset);
+        }
+
+        // Deduplicated banks point several samples at the same offset, so sizes
+        // run to the next distinct offset rather than the next sample
+        let mut starts: Vec<u64> = samples.iter().map(|s| s.data_offset).collect();
+        starts.sort_unstable();
+        starts.dedup();
+        for sample in &mut samples {
+            let next_offset = starts
+                .iter()
+                .find(|&&o| o > sample.data_offset)
+                .copied()
+                .unwrap_or(data_end)
+                .min(data_end);
+            sample.data_size = next_offset.saturating_sub(sample.data_offset);
         }
 
+        let aligned = |align: u64| {
+            (data_size as u64).is_multiple_of(align)
+                && starts
+                    .iter()
+                    .all(|o| (o - data_offset).is_multiple_of(align))
+        };
+        let data_alignment = if aligned(DEFAULT_DATA_ALIGNMENT) {
+            DEFAULT_DATA_ALIGNMENT
+        } else {
+            FSB5_OFFSET_QUANTUM
+        };
+
         if name_table_size > 0 {
-            let name_table_offset = FSB5_HEADER_SIZE as u64 + sample_headers_size as u64;
-            cursor.seek(SeekFrom::Start(name_table_offset))?;
-            let mut offsets = Vec::with_capacity(samples.len());
-            for _ in 0..samples.len() {
-                offsets.push(cursor.read_u32::<LittleEndian>()?);
-            }
-            for (i, &offset) in offsets.iter().enumerate() {
-                cursor.seek(SeekFrom::Start(name_table_offset + offset as u64))?;
-                let mut name_bytes = Vec::new();
-                loop {
-                    let b = cursor.read_u8()?;
-                    if b == 0 {
-                        break;
-                    }
-                    name_bytes.push(b);
+            let start = header_size + sample_headers_size as usize;
+            let end = start + name_table_size as usize;
+            if let Some(table) = data.get(start..end) {
+                let base = name_table_base(table, samples.len());
+                if base != 0 {
+                    tracing::info!(
+                        "FSB5 name offsets are relative to the string region (base {})",
+                        base
+                    );
+                } else {
+                    tracing::debug!("FSB5 name offsets are relative to the name table");
                 }
-                if let Ok(name) = String::from_utf8(name_bytes) {
-                    samples[i].name = Some(name);
+                for (i, sample) in samples.iter_mut().enumerate() {
+                    let name = table
+                        .get(i * 4..i * 4 + 4)
+                        .and_then(|b| name_at(table, base, u32::from_le_bytes(b.try_into().ok()?)))
+                        .and_then(|b| std::str::from_utf8(b).ok());
+                    // Unnamed samples in a partly named bank have empty strings
+                    if let Some(name) = name.filter(|n| !n.is_empty()) {
+                        sample.name = Some(name.to_string());
+                    }
                 }
             }
         }
@@ -410,39 +1120,319 @@ impl FsbBank {
             samples,
             encryption,
             data,
-            header_size: FSB5_HEADER_SIZE,
+            header_size,
             sample_headers_size,
             name_table_size,
             data_size,
             flags,
             fsb5_mode,
+            codec_source,
+            data_alignment,
+            fsb5_header_version: header_version,
+            fsb5_v0_extra: v0_extra,
+            header_tail,
+            truncated,
         })
     }
 
-    pub fn sample_data(&self, index: usize) -> io::Result<&[u8]> {
-        let sample = self
-            .samples
-            .get(index)
-            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Sample not found"))?;
+    // The whole file as loaded, after decryption
+    pub fn decrypted_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    // Encrypts the loaded bytes the way they were on disk, so tools can diff
+    // an original file against a rebuilt one in memory. Edits made since
+    // loading are not included; use save for those.
+    pub fn reencrypt(&self) -> Vec<u8> {
+        let mut output = self.data.clone();
+        match self.version {
+            Version::Fsb5 => {
+                let data_offset = (self.header_size
+                    + self.sample_headers_size as usize
+                    + self.name_table_size as usize)
+                    .min(output.len());
+                let data_end = (data_offset + self.data_size as usize).min(output.len());
+                encrypt_fsb5(&mut output, self.encryption, data_offset..data_end);
+            }
+            Version::Fsb4 if self.encryption == Encryption::Fsbext => {
+                crypto::fsbext_encrypt(&mut output, FSB_KEY);
+            }
+            _ => {}
+        }
+        output
+    }
+
+    pub fn sample_data(&self, index: usize) -> Result<&[u8], FsbError> {
+        let sample = self
+            .samples
+            .get(index)
+            .ok_or(FsbError::SampleNotFound { index })?;
         let start = sample.data_offset as usize;
         let end = start + sample.data_size as usize;
         if end > self.data.len() {
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "Sample data out of bounds",
-            ));
+            return Err(FsbError::SampleOutOfBounds { index });
         }
         Ok(&self.data[start..end])
     }
 
+    pub fn guess_game(&self) -> GameGuess {
+        let format = (self.version, self.codec, self.encryption);
+        let games: Vec<GameProfile> = GameProfile::ALL
+            .into_iter()
+            .filter(|g| g.expects() == format)
+            .collect();
+        match games[..] {
+            [game] => GameGuess::Game(game),
+            [a, b] => GameGuess::Either(a, b),
+            _ => GameGuess::Unknown,
+        }
+    }
+
+    pub fn encryption_scheme(&self) -> &'static str {
+        match self.encryption {
+            Encryption::None => "Plaintext",
+            Encryption::Aes => "AES (header and data)",
+            Encryption::Fsbext => "fsbext (whole file)",
+        }
+    }
+
+    // Checks the bank against what `game` will load. Only the container is
+    // inspected; use self_test for the audio itself.
+    pub fn lint_for_game(&self, game: GameProfile) -> Vec<LintIssue> {
+        let (version, codec, encryption) = game.expects();
+        let mut issues = Vec::new();
+        if self.version != version {
+            issues.push(LintIssue::WrongVersion {
+                game,
+                expected: version,
+                actual: self.version,
+            });
+        }
+        if self.codec != codec {
+            issues.push(LintIssue::WrongCodec {
+                game,
+                expected: codec,
+                actual: self.codec,
+            });
+        }
+        if self.encryption != encryption {
+            issues.push(LintIssue::WrongEncryption {
+                game,
+                expected: encryption,
+                actual: self.encryption,
+            });
+        }
+        if self.samples.is_empty() {
+            issues.push(LintIssue::Empty);
+        }
+        issues
+    }
+
+    // Checks each sample's audio against what its header declares. Vorbis
+    // packets carry no sample rate, so for Vorbis only the channel count
+    // (via the setup header) and the decoded length can be compared.
+    pub fn self_test(&self) -> Vec<SampleIssue> {
+        let mut issues = Vec::new();
+        for sample in &self.samples {
+            let mut report = |kind| {
+                issues.push(SampleIssue {
+                    index: sample.index,
+                    name: sample.display_name(),
+                    kind,
+                })
+            };
+            let Ok(data) = self.sample_data(sample.index) else {
+                report(IssueKind::DataOutOfBounds);
+                continue;
+            };
+            if self.codec != Codec::Vorbis {
+                continue;
+            }
+            let Some(setup) = sample.vorbis_crc.and_then(crate::get_vorbis_setup_header) else {
+                continue;
+            };
+
+            let modes = match crate::audio::parse_setup_modes(&setup, sample.channels) {
+                Some(modes) => modes,
+                None => {
+                    if let Some(actual) =
+                        (1..=8).find(|&c| crate::audio::parse_setup_modes(&setup, c).is_some())
+                    {
+                        report(IssueKind::ChannelMismatch {
+                            declared: sample.channels,
+                            actual,
+                        });
+                    }
+                    continue;
+                }
+            };
+
+            // The last packet is trimmed to the real length, by at most one long block
+            match crate::audio::framed_sample_count(data, &modes) {
+                None => report(IssueKind::BadAudioPacket),
+                Some(framed) if sample.samples > framed || framed - sample.samples > 2048 => {
+                    report(IssueKind::LengthMismatch {
+                        declared: sample.samples,
+                        actual: framed,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        issues
+    }
+
+    pub fn diff(&self, other: &FsbBank) -> Vec<SampleDiff> {
+        let mut diffs = Vec::new();
+        let count = self.samples.len().max(other.samples.len());
+        for i in 0..count {
+            match (self.samples.get(i), other.samples.get(i)) {
+                (Some(a), Some(b)) => {
+                    let mut fields = Vec::new();
+                    if a.name != b.name {
+                        fields.push("name");
+                    }
+                    if a.frequency != b.frequency {
+                        fields.push("frequency");
+                    }
+                    if a.channels != b.channels {
+                        fields.push("channels");
+                    }
+                    if a.samples != b.samples {
+                        fields.push("length");
+                    }
+                    if (a.loop_start, a.loop_end) != (b.loop_start, b.loop_end) {
+                        fields.push("loop");
+                    }
+                    if a.vorbis_crc != b.vorbis_crc {
+                        fields.push("vorbis_crc");
+                    }
+                    if self.sample_data(i).ok() != other.sample_data(i).ok() {
+                        fields.push("data");
+                    }
+                    if !fields.is_empty() {
+                        diffs.push(SampleDiff {
+                            index: i,
+                            name: b.display_name(),
+                            kind: DiffKind::Changed(fields),
+                        });
+                    }
+                }
+                (Some(a), None) => diffs.push(SampleDiff {
+                    index: i,
+                    name: a.display_name(),
+                    kind: DiffKind::Removed,
+                }),
+                (None, Some(b)) => diffs.push(SampleDiff {
+                    index: i,
+                    name: b.display_name(),
+                    kind: DiffKind::Added,
+                }),
+                (None, None) => {}
+            }
+        }
+        diffs
+    }
+
     pub fn save<P: AsRef<Path>>(&self, path: P, encrypt: bool) -> io::Result<()> {
         match self.version {
-            Version::Fsb4 => self.save_fsb4(path),
-            Version::Fsb5 => self.save_fsb5(path, encrypt),
+            Version::Fsb3 => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Saving FSB3 banks is not supported",
+            )),
+            Version::Fsb4 if encrypt && self.encryption == Encryption::Fsbext => {
+                self.save_fsb4(path, Encryption::Fsbext)
+            }
+            Version::Fsb4 => self.save_fsb4(path, Encryption::None),
+            Version::Fsb5 if !encrypt => self.save_fsb5(path, Encryption::None),
+            Version::Fsb5 => match self.encryption {
+                Encryption::None | Encryption::Aes => self.save_fsb5(path, Encryption::Aes),
+                Encryption::Fsbext => self.save_fsb5(path, Encryption::Fsbext),
+            },
+        }
+    }
+
+    // Saves to a temporary file next to `path`, reloads it and checks every
+    // sample against this bank before it replaces `path`. A failed check
+    // leaves `path` as it was.
+    pub fn save_verified<P: AsRef<Path>>(&self, path: P, encrypt: bool) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".verify");
+        let temp = path.with_file_name(temp_name);
+        match self
+            .save(&temp, encrypt)
+            .and_then(|()| self.verify_saved(&temp))
+        {
+            Ok(()) => std::fs::rename(&temp, path),
+            Err(err) => {
+                let _ = std::fs::remove_file(&temp);
+                Err(err)
+            }
+        }
+    }
+
+    fn verify_saved(&self, path: &Path) -> io::Result<()> {
+        let saved = FsbBank::load(path)?;
+        if saved.samples.len() != self.samples.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Saved bank has {} samples, expected {}",
+                    saved.samples.len(),
+                    self.samples.len()
+                ),
+            ));
+        }
+        // FSB5 sizes come back padded to the sample alignment
+        let padding = match self.version {
+            Version::Fsb5 => self.fsb5_sample_alignment() - 1,
+            _ => 0,
+        };
+        for (ours, theirs) in self.samples.iter().zip(&saved.samples) {
+            let size_ok =
+                theirs.data_size >= ours.data_size && theirs.data_size - ours.data_size <= padding;
+            let mismatch = if !size_ok {
+                Some(("data size", ours.data_size, theirs.data_size))
+            } else if theirs.frequency != ours.frequency {
+                Some(("frequency", ours.frequency as u64, theirs.frequency as u64))
+            } else if theirs.channels != ours.channels {
+                Some(("channels", ours.channels as u64, theirs.channels as u64))
+            } else {
+                None
+            };
+            if let Some((field, expected, got)) = mismatch {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Saved sample {} ({}) has {} {}, expected {}",
+                        ours.index,
+                        ours.display_name(),
+                        field,
+                        got,
+                        expected
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Writes the bank with a different encryption than it was loaded with,
+    // e.g. to re-pack a plaintext bank the game expects encrypted
+    pub fn save_as<P: AsRef<Path>>(&self, path: P, encryption: Encryption) -> io::Result<()> {
+        match self.version {
+            Version::Fsb4 if encryption == Encryption::Aes => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "AES encryption is not supported for FSB4",
+            )),
+            Version::Fsb3 => self.save(path, false),
+            Version::Fsb4 => self.save_fsb4(path, encryption),
+            Version::Fsb5 => self.save_fsb5(path, encryption),
         }
     }
 
-    fn save_fsb4<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+    fn save_fsb4<P: AsRef<Path>>(&self, path: P, encryption: Encryption) -> io::Result<()> {
         let mut output = Vec::new();
         output.extend_from_slice(FSB4_MAGIC);
         output.write_u32::<LittleEndian>(self.samples.len() as u32)?;
@@ -503,39 +1493,157 @@ impl FsbBank {
             }
         }
 
+        if encryption == Encryption::Fsbext {
+            crypto::fsbext_encrypt(&mut output, FSB_KEY);
+        }
         let mut file = File::create(path)?;
         file.write_all(&output)
     }
 
-    fn save_fsb5<P: AsRef<Path>>(&self, path: P, encrypt: bool) -> io::Result<()> {
-        let mut output = Vec::new();
-        let mut audio_data = Vec::new();
-        let mut sample_data_offsets = Vec::new();
+    // (index, data_offset, data_size) for every sample, offsets from the start
+    // of the file as currently loaded
+    pub fn layout_table(&self) -> Vec<(usize, u64, u64)> {
+        self.samples
+            .iter()
+            .map(|s| (s.index, s.data_offset, s.data_size))
+            .collect()
+    }
+
+    // The same table for the file `save` would write now. Samples whose data
+    // is out of bounds are dropped by save, so they come out with size 0.
+    pub fn planned_layout_table(&self) -> Vec<(usize, u64, u64)> {
+        let in_bounds = |s: &Sample| (s.data_offset + s.data_size) as usize <= self.data.len();
+        match self.version {
+            // Never rewritten, so the layout is the one on disk
+            Version::Fsb3 => self.layout_table(),
+            Version::Fsb4 => {
+                let mut offset = 48 + self.samples.len() as u64 * 80;
+                self.samples
+                    .iter()
+                    .map(|s| {
+                        let size = if in_bounds(s) { s.data_size } else { 0 };
+                        offset += size;
+                        (s.index, offset - size, size)
+                    })
+                    .collect()
+            }
+            Version::Fsb5 => {
+                let (offsets, _) = self.fsb5_audio_layout();
+                let data_start = (fsb5_header_size(self.fsb5_header_version)
+                    + self.fsb5_sample_headers(&offsets).len()
+                    + self.fsb5_name_table().len()) as u64;
+                self.samples
+                    .iter()
+                    .zip(offsets)
+                    .map(|(s, offset)| {
+                        let size = if in_bounds(s) { s.data_size } else { 0 };
+                        (s.index, data_start + offset, size)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    // Encryption works in place and never changes the size
+    pub fn predicted_save_size(&self) -> usize {
+        match self.version {
+            Version::Fsb3 => self.data.len(),
+            Version::Fsb4 => {
+                let data_size: usize = self
+                    .samples
+                    .iter()
+                    .filter(|s| (s.data_offset + s.data_size) as usize <= self.data.len())
+                    .map(|s| s.data_size as usize)
+                    .sum();
+                48 + self.samples.len() * 80 + data_size
+            }
+            Version::Fsb5 => {
+                let (offsets, audio_size) = self.fsb5_audio_layout();
+                fsb5_header_size(self.fsb5_header_version)
+                    + self.fsb5_sample_headers(&offsets).len()
+                    + self.fsb5_name_table().len()
+                    + audio_size
+            }
+        }
+    }
 
+    fn fsb5_sample_alignment(&self) -> u64 {
+        self.data_alignment
+            .max(FSB5_OFFSET_QUANTUM)
+            .next_multiple_of(FSB5_OFFSET_QUANTUM)
+    }
+
+    // Aliased samples share one copy of their data, so each distinct range
+    // is placed once and later samples pointing at it reuse its offset
+    fn fsb5_audio_layout(&self) -> (Vec<u64>, usize) {
+        let align = self.fsb5_sample_alignment() as usize;
+        let mut offsets = Vec::with_capacity(self.samples.len());
+        let mut placed: HashMap<(u64, u64), u64> = HashMap::new();
+        let mut size = 0usize;
         for sample in &self.samples {
-            while audio_data.len() % 32 != 0 {
-                audio_data.push(0);
+            let range = (sample.data_offset, sample.data_size);
+            if let Some(&offset) = placed.get(&range) {
+                offsets.push(offset);
+                continue;
             }
-            sample_data_offsets.push(audio_data.len() as u64);
-            let start = sample.data_offset as usize;
-            let end = start + sample.data_size as usize;
+            size = size.next_multiple_of(align);
+            offsets.push(size as u64);
+            let end = (sample.data_offset + sample.data_size) as usize;
             if end <= self.data.len() {
-                audio_data.extend_from_slice(&self.data[start..end]);
+                placed.insert(range, size as u64);
+                size += sample.data_size as usize;
             }
         }
-        while audio_data.len() % 32 != 0 {
-            audio_data.push(0);
-        }
+        (offsets, size.next_multiple_of(align))
+    }
 
+    fn fsb5_sample_headers(&self, data_offsets: &[u64]) -> Vec<u8> {
         let mut sample_headers = Vec::new();
         for (i, sample) in self.samples.iter().enumerate() {
-            let data_offset = sample_data_offsets[i] / 16;
-            let has_chunks = sample.vorbis_crc.is_some() || sample.loop_start.is_some();
+            let data_offset = data_offsets[i] / 16;
+            let mut chunks: Vec<(u32, Vec<u8>)> = Vec::new();
+            if let Some(start) = sample.loop_start {
+                let end = sample.loop_end.unwrap_or(sample.samples as u32);
+                let mut chunk = start.to_le_bytes().to_vec();
+                chunk.extend_from_slice(&end.to_le_bytes());
+                chunks.push((3, chunk));
+            }
+            // Only the coefficients are kept; the rest of each DSP header
+            // (gain, initial and loop context) is written as zero
+            if let Some(coeffs) = &sample.dsp_coeffs {
+                let mut chunk = Vec::with_capacity(coeffs.len() * crate::audio::DSP_HEADER_SIZE);
+                for table in coeffs {
+                    let start = chunk.len();
+                    chunk.extend(table.iter().flat_map(|c| c.to_be_bytes()));
+                    chunk.resize(start + crate::audio::DSP_HEADER_SIZE, 0);
+                }
+                chunks.push((7, chunk));
+            }
+            // The config word goes last; the bytes before it are kept from
+            // the loaded chunk
+            if let Some(config) = sample.at9_config {
+                let mut chunk = sample
+                    .chunks
+                    .iter()
+                    .find(|(kind, body)| *kind == 9 && body.len() >= 4)
+                    .map_or_else(|| vec![0; 4], |(_, body)| body.clone());
+                let len = chunk.len();
+                chunk[len - 4..].copy_from_slice(&config);
+                chunks.push((9, chunk));
+            }
+            if let Some(crc) = sample.vorbis_crc {
+                let mut chunk = crc.to_le_bytes().to_vec();
+                if let Some(table) = &sample.vorbis_seek_table {
+                    chunk.extend(table.iter().flat_map(|e| e.to_le_bytes()));
+                }
+                chunks.push((11, chunk));
+            }
+
             let freq_index = frequency_to_index(sample.frequency);
             let channels_bit = if sample.channels > 1 { 1u64 } else { 0u64 };
 
             let mut mode: u64 = 0;
-            if has_chunks {
+            if !chunks.is_empty() {
                 mode |= 1;
             }
             mode |= (freq_index as u64 & 0xF) << 1;
@@ -544,81 +1652,107 @@ impl FsbBank {
             mode |= (sample.samples & 0x3FFFFFFF) << 34;
             sample_headers.extend_from_slice(&mode.to_le_bytes());
 
-            if has_chunks {
-                let has_vorbis = sample.vorbis_crc.is_some();
+            let last = chunks.len().saturating_sub(1);
+            for (n, (kind, chunk)) in chunks.iter().enumerate() {
+                let more = (n != last) as u32;
+                let chunk_header: u32 =
+                    more | ((chunk.len() as u32 & 0xFFFFFF) << 1) | (kind << 25);
+                sample_headers.extend_from_slice(&chunk_header.to_le_bytes());
+                sample_headers.extend_from_slice(chunk);
+            }
+        }
+        sample_headers
+    }
+
+    fn fsb5_name_table(&self) -> Vec<u8> {
+        if self.name_table_size == 0 && self.samples.iter().all(|s| s.name.is_none()) {
+            return Vec::new();
+        }
+        let names: Vec<&str> = self
+            .samples
+            .iter()
+            .map(|s| s.name.as_deref().unwrap_or(""))
+            .collect();
+
+        let start = self.header_size + self.sample_headers_size as usize;
+        let end = start + self.name_table_size as usize;
+        // The original table only fits if no samples were added or removed
+        let count = (names.len() as u32).to_le_bytes();
+        if let (Some(original), Some(true)) = (
+            self.data.get(start..end),
+            self.data.get(8..12).map(|c| c == count),
+        ) {
+            if read_name_table(original, names.len()).as_deref() == Some(&names[..]) {
+                return original.to_vec();
+            }
+        }
+        build_name_table(&names)
+    }
 
-                if let (Some(start), Some(end)) = (sample.loop_start, sample.loop_end) {
-                    let chunk_header: u32 =
-                        (if has_vorbis { 1 } else { 0 }) | (8u32 << 1) | (3u32 << 25);
-                    sample_headers.extend_from_slice(&chunk_header.to_le_bytes());
-                    sample_headers.extend_from_slice(&start.to_le_bytes());
-                    sample_headers.extend_from_slice(&end.to_le_bytes());
-                }
+    fn save_fsb5<P: AsRef<Path>>(&self, path: P, encryption: Encryption) -> io::Result<()> {
+        let mut output = Vec::new();
+        let (sample_data_offsets, audio_size) = self.fsb5_audio_layout();
+        let mut audio_data = Vec::with_capacity(audio_size);
 
-                if let Some(crc) = sample.vorbis_crc {
-                    let seek_table = sample.vorbis_seek_table.as_ref();
-                    let chunk_data_size = 4 + seek_table.map(|t| t.len() * 4).unwrap_or(0);
-                    let chunk_header: u32 =
-                        ((chunk_data_size as u32 & 0xFFFFFF) << 1) | (11u32 << 25);
-                    sample_headers.extend_from_slice(&chunk_header.to_le_bytes());
-                    sample_headers.extend_from_slice(&crc.to_le_bytes());
-                    if let Some(table) = seek_table {
-                        for entry in table {
-                            sample_headers.extend_from_slice(&entry.to_le_bytes());
-                        }
-                    }
-                }
+        for (i, sample) in self.samples.iter().enumerate() {
+            // Already written for an earlier alias
+            if (sample_data_offsets[i] as usize) < audio_data.len() {
+                continue;
+            }
+            audio_data.resize(sample_data_offsets[i] as usize, 0);
+            let start = sample.data_offset as usize;
+            let end = start + sample.data_size as usize;
+            if end <= self.data.len() {
+                audio_data.extend_from_slice(&self.data[start..end]);
             }
         }
+        audio_data.resize(audio_size, 0);
 
-        let name_table_start = self.header_size + self.sample_headers_size as usize;
-        let name_table_end = name_table_start + self.name_table_size as usize;
-        let name_table = if name_table_end <= self.data.len() {
-            self.data[name_table_start..name_table_end].to_vec()
-        } else {
-            Vec::new()
-        };
+        let sample_headers = self.fsb5_sample_headers(&sample_data_offsets);
 
+        let name_table = self.fsb5_name_table();
         let new_sample_headers_size = sample_headers.len() as u32;
         let new_data_size = audio_data.len() as u32;
 
+        let header_size = fsb5_header_size(self.fsb5_header_version);
         output.extend_from_slice(FSB5_MAGIC);
-        output.write_u32::<LittleEndian>(1)?;
+        output.write_u32::<LittleEndian>(self.fsb5_header_version)?;
         output.write_u32::<LittleEndian>(self.samples.len() as u32)?;
         output.write_u32::<LittleEndian>(new_sample_headers_size)?;
-        output.write_u32::<LittleEndian>(self.name_table_size)?;
+        output.write_u32::<LittleEndian>(name_table.len() as u32)?;
         output.write_u32::<LittleEndian>(new_data_size)?;
-        output.write_u32::<LittleEndian>(self.codec as u32)?;
-        output.write_u32::<LittleEndian>(self.fsb5_mode)?;
-        output.write_u32::<LittleEndian>(self.flags)?;
-
-        if self.data.len() >= 60 {
-            output.extend_from_slice(&self.data[36..60]);
-        } else {
-            output.extend_from_slice(&[0u8; 24]);
+        let (codec_field, mut fsb5_mode) = match self.codec_source {
+            CodecSource::Field => (self.codec as u32, self.fsb5_mode),
+            CodecSource::Mode => (0, Fsb5Mode(self.fsb5_mode.0 & !0xFF | self.codec as u32)),
+        };
+        output.write_u32::<LittleEndian>(codec_field)?;
+        if self.fsb5_header_version == 0 {
+            output.write_u32::<LittleEndian>(self.fsb5_v0_extra)?;
+        }
+        // Basic headers can't describe chunks, so drop the bit once any are
+        // written. A mode holding the codec has no such bit.
+        if self.codec_source == CodecSource::Field
+            && self
+                .samples
+                .iter()
+                .any(|s| s.vorbis_crc.is_some() || s.loop_start.is_some())
+        {
+            fsb5_mode.0 &= !Fsb5Mode::BASIC_HEADERS;
         }
+        output.write_u32::<LittleEndian>(fsb5_mode.0)?;
+        output.write_u32::<LittleEndian>(self.flags)?;
+        output.extend_from_slice(&self.header_tail);
 
         output.extend_from_slice(&sample_headers);
         output.extend_from_slice(&name_table);
         output.extend_from_slice(&audio_data);
 
-        if encrypt {
-            match self.encryption {
-                Encryption::None | Encryption::Aes => {
-                    crypto::encrypt_aes_block(&mut output[0..32], FSB_KEY);
-                    let data_offset = FSB5_HEADER_SIZE
-                        + new_sample_headers_size as usize
-                        + self.name_table_size as usize;
-                    let data_end = data_offset + new_data_size as usize;
-                    if data_end <= output.len() {
-                        crypto::encrypt_aes_data(&mut output[data_offset..data_end], FSB_KEY);
-                    }
-                }
-                Encryption::Fsbext => {
-                    crypto::fsbext_encrypt(&mut output, FSB_KEY);
-                }
-            }
-        }
+        let data_offset = header_size + new_sample_headers_size as usize + name_table.len();
+        encrypt_fsb5(
+            &mut output,
+            encryption,
+            data_offset..data_offset + new_data_size as usize,
+        );
 
         let mut file = File::create(path)?;
         file.write_all(&output)
@@ -631,7 +1765,7 @@ impl FsbBank {
         let sample = self
             .samples
             .get(index)
-            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Sample not found"))?;
+            .ok_or(FsbError::SampleNotFound { index })?;
         crate::audio::mp3::extract_mp3_from_fsb4(
             self.sample_data(index)?,
             sample.frequency,
@@ -639,30 +1773,502 @@ impl FsbBank {
         )
     }
 
+    // Cuts [start_secs, end_secs) out of a sample without re-encoding. Vorbis
+    // is cut at packet boundaries and rebuilt as an OGG starting at granule
+    // 0; MP3 keeps the whole frames that overlap the range.
+    pub fn extract_time_range(
+        &self,
+        index: usize,
+        start_secs: f32,
+        end_secs: f32,
+    ) -> io::Result<Vec<u8>> {
+        self.extract_time_range_with_options(
+            index,
+            start_secs,
+            end_secs,
+            crate::OggRebuildOptions::default(),
+        )
+    }
+
+    // Like extract_time_range, with the OGG page flushing, serial and vendor
+    // for Vorbis cuts taken from `options`
+    pub fn extract_time_range_with_options(
+        &self,
+        index: usize,
+        start_secs: f32,
+        end_secs: f32,
+        options: crate::OggRebuildOptions,
+    ) -> io::Result<Vec<u8>> {
+        let sample = self
+            .samples
+            .get(index)
+            .ok_or(FsbError::SampleNotFound { index })?;
+        if !(start_secs >= 0.0 && start_secs < end_secs) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid range {}s to {}s", start_secs, end_secs),
+            ));
+        }
+        let start = (start_secs as f64 * sample.frequency as f64) as u64;
+        let end = (end_secs as f64 * sample.frequency as f64).ceil() as u64;
+        if sample.samples > 0 && start >= sample.samples {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}s is past the end of the sample", start_secs),
+            ));
+        }
+
+        match self.codec {
+            Codec::Vorbis => {
+                let raw = self.sample_data(index)?;
+                let table = sample.vorbis_seek_table.as_deref().unwrap_or_default();
+                let (bytes, first) = vorbis_packet_range(raw, table, start, end);
+                Ok(crate::rebuild_ogg_packets(
+                    self,
+                    sample,
+                    &raw[bytes],
+                    end.saturating_sub(first),
+                    options,
+                )?)
+            }
+            Codec::Mpeg => {
+                let frames = self.extract_mp3(index)?;
+                Ok(mp3_frame_range(&frames, sample.channels, start, end))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Range extraction isn't supported for {:?}", self.codec),
+            )),
+        }
+    }
+
     pub fn extract_audio(&self, index: usize) -> io::Result<(Vec<u8>, &'static str)> {
+        if let Some(result) = crate::codec::extract_registered(self, index) {
+            return result;
+        }
+        let sample = self
+            .samples
+            .get(index)
+            .ok_or(FsbError::SampleNotFound { index })?;
         match self.codec {
             Codec::Mpeg => Ok((self.extract_mp3(index)?, "mp3")),
             Codec::Vorbis => Ok((self.sample_data(index)?.to_vec(), "vorbis_raw")),
-            Codec::Pcm16 => {
-                let sample = &self.samples[index];
+            Codec::Pcm8 | Codec::Pcm16 | Codec::Pcm24 | Codec::Pcm32 | Codec::PcmFloat => {
                 let raw = self.sample_data(index)?;
+                let (bits, format_tag) = self.codec.wav_format().unwrap_or_default();
                 Ok((
-                    create_wav_header(raw, sample.frequency, sample.channels as u16, 16),
+                    crate::audio::create_wav_header(
+                        raw,
+                        sample.frequency,
+                        sample.channels as u16,
+                        bits,
+                        format_tag,
+                    ),
                     "wav",
                 ))
             }
+            Codec::GcAdpcm if sample.dsp_coeffs.is_some() => {
+                let coeffs = sample.dsp_coeffs.as_deref().unwrap_or_default();
+                let pcm = crate::audio::decode_gcadpcm(
+                    self.sample_data(index)?,
+                    sample.channels,
+                    sample.samples,
+                    coeffs,
+                )
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "GC ADPCM sample has fewer coefficient tables than channels",
+                    )
+                })?;
+                Ok((
+                    crate::audio::create_wav_header(
+                        &pcm,
+                        sample.frequency,
+                        sample.channels as u16,
+                        16,
+                        crate::audio::WAVE_FORMAT_PCM,
+                    ),
+                    "wav",
+                ))
+            }
+            Codec::ImaAdpcm => {
+                let mut pcm =
+                    crate::audio::decode_ima_adpcm(self.sample_data(index)?, sample.channels);
+                if sample.samples > 0 {
+                    pcm.truncate((sample.samples * sample.channels.max(1) as u64) as usize);
+                }
+                let pcm: Vec<u8> = pcm.iter().flat_map(|s| s.to_le_bytes()).collect();
+                Ok((
+                    crate::audio::create_wav_header(
+                        &pcm,
+                        sample.frequency,
+                        sample.channels as u16,
+                        16,
+                        crate::audio::WAVE_FORMAT_PCM,
+                    ),
+                    "wav",
+                ))
+            }
+            Codec::At9 if sample.at9_config.is_some() => Ok((
+                crate::audio::wrap_at9(
+                    self.sample_data(index)?,
+                    sample.at9_config.unwrap_or_default(),
+                    sample.frequency,
+                    sample.channels,
+                    sample.samples,
+                ),
+                "at9",
+            )),
             _ => Ok((self.sample_data(index)?.to_vec(), "bin")),
         }
     }
 
+    pub fn alias_groups(&self) -> Vec<Vec<usize>> {
+        let mut groups: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+        for sample in &self.samples {
+            groups
+                .entry((sample.data_offset, sample.data_size))
+                .or_default()
+                .push(sample.index);
+        }
+        let mut groups: Vec<Vec<usize>> = groups.into_values().filter(|g| g.len() > 1).collect();
+        groups.sort();
+        groups
+    }
+
+    pub fn aliases_of(&self, index: usize) -> Vec<usize> {
+        let Some(target) = self.samples.get(index) else {
+            return Vec::new();
+        };
+        self.samples
+            .iter()
+            .filter(|s| {
+                s.index != index
+                    && s.data_offset == target.data_offset
+                    && s.data_size == target.data_size
+            })
+            .map(|s| s.index)
+            .collect()
+    }
+
+    pub fn link_alias(&mut self, index: usize, source: usize) -> io::Result<()> {
+        let src = self
+            .samples
+            .get(source)
+            .cloned()
+            .ok_or(FsbError::SampleNotFound { index: source })?;
+        let target = self
+            .samples
+            .get_mut(index)
+            .ok_or(FsbError::SampleNotFound { index })?;
+        target.data_offset = src.data_offset;
+        target.data_size = src.data_size;
+        target.frequency = src.frequency;
+        target.channels = src.channels;
+        target.samples = src.samples;
+        target.vorbis_crc = src.vorbis_crc;
+        target.vorbis_seek_table = src.vorbis_seek_table;
+        Ok(())
+    }
+
+    // Swaps the audio two slots point at, along with everything describing
+    // that audio. Slot positions stay put; names only move with include_names.
+    pub fn swap_samples(&mut self, a: usize, b: usize, include_names: bool) -> io::Result<()> {
+        if let Some(index) = [a, b].into_iter().find(|&i| i >= self.samples.len()) {
+            return Err(FsbError::SampleNotFound { index }.into());
+        }
+        if a == b {
+            return Ok(());
+        }
+        self.samples.swap(a, b);
+        self.samples[a].index = a;
+        self.samples[b].index = b;
+        if !include_names {
+            let name = self.samples[a].name.take();
+            self.samples[a].name = std::mem::replace(&mut self.samples[b].name, name);
+        }
+        Ok(())
+    }
+
+    // An empty name clears it. Saving builds a name table when any sample
+    // has a name, so this also labels banks that were saved without one.
+    pub fn set_sample_name(&mut self, index: usize, name: String) -> io::Result<()> {
+        let sample = self
+            .samples
+            .get_mut(index)
+            .ok_or(FsbError::SampleNotFound { index })?;
+        sample.name = (!name.is_empty()).then_some(name);
+        Ok(())
+    }
+
+    // Drops a sample and closes the gap in the data. Bytes an alias still
+    // uses are kept; the FSB5 name table is rebuilt from the remaining names
+    // on save.
+    pub fn remove_sample(&mut self, index: usize) -> io::Result<()> {
+        if index >= self.samples.len() {
+            return Err(FsbError::SampleNotFound { index }.into());
+        }
+        let aliased = !self.aliases_of(index).is_empty();
+        let removed = self.samples.remove(index);
+        let (start, size) = (removed.data_offset, removed.data_size);
+        if !aliased && start + size <= self.data.len() as u64 {
+            self.data.drain(start as usize..(start + size) as usize);
+            for sample in &mut self.samples {
+                if sample.data_offset > start {
+                    sample.data_offset = sample.data_offset.saturating_sub(size);
+                }
+            }
+            self.data_size = self.data_size.saturating_sub(size as u32);
+        }
+        for (i, sample) in self.samples.iter_mut().enumerate() {
+            sample.index = i;
+        }
+        Ok(())
+    }
+
+    // Indices of the samples sharing one Vorbis setup header
+    pub fn samples_with_crc(&self, crc: u32) -> Vec<usize> {
+        self.samples
+            .iter()
+            .filter(|s| s.vorbis_crc == Some(crc))
+            .map(|s| s.index)
+            .collect()
+    }
+
+    pub fn crc_coverage(&self) -> (usize, usize) {
+        if self.codec != Codec::Vorbis {
+            return (0, 0);
+        }
+        let known = self
+            .samples
+            .iter()
+            .filter(|s| s.vorbis_crc.is_some_and(crate::has_vorbis_setup_header))
+            .count();
+        (known, self.samples.len())
+    }
+
+    pub fn export_sample(&self, index: usize) -> io::Result<(Vec<u8>, &'static str)> {
+        if self.samples.get(index).is_some_and(Sample::is_empty) {
+            return Err(FsbError::EmptySample { index }.into());
+        }
+        match self.codec {
+            Codec::Vorbis if !crate::has_codec_handler(Codec::Vorbis) => {
+                let sample = self
+                    .samples
+                    .get(index)
+                    .ok_or(FsbError::SampleNotFound { index })?;
+                Ok((crate::rebuild_ogg(self, sample)?, "ogg"))
+            }
+            _ => self.extract_audio(index),
+        }
+    }
+
+    // Every sample's OGG chained into one stream, plus a cue sheet naming
+    // `file_name` with a track per sample. Cue times assume each sample plays
+    // at its own rate; banks with mixed rates note each track's rate. Empty
+    // samples get no track and are only counted, in the third value.
+    pub fn export_concatenated_ogg(&self, file_name: &str) -> io::Result<(Vec<u8>, String, usize)> {
+        let samples: Vec<&Sample> = self.samples.iter().filter(|s| !s.is_empty()).collect();
+        let skipped = self.samples.len() - samples.len();
+        let mixed_rates = samples.windows(2).any(|w| w[0].frequency != w[1].frequency);
+        let mut ogg = Vec::new();
+        let mut cue = String::from("REM COMMENT \"CUMS\"\n");
+        if mixed_rates {
+            cue += "REM COMMENT \"Samples use different rates; see each track\"\n";
+        }
+        if skipped > 0 {
+            cue += &format!("REM COMMENT \"{} empty sample(s) left out\"\n", skipped);
+        }
+        cue += &format!("FILE \"{}\" OGG\n", file_name.replace('"', "'"));
+
+        let mut elapsed = 0.0f64;
+        for (i, sample) in samples.into_iter().enumerate() {
+            let options = crate::OggRebuildOptions {
+                serial: crate::OggRebuildOptions::default()
+                    .serial
+                    .wrapping_add(i as u32),
+                ..Default::default()
+            };
+            ogg.extend(crate::rebuild_ogg_with_options(self, sample, options)?);
+
+            // Cue positions are in frames of 1/75 s
+            let frames = (elapsed * 75.0).round() as u64;
+            cue += &format!("  TRACK {:02} AUDIO\n", i + 1);
+            cue += &format!(
+                "    TITLE \"{}\"\n",
+                sample.display_name().replace('"', "'")
+            );
+            if mixed_rates {
+                cue += &format!("    REM COMMENT \"{} Hz\"\n", sample.frequency);
+            }
+            cue += &format!(
+                "    INDEX 01 {:02}:{:02}:{:02}\n",
+                frames / 75 / 60,
+                frames / 75 % 60,
+                frames % 75
+            );
+            if sample.frequency > 0 {
+                elapsed += sample.samples as f64 / sample.frequency as f64;
+            }
+        }
+        Ok((ogg, cue, skipped))
+    }
+
+    // Exports every sample as export_sample would, in parallel when built
+    // with the `rayon` feature. Results stay in sample order, each paired
+    // with the sample's display name so failures can be reported by name.
+    // Empty samples are left out and only counted, in the second value.
+    pub fn extract_all_parallel(&self) -> (Vec<io::Result<ExportedSample>>, usize) {
+        let export = |sample: &Sample| {
+            let (data, ext) = self.export_sample(sample.index)?;
+            Ok((sample.display_name(), data, ext))
+        };
+        let skipped = self.samples.iter().filter(|s| s.is_empty()).count();
+        #[cfg(feature = "rayon")]
+        let results = {
+            use rayon::prelude::*;
+            self.samples
+                .par_iter()
+                .filter(|s| !s.is_empty())
+                .map(export)
+                .collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let results = self
+            .samples
+            .iter()
+            .filter(|s| !s.is_empty())
+            .map(export)
+            .collect();
+        (results, skipped)
+    }
+
+    // Encodes `audio_path` and adds it as a new last sample; see
+    // crate::append_sample for how the format is chosen
+    pub fn append_sample<P: AsRef<Path>>(
+        &mut self,
+        audio_path: P,
+        fsbankcl_path: P,
+        temp_dir: P,
+        name: Option<String>,
+        settings: &crate::AudioSettings,
+    ) -> io::Result<usize> {
+        crate::append_sample(
+            self,
+            audio_path.as_ref(),
+            fsbankcl_path.as_ref(),
+            temp_dir.as_ref(),
+            name,
+            settings,
+        )
+    }
+
+    // Replaces several samples, batching encoder runs; see
+    // crate::replace_samples. Returns (quality, fits) per edit.
+    pub fn replace_samples<P: AsRef<Path>>(
+        &mut self,
+        edits: &[(usize, std::path::PathBuf, crate::AudioSettings)],
+        fsbankcl_path: P,
+        temp_dir: P,
+    ) -> io::Result<Vec<(u32, bool)>> {
+        Ok(crate::replace_samples(
+            self,
+            edits,
+            fsbankcl_path.as_ref(),
+            temp_dir.as_ref(),
+        )?)
+    }
+
+    // Adds already-encoded `data` as a new last sample and returns its index.
+    // The offset, size and index of `sample` are filled in here.
+    pub fn push_sample(&mut self, data: &[u8], mut sample: Sample) -> usize {
+        sample.index = self.samples.len();
+        sample.data_offset = self.data.len() as u64;
+        sample.data_size = data.len() as u64;
+        self.data.extend_from_slice(data);
+        self.data_size += data.len() as u32;
+        self.samples.push(sample);
+        self.samples.len() - 1
+    }
+
+    // Pull-based alternative to rebuild_ogg for long streams: pages are muxed
+    // as the reader asks for them instead of building the whole file up front
+    pub fn stream_ogg(&self, index: usize) -> io::Result<crate::OggStream<'_>> {
+        let sample = self
+            .samples
+            .get(index)
+            .ok_or(FsbError::SampleNotFound { index })?;
+        Ok(crate::open_ogg_stream(
+            self,
+            sample,
+            &[],
+            crate::OggRebuildOptions::default(),
+        )?)
+    }
+
+    pub fn extract_range<P: AsRef<Path>>(
+        &self,
+        range: Range<usize>,
+        dir: P,
+    ) -> io::Result<(usize, usize)> {
+        self.extract_range_with_template(range, dir, crate::DEFAULT_NAME_TEMPLATE)
+    }
+
+    pub fn extract_range_with_template<P: AsRef<Path>>(
+        &self,
+        range: Range<usize>,
+        dir: P,
+        template: &str,
+    ) -> io::Result<(usize, usize)> {
+        crate::validate_name_template(template)?;
+        let requested = range.len();
+        let end = range.end.min(self.samples.len());
+        let start = range.start.min(end);
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let mut extracted = 0;
+        for sample in &self.samples[start..end] {
+            if let Ok((data, ext)) = self.export_sample(sample.index) {
+                let path = dir.join(crate::export_file_name(template, sample, ext)?);
+                if std::fs::write(path, data).is_ok() {
+                    extracted += 1;
+                }
+            }
+        }
+        Ok((extracted, requested))
+    }
+
     pub fn replace_sample<P: AsRef<Path>>(
         &mut self,
         index: usize,
         audio_path: P,
         temp_dir: P,
+    ) -> io::Result<()> {
+        self.replace_sample_with_settings(
+            index,
+            audio_path,
+            temp_dir,
+            &crate::AudioSettings::default(),
+        )
+    }
+
+    // FSB4 replacements are converted by ffmpeg, which also applies the
+    // volume, pitch, speed and fades of `settings`
+    pub fn replace_sample_with_settings<P: AsRef<Path>>(
+        &mut self,
+        index: usize,
+        audio_path: P,
+        temp_dir: P,
+        settings: &crate::AudioSettings,
     ) -> io::Result<()> {
         match self.version {
-            Version::Fsb4 => self.replace_sample_fsb4(index, audio_path, temp_dir),
+            Version::Fsb3 => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Replacing samples in FSB3 banks is not supported",
+            )),
+            Version::Fsb4 => self.replace_sample_fsb4(index, audio_path, temp_dir, settings),
             Version::Fsb5 => Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "Use replace_sample_fsb5 for FSB5",
@@ -675,43 +2281,356 @@ impl FsbBank {
         index: usize,
         audio_path: P,
         temp_dir: P,
+        settings: &crate::AudioSettings,
     ) -> io::Result<()> {
         if index >= self.samples.len() {
-            return Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                "Sample index out of bounds",
-            ));
+            return Err(FsbError::SampleNotFound { index }.into());
         }
 
         let temp_dir = temp_dir.as_ref();
         std::fs::create_dir_all(temp_dir)?;
-        let new_mp3_data = prepare_mp3_data(audio_path.as_ref(), temp_dir)?;
+        let new_mp3_data = prepare_mp3_data(audio_path.as_ref(), temp_dir, settings)?;
         let mp3_info = crate::audio::mp3::get_mp3_info(&new_mp3_data);
 
-        let old_size = self.samples[index].data_size as usize;
-        let old_offset = self.samples[index].data_offset as usize;
-        let new_size = new_mp3_data.len();
-        let size_diff = new_size as i64 - old_size as i64;
-
-        let mut new_data = Vec::new();
-        new_data.extend_from_slice(&self.data[..old_offset]);
-        new_data.extend_from_slice(&new_mp3_data);
-        new_data.extend_from_slice(&self.data[old_offset + old_size..]);
-
-        self.samples[index].data_size = new_size as u64;
+        self.splice_sample(index, &new_mp3_data);
         if let Some((sample_rate, channels, _)) = mp3_info {
             self.samples[index].frequency = sample_rate;
             self.samples[index].channels = channels;
         }
+        let channels = self.samples[index].channels;
+        let (total, _) = crate::audio::count_mp3_samples(&new_mp3_data, channels);
+        if total > 0 {
+            self.samples[index].samples = total;
+        }
+        Ok(())
+    }
 
-        for i in (index + 1)..self.samples.len() {
-            self.samples[i].data_offset = (self.samples[i].data_offset as i64 + size_diff) as u64;
+    // Replaces a PCM16 sample with the audio of a 16-bit WAV, no encoder needed
+    pub fn replace_sample_pcm(&mut self, index: usize, wav: &[u8]) -> io::Result<()> {
+        if self.codec != Codec::Pcm16 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("WAV replacement needs a PCM16 bank, not {:?}", self.codec),
+            ));
+        }
+        if index >= self.samples.len() {
+            return Err(FsbError::SampleNotFound { index }.into());
+        }
+        let (sample_rate, channels, bits, pcm) = crate::audio::parse_wav(wav)?;
+        if bits != 16 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Only 16-bit WAV can replace PCM16 samples, got {}-bit",
+                    bits
+                ),
+            ));
         }
 
-        self.data_size = (self.data_size as i64 + size_diff) as u32;
-        self.data = new_data;
+        self.splice_sample(index, &pcm);
+        let sample = &mut self.samples[index];
+        sample.frequency = sample_rate;
+        sample.channels = channels as u32;
+        sample.samples = (pcm.len() / (channels as usize * 2)) as u64;
         Ok(())
     }
+
+    // Swaps a sample's bytes in `data` and shifts the samples stored after
+    // it. Aliased bytes are still used by other samples, so for those the
+    // new data is appended and only this sample moves to it.
+    pub(crate) fn splice_sample(&mut self, index: usize, bytes: &[u8]) {
+        let old_size = self.samples[index].data_size as usize;
+        let old_offset = self.samples[index].data_offset as usize;
+
+        if !self.aliases_of(index).is_empty() {
+            self.samples[index].data_offset = self.data.len() as u64;
+            self.data.extend_from_slice(bytes);
+            self.data_size += bytes.len() as u32;
+        } else {
+            let size_diff = bytes.len() as i64 - old_size as i64;
+            let mut new_data = Vec::new();
+            new_data.extend_from_slice(&self.data[..old_offset]);
+            new_data.extend_from_slice(bytes);
+            new_data.extend_from_slice(&self.data[old_offset + old_size..]);
+
+            for s in &mut self.samples {
+                if s.data_offset > old_offset as u64 {
+                    s.data_offset = (s.data_offset as i64 + size_diff) as u64;
+                }
+            }
+            self.data_size = (self.data_size as i64 + size_diff) as u32;
+            self.data = new_data;
+        }
+        self.samples[index].data_size = bytes.len() as u64;
+    }
+}
+
+fn name_at(table: &[u8], base: usize, offset: u32) -> Option<&[u8]> {
+    let rest = table.get(base + offset as usize..)?;
+    let len = rest.iter().position(|&b| b == 0)?;
+    Some(&rest[..len])
+}
+
+// Most banks store offsets from the start of the table, some from the first
+// string after the offset array. Whichever makes the first name readable wins.
+fn name_table_base(table: &[u8], count: usize) -> usize {
+    let printable = |base: usize| {
+        table
+            .get(0..4)
+            .and_then(|b| name_at(table, base, u32::from_le_bytes(b.try_into().ok()?)))
+            .and_then(|b| std::str::from_utf8(b).ok())
+            .is_some_and(|s| !s.is_empty() && !s.chars().any(char::is_control))
+    };
+    let alternate = count * 4;
+    if !printable(0) && printable(alternate) {
+        alternate
+    } else {
+        0
+    }
+}
+
+fn read_name_table(table: &[u8], count: usize) -> Option<Vec<&str>> {
+    let base = name_table_base(table, count);
+    let mut names = Vec::with_capacity(count);
+    for i in 0..count {
+        let offset = u32::from_le_bytes(table.get(i * 4..i * 4 + 4)?.try_into().ok()?);
+        names.push(std::str::from_utf8(name_at(table, base, offset)?).ok()?);
+    }
+    Some(names)
+}
+
+// Identical names share one string, as in FMOD-built banks.
+fn build_name_table(names: &[&str]) -> Vec<u8> {
+    let mut offsets = Vec::with_capacity(names.len());
+    let mut strings = Vec::new();
+    let mut seen: HashMap<&str, u32> = HashMap::new();
+    let base = names.len() as u32 * 4;
+    for &name in names {
+        let offset = *seen.entry(name).or_insert_with(|| {
+            let offset = base + strings.len() as u32;
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+            offset
+        });
+        offsets.push(offset);
+    }
+
+    let mut table = Vec::with_capacity(base as usize + strings.len());
+    for offset in offsets {
+        table.extend_from_slice(&offset.to_le_bytes());
+    }
+    table.extend_from_slice(&strings);
+    table.resize(table.len().next_multiple_of(16), 0);
+    table
+}
+
+fn check_truncated(actual: u64, expected: u64) -> io::Result<()> {
+    if expected > actual {
+        return Err(FsbError::Truncated { expected, actual }.into());
+    }
+    Ok(())
+}
+
+// Older banks often store the byte size or a rough guess as the sample
+// count, so trust the MP3 frames when the two disagree
+fn recount_mp3_samples(samples: &mut [Sample], data: &[u8]) {
+    for sample in samples {
+        let start = sample.data_offset as usize;
+        let end = (start + sample.data_size as usize).min(data.len());
+        let Some(frames) = data.get(start..end) else {
+            continue;
+        };
+        let (counted, _) = crate::audio::count_mp3_samples(frames, sample.channels);
+        let stored = sample.samples;
+        if counted > 0
+            && (stored == 0
+                || stored == sample.data_size
+                || stored.abs_diff(counted) > counted / 10)
+        {
+            sample.samples = counted;
+        }
+    }
+}
+
+// Reads the chunk list after a sample's mode word into `sample`. Chunks read
+// before the data runs out are kept, and the error says UnexpectedEof.
+fn parse_fsb5_chunks(
+    cursor: &mut Cursor<&[u8]>,
+    sample: &mut Sample,
+    headers_end: u64,
+) -> io::Result<()> {
+    let data = *cursor.get_ref();
+    let index = sample.index;
+    loop {
+        if sample.chunks.len() >= MAX_CHUNKS_PER_SAMPLE {
+            return Err(FsbError::TooManyChunks {
+                index,
+                limit: MAX_CHUNKS_PER_SAMPLE,
+            }
+            .into());
+        }
+        if cursor.position() + 4 > headers_end {
+            return Err(FsbError::ChunkOverrun { index }.into());
+        }
+        let chunk_header = cursor.read_u32::<LittleEndian>()?;
+        let more_chunks = (chunk_header & 1) != 0;
+        let chunk_size = ((chunk_header >> 1) & 0xFFFFFF) as usize;
+        let chunk_type = (chunk_header >> 25) & 0x7F;
+        let chunk_start = cursor.position();
+        if chunk_start + chunk_size as u64 > headers_end {
+            return Err(FsbError::ChunkOverrun { index }.into());
+        }
+        let start = chunk_start as usize;
+        let body = data
+            .get(start..start + chunk_size)
+            .ok_or(FsbError::Truncated {
+                expected: headers_end,
+                actual: data.len() as u64,
+            })?;
+        sample.chunks.push((chunk_type as u8, body.to_vec()));
+
+        match chunk_type {
+            3 => {
+                sample.loop_start = Some(cursor.read_u32::<LittleEndian>()?);
+                sample.loop_end = Some(cursor.read_u32::<LittleEndian>()?);
+            }
+            7 => {
+                sample.dsp_coeffs = crate::audio::parse_dsp_coeffs(body, sample.channels);
+            }
+            // Type 2 is the frequency chunk; ATRAC9 keeps its config in
+            // type 9, with the word in the last 4 bytes
+            9 if chunk_size >= 4 => {
+                let config: [u8; 4] = body[chunk_size - 4..].try_into().unwrap();
+                sample.at9_config = (config[0] == 0xFE).then_some(config);
+            }
+            11 => {
+                sample.vorbis_crc = Some(cursor.read_u32::<LittleEndian>()?);
+                let seek_count = chunk_size.saturating_sub(4) / 4;
+                let mut table = Vec::with_capacity(seek_count);
+                for _ in 0..seek_count {
+                    table.push(cursor.read_u32::<LittleEndian>()?);
+                }
+                sample.vorbis_seek_table = Some(table);
+            }
+            _ => {}
+        }
+        cursor.seek(SeekFrom::Start(chunk_start + chunk_size as u64))?;
+        if !more_chunks {
+            return Ok(());
+        }
+    }
+}
+
+fn encrypt_fsb5(output: &mut [u8], encryption: Encryption, data: Range<usize>) {
+    match encryption {
+        Encryption::None => {}
+        Encryption::Aes => {
+            crypto::encrypt_aes_block(&mut output[0..32], FSB_KEY);
+            if data.end <= output.len() {
+                crypto::encrypt_aes_data(&mut output[data], FSB_KEY);
+            }
+        }
+        Encryption::Fsbext => {
+            crypto::fsbext_encrypt(output, FSB_KEY);
+        }
+    }
+}
+
+fn fsb5_header_size(header_version: u32) -> usize {
+    if header_version == 0 {
+        FSB5_V0_HEADER_SIZE
+    } else {
+        FSB5_HEADER_SIZE
+    }
+}
+
+// The codec field, or the mode's low byte when the field is empty or unknown
+fn fsb5_codec(codec_raw: u32, mode: u32) -> Option<(Codec, CodecSource)> {
+    match Codec::from_u32(codec_raw) {
+        Some(codec) if codec != Codec::None => Some((codec, CodecSource::Field)),
+        field => match Codec::from_u32(mode & 0xFF) {
+            Some(codec) if codec != Codec::None => Some((codec, CodecSource::Mode)),
+            _ => field.map(|codec| (codec, CodecSource::Field)),
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BankInfo {
+    pub version: Version,
+    pub encryption: Encryption,
+    // From the bank header alone; None for FSB3, whose codec is per sample
+    pub likely_codec: Option<Codec>,
+}
+
+// Identifies a bank from its first bytes without parsing any sample
+// headers, e.g. to sort a folder of files before loading them. Only a copy
+// of the header is decrypted.
+pub fn inspect(bytes: &[u8]) -> io::Result<BankInfo> {
+    if bytes.len() < 8 {
+        return Err(FsbError::TooSmall.into());
+    }
+    let version = FsbBank::detect_version(bytes)?;
+    let encryption = match version {
+        Version::Fsb3 => Encryption::None,
+        Version::Fsb4 => detect_fsb4_encryption(bytes),
+        Version::Fsb5 => detect_fsb5_encryption(bytes),
+    };
+    let mut header = bytes[..bytes.len().min(FSB5_V0_HEADER_SIZE)].to_vec();
+    match encryption {
+        Encryption::None => {}
+        Encryption::Aes => crypto::decrypt_aes_block(&mut header[0..32], FSB_KEY),
+        Encryption::Fsbext => crypto::fsbext_decrypt(&mut header, FSB_KEY),
+    }
+    let field = |offset: usize| {
+        header
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    };
+    let likely_codec = match version {
+        Version::Fsb3 => None,
+        Version::Fsb4 => field(20).map(|flags| {
+            if flags & 0x00200000 != 0 {
+                Codec::Mpeg
+            } else {
+                Codec::Pcm16
+            }
+        }),
+        Version::Fsb5 => {
+            // Version 0 headers have an extra field before the mode
+            let mode_offset = if field(4) == Some(0) { 32 } else { 28 };
+            field(24)
+                .zip(field(mode_offset))
+                .and_then(|(codec, mode)| fsb5_codec(codec, mode))
+                .map(|(codec, _)| codec)
+        }
+    };
+    Ok(BankInfo {
+        version,
+        encryption,
+        likely_codec,
+    })
+}
+
+fn detect_fsb4_encryption(data: &[u8]) -> Encryption {
+    if &data[0..4] == FSB4_MAGIC {
+        Encryption::None
+    } else {
+        Encryption::Fsbext
+    }
+}
+
+fn detect_fsb5_encryption(data: &[u8]) -> Encryption {
+    if &data[0..4] == FSB5_MAGIC || data.len() < 32 {
+        return Encryption::None;
+    }
+    let mut test = data[0..32].to_vec();
+    crypto::decrypt_aes_block(&mut test, FSB_KEY);
+    if &test[0..4] == FSB5_MAGIC {
+        Encryption::Aes
+    } else {
+        Encryption::Fsbext
+    }
 }
 
 fn frequency_to_index(freq: u32) -> usize {
@@ -732,36 +2651,90 @@ fn frequency_to_index(freq: u32) -> usize {
     }
 }
 
-fn create_wav_header(
-    pcm_data: &[u8],
-    sample_rate: u32,
-    channels: u16,
-    bits_per_sample: u16,
-) -> Vec<u8> {
-    let byte_rate = sample_rate * channels as u32 * (bits_per_sample as u32 / 8);
-    let block_align = channels * (bits_per_sample / 8);
-    let data_size = pcm_data.len() as u32;
-    let file_size = 36 + data_size;
-
-    let mut wav = Vec::with_capacity(44 + pcm_data.len());
-    wav.extend_from_slice(b"RIFF");
-    wav.extend_from_slice(&file_size.to_le_bytes());
-    wav.extend_from_slice(b"WAVE");
-    wav.extend_from_slice(b"fmt ");
-    wav.extend_from_slice(&16u32.to_le_bytes());
-    wav.extend_from_slice(&1u16.to_le_bytes());
-    wav.extend_from_slice(&channels.to_le_bytes());
-    wav.extend_from_slice(&sample_rate.to_le_bytes());
-    wav.extend_from_slice(&byte_rate.to_le_bytes());
-    wav.extend_from_slice(&block_align.to_le_bytes());
-    wav.extend_from_slice(&bits_per_sample.to_le_bytes());
-    wav.extend_from_slice(b"data");
-    wav.extend_from_slice(&data_size.to_le_bytes());
-    wav.extend_from_slice(pcm_data);
-    wav
-}
-
-fn prepare_mp3_data<P: AsRef<Path>>(audio_path: P, temp_dir: P) -> io::Result<Vec<u8>> {
+// FSB5 Vorbis seek table as flat (sample, byte offset) pairs, one at the
+// first packet boundary past each multiple of `interval_samples`. Packets
+// count as 1024 samples, as in the rebuilt OGG granules.
+pub fn rebuild_vorbis_seek_table(raw: &[u8], interval_samples: u32) -> Vec<u32> {
+    let interval = interval_samples.max(1);
+    let mut table = Vec::new();
+    let (mut sample, mut offset, mut next) = (0u32, 0u32, 0u32);
+    for packet in crate::audio::VorbisPacketIterator::new(raw) {
+        if sample >= next {
+            table.extend_from_slice(&[sample, offset]);
+            next = (sample / interval + 1).saturating_mul(interval);
+        }
+        sample = sample.saturating_add(1024);
+        offset += 2 + packet.len() as u32;
+    }
+    table
+}
+
+// Byte range of the packets covering samples [start, end) and the sample
+// the first of them begins at. The seek table only gives a place to start
+// walking from; packets count as 1024 samples as above.
+fn vorbis_packet_range(
+    raw: &[u8],
+    seek_table: &[u32],
+    start: u64,
+    end: u64,
+) -> (Range<usize>, u64) {
+    let (mut sample, mut offset) = seek_table
+        .chunks_exact(2)
+        .map(|e| (e[0] as u64, e[1] as usize))
+        .take_while(|&(s, o)| s <= start && o <= raw.len())
+        .last()
+        .unwrap_or((0, 0));
+    let mut first = None;
+    for packet in crate::audio::VorbisPacketIterator::new(&raw[offset..]) {
+        if sample + 1024 > start && first.is_none() {
+            first = Some((offset, sample));
+        }
+        if sample >= end {
+            break;
+        }
+        sample += 1024;
+        offset += 2 + packet.len();
+    }
+    let (from, first_sample) = first.unwrap_or((offset, sample));
+    (from..offset, first_sample)
+}
+
+// Whole frames overlapping samples [start, end). Multichannel FSB4 streams
+// interleave one mono frame per channel, which together cover one frame time.
+fn mp3_frame_range(frames: &[u8], channels: u32, start: u64, end: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    let (mut pos, mut sample) = (0, 0u64);
+    while pos + 4 <= frames.len() && sample < end {
+        let header = u32::from_be_bytes([
+            frames[pos],
+            frames[pos + 1],
+            frames[pos + 2],
+            frames[pos + 3],
+        ]);
+        let Some(frame) = crate::audio::Mp3FrameHeader::parse(header) else {
+            break;
+        };
+        let frame_end = (pos + frame.frame_size).min(frames.len());
+        let mut samples = frame.samples_per_frame();
+        if channels > 1 && frame.channel_mode == 3 {
+            samples /= channels as u64;
+        }
+        if sample + samples > start {
+            out.extend_from_slice(&frames[pos..frame_end]);
+        }
+        sample += samples;
+        pos = frame_end;
+    }
+    out
+}
+
+// MP3 input is used as-is unless `settings` asks for processing, which
+// means a re-encode like any other format
+fn prepare_mp3_data<P: AsRef<Path>>(
+    audio_path: P,
+    temp_dir: P,
+    settings: &crate::AudioSettings,
+) -> io::Result<Vec<u8>> {
     let audio_path = audio_path.as_ref();
     let ext = audio_path
         .extension()
@@ -769,19 +2742,26 @@ fn prepare_mp3_data<P: AsRef<Path>>(audio_path: P, temp_dir: P) -> io::Result<Ve
         .unwrap_or("")
         .to_lowercase();
 
-    if ext == "mp3" {
+    if ext == "mp3" && !settings.needs_processing() {
         return std::fs::read(audio_path);
     }
 
     let temp_mp3 = temp_dir.as_ref().join("converted.mp3");
-    let ffmpeg =
-        find_ffmpeg().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "FFmpeg not found"))?;
-
-    let output = Command::new(&ffmpeg)
+    let ffmpeg = crate::find_ffmpeg().ok_or(FsbError::FfmpegMissing)?;
+    let input = audio_path.to_string_lossy();
+    let input_secs = if settings.fade_out_ms > 0.0 {
+        crate::probe_duration(&ffmpeg, &input)
+    } else {
+        None
+    };
+
+    let mut command = Command::new(&ffmpeg);
+    command.args(["-y", "-i", &input]);
+    if let Some(filter) = settings.to_ffmpeg_filter_for_duration(input_secs) {
+        command.args(["-af", &filter]);
+    }
+    let output = command
         .args([
-            "-y",
-            "-i",
-            &audio_path.to_string_lossy(),
             "-acodec",
             "libmp3lame",
             "-ab",
@@ -800,18 +2780,3 @@ fn prepare_mp3_data<P: AsRef<Path>>(audio_path: P, temp_dir: P) -> io::Result<Ve
     let _ = std::fs::remove_file(&temp_mp3);
     Ok(data)
 }
-
-fn find_ffmpeg() -> Option<std::path::PathBuf> {
-    if let Ok(exe_path) = std::env::current_exe() {
-        if let Some(exe_dir) = exe_path.parent() {
-            let local = exe_dir.join("ffmpeg.exe");
-            if local.exists() {
-                return Some(local);
-            }
-        }
-    }
-    if Command::new("ffmpeg").arg("-version").output().is_ok() {
-        return Some(std::path::PathBuf::from("ffmpeg"));
-    }
-    None
-}
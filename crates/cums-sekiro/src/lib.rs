@@ -1,17 +1,39 @@
 pub mod audio;
+mod codec;
 mod crypto;
+#[cfg(feature = "native-encode")]
+mod encode;
+mod error;
 pub mod formats;
 mod fsb;
+mod loudness;
+mod naming;
+mod txth;
 
-pub use crypto::FSB_KEY;
-pub use fsb::{Codec, Encryption, Fsb4Mode, FsbBank, Sample, Version};
+pub use codec::{has_codec_handler, register_codec_handler, CodecHandler};
+pub use crypto::{decrypt_aes_data, encrypt_aes_data, fsbext_decrypt, fsbext_encrypt, FSB_KEY};
+#[cfg(feature = "native-encode")]
+pub use encode::encode_vorbis;
+pub use error::FsbError;
+pub use fsb::{
+    inspect, rebuild_vorbis_seek_table, BankInfo, Codec, CodecSource, DiffKind, Encryption,
+    ExportedSample, Fsb4Mode, Fsb5Mode, FsbBank, FsbBankReader, GameGuess, GameProfile, IssueKind,
+    LintIssue, Sample, SampleDiff, SampleIssue, Version, DEFAULT_DATA_ALIGNMENT,
+    FSB5_HEADER_VERSION, FSB5_OFFSET_QUANTUM, VORBIS_PRE_SKIP,
+};
+pub use loudness::{measure_loudness, Loudness};
+pub use naming::{
+    export_file_name, validate_name_template, DEFAULT_NAME_TEMPLATE, NAME_PLACEHOLDERS,
+};
+pub use txth::build_txth;
 
-use byteorder::{LittleEndian, ReadBytesExt};
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
-use std::io::{Cursor, Read};
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
 
 const VORBIS_HEADERS_JSON: &str = include_str!("vorbis_headers.json");
 
@@ -33,11 +55,30 @@ static VORBIS_HEADERS: Lazy<HashMap<u32, Vec<u8>>> = Lazy::new(|| {
     headers
 });
 
-#[derive(Debug, Clone)]
+// The bundled table's other field, kept so exported entries match its shape
+static VORBIS_SEEK_BITS: Lazy<HashMap<u32, serde_json::Value>> = Lazy::new(|| {
+    let map: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(VORBIS_HEADERS_JSON).unwrap_or_default();
+    map.into_iter()
+        .filter_map(|(crc, value)| Some((crc.parse().ok()?, value.get("seekBit")?.clone())))
+        .collect()
+});
+
+static EXTRA_VORBIS_HEADERS: Lazy<RwLock<HashMap<u32, Vec<u8>>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct AudioSettings {
     pub volume_db: f32,
     pub pitch_semitones: f32,
     pub speed: f32,
+    pub fade_in_ms: f32,
+    pub fade_out_ms: f32,
+    pub keep_size: bool,
+    // Encoder quality, 1-100. With keep_size this is where the search starts.
+    pub quality: u8,
+    // Codec to encode to; None keeps the bank's codec
+    pub target_codec: Option<Codec>,
 }
 
 impl Default for AudioSettings {
@@ -46,18 +87,44 @@ impl Default for AudioSettings {
             volume_db: 0.0,
             pitch_semitones: 0.0,
             speed: 1.0,
+            fade_in_ms: 0.0,
+            fade_out_ms: 0.0,
+            keep_size: false,
+            quality: DEFAULT_QUALITY,
+            target_codec: None,
         }
     }
 }
 
 impl AudioSettings {
+    pub fn validate(&self) -> Result<(), std::io::Error> {
+        if !(1..=100).contains(&self.quality) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Quality must be between 1 and 100, got {}", self.quality),
+            ));
+        }
+        if let Some(codec) = self.target_codec {
+            fsbankcl_format(codec)?;
+        }
+        Ok(())
+    }
+
     pub fn needs_processing(&self) -> bool {
         self.volume_db.abs() > 0.01
             || self.pitch_semitones.abs() > 0.01
             || (self.speed - 1.0).abs() > 0.01
+            || self.fade_in_ms > 0.0
+            || self.fade_out_ms > 0.0
     }
 
     pub fn to_ffmpeg_filter(&self) -> Option<String> {
+        self.to_ffmpeg_filter_for_duration(None)
+    }
+
+    // Fades are applied last so their times are on the output timeline. Without
+    // the input duration the fade-out is done on the reversed signal instead.
+    pub fn to_ffmpeg_filter_for_duration(&self, input_secs: Option<f32>) -> Option<String> {
         if !self.needs_processing() {
             return None;
         }
@@ -82,35 +149,362 @@ impl AudioSettings {
             }
             filters.push(format!("atempo={:.4}", speed));
         }
+        if self.fade_in_ms > 0.0 {
+            filters.push(format!("afade=t=in:st=0:d={:.3}", self.fade_in_ms / 1000.0));
+        }
+        if self.fade_out_ms > 0.0 {
+            let fade = self.fade_out_ms / 1000.0;
+            match input_secs {
+                Some(secs) => {
+                    let pitch_ratio = 2.0_f32.powf(self.pitch_semitones / 12.0);
+                    let out_secs = secs / pitch_ratio / self.speed.clamp(0.25, 4.0);
+                    filters.push(format!(
+                        "afade=t=out:st={:.3}:d={:.3}",
+                        (out_secs - fade).max(0.0),
+                        fade
+                    ));
+                }
+                None => filters.push(format!("areverse,afade=t=in:d={:.3},areverse", fade)),
+            }
+        }
         Some(filters.join(","))
     }
 }
 
+// Headers registered at runtime win over the bundled table
 pub fn get_vorbis_setup_header(crc: u32) -> Option<Vec<u8>> {
-    VORBIS_HEADERS.get(&crc).cloned()
+    EXTRA_VORBIS_HEADERS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&crc)
+        .cloned()
+        .or_else(|| VORBIS_HEADERS.get(&crc).cloned())
+}
+
+// Every CRC with a setup header, bundled or registered, in ascending order
+pub fn known_vorbis_crcs() -> Vec<u32> {
+    let mut crcs: Vec<u32> = VORBIS_HEADERS.keys().copied().collect();
+    crcs.extend(
+        EXTRA_VORBIS_HEADERS
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys(),
+    );
+    crcs.sort_unstable();
+    crcs.dedup();
+    crcs
+}
+
+// Headers registered at runtime only live for the current process. This
+// replaces any header already known for `crc`.
+pub fn register_vorbis_header(crc: u32, setup_bytes: Vec<u8>) {
+    EXTRA_VORBIS_HEADERS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(crc, setup_bytes);
+}
+
+// Registers `header` only if nothing is known for `crc` yet
+pub fn register_vorbis_setup_header(crc: u32, header: Vec<u8>) -> bool {
+    if has_vorbis_setup_header(crc) {
+        return false;
+    }
+    register_vorbis_header(crc, header);
+    true
+}
+
+pub fn has_vorbis_setup_header(crc: u32) -> bool {
+    VORBIS_HEADERS.contains_key(&crc)
+        || EXTRA_VORBIS_HEADERS
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(&crc)
+}
+
+pub fn export_vorbis_header(crc: u32) -> Option<Vec<u8>> {
+    get_vorbis_setup_header(crc)
+}
+
+// One entry in the bundled table's shape. Registered headers have no
+// seekBit unless the bundled table also knows their CRC.
+pub fn export_vorbis_header_json(crc: u32) -> Option<String> {
+    use base64::Engine;
+    let header = get_vorbis_setup_header(crc)?;
+    let mut entry = serde_json::Map::new();
+    entry.insert(
+        "headerBytes".into(),
+        base64::engine::general_purpose::STANDARD
+            .encode(header)
+            .into(),
+    );
+    if let Some(seek_bit) = VORBIS_SEEK_BITS.get(&crc) {
+        entry.insert("seekBit".into(), seek_bit.clone());
+    }
+    let mut out = serde_json::Map::new();
+    out.insert(crc.to_string(), entry.into());
+    serde_json::to_string_pretty(&out).ok()
 }
 
-pub fn rebuild_ogg(bank: &FsbBank, sample: &Sample) -> Result<Vec<u8>, std::io::Error> {
+pub fn rebuild_ogg(bank: &FsbBank, sample: &Sample) -> Result<Vec<u8>, FsbError> {
+    let setup = setup_header_for(bank, sample)?;
+    rebuild_ogg_with_setup(bank, sample, &setup)
+}
+
+// Like rebuild_ogg, but an unknown setup CRC falls back to the first known
+// header that parses for the sample's channel count. Only the header's shape
+// is checked, not its codebooks, so the audio can come out garbled or desync;
+// it's meant for getting something to extract or preview, never for saving.
+pub fn rebuild_ogg_lenient(bank: &FsbBank, sample: &Sample) -> Result<Vec<u8>, FsbError> {
+    let setup = match setup_header_for(bank, sample) {
+        Err(FsbError::UnknownVorbisCrc(crc)) => {
+            let (fallback, setup) = compatible_setup_header(sample.channels as u8)
+                .ok_or(FsbError::UnknownVorbisCrc(crc))?;
+            tracing::warn!(
+                "Unknown setup CRC 0x{:08X} for {}; using 0x{:08X} instead",
+                crc,
+                sample.display_name(),
+                fallback
+            );
+            setup
+        }
+        result => result?,
+    };
+    rebuild_ogg_with_setup(bank, sample, &setup)
+}
+
+fn compatible_setup_header(channels: u8) -> Option<(u32, Vec<u8>)> {
+    known_vorbis_crcs().into_iter().find_map(|crc| {
+        let setup = get_vorbis_setup_header(crc)?;
+        lewton::header::read_header_setup(&setup, channels, VORBIS_BLOCKSIZES).ok()?;
+        Some((crc, setup))
+    })
+}
+
+// Like rebuild_ogg with a caller-supplied setup header instead of the table
+// lookup, e.g. one exported from a bank whose CRC is known. `setup_header`
+// must be the third Vorbis header packet, starting with "\x05vorbis".
+pub fn rebuild_ogg_with_setup(
+    bank: &FsbBank,
+    sample: &Sample,
+    setup_header: &[u8],
+) -> Result<Vec<u8>, FsbError> {
+    if !setup_header.starts_with(b"\x05vorbis") {
+        return Err(FsbError::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Not a Vorbis setup header (expected the third header packet)",
+        )));
+    }
+    let mut output = Vec::new();
+    open_ogg_stream_with_setup(
+        bank,
+        sample,
+        setup_header.to_vec(),
+        &[],
+        OggRebuildOptions::default(),
+    )?
+    .read_to_end(&mut output)?;
+    Ok(output)
+}
+
+// Muxes a run of whole packets cut from the sample, with granules counted
+// from zero and the last page clamped to `samples`
+pub(crate) fn rebuild_ogg_packets(
+    bank: &FsbBank,
+    sample: &Sample,
+    packets: &[u8],
+    samples: u64,
+    options: OggRebuildOptions,
+) -> Result<Vec<u8>, FsbError> {
+    let setup = setup_header_for(bank, sample)?;
+    let id_header = generate_vorbis_id_header(sample.frequency, sample.channels as u8);
+    let mut output = Vec::new();
+    OggStream::new(
+        id_header,
+        generate_vorbis_comment_header(&options.vendor, &[]),
+        setup,
+        packets,
+        samples,
+        options,
+    )?
+    .read_to_end(&mut output)?;
+    Ok(output)
+}
+
+pub fn rebuild_ogg_with_options(
+    bank: &FsbBank,
+    sample: &Sample,
+    options: OggRebuildOptions,
+) -> Result<Vec<u8>, FsbError> {
+    rebuild_ogg_with_comments(bank, sample, &[], options)
+}
+
+fn rebuild_ogg_with_comments(
+    bank: &FsbBank,
+    sample: &Sample,
+    comments: &[String],
+    options: OggRebuildOptions,
+) -> Result<Vec<u8>, FsbError> {
+    let mut output = Vec::new();
+    open_ogg_stream(bank, sample, comments, options)?.read_to_end(&mut output)?;
+    Ok(output)
+}
+
+pub(crate) fn open_ogg_stream<'a>(
+    bank: &'a FsbBank,
+    sample: &Sample,
+    comments: &[String],
+    options: OggRebuildOptions,
+) -> Result<OggStream<'a>, FsbError> {
+    let setup = setup_header_for(bank, sample)?;
+    open_ogg_stream_with_setup(bank, sample, setup, comments, options)
+}
+
+fn setup_header_for(bank: &FsbBank, sample: &Sample) -> Result<Vec<u8>, FsbError> {
     if bank.codec != Codec::Vorbis {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Not Vorbis",
-        ));
+        return Err(FsbError::NotVorbis);
     }
-    let crc = sample
-        .vorbis_crc
-        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Missing CRC"))?;
-    let setup = get_vorbis_setup_header(crc).ok_or_else(|| {
-        std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            format!("Unknown CRC 0x{:08X}", crc),
-        )
+    let crc = sample.vorbis_crc.ok_or(FsbError::MissingVorbisCrc {
+        index: sample.index,
     })?;
+    get_vorbis_setup_header(crc).ok_or(FsbError::UnknownVorbisCrc(crc))
+}
+
+fn open_ogg_stream_with_setup<'a>(
+    bank: &'a FsbBank,
+    sample: &Sample,
+    setup: Vec<u8>,
+    comments: &[String],
+    options: OggRebuildOptions,
+) -> Result<OggStream<'a>, FsbError> {
+    if bank.codec != Codec::Vorbis {
+        return Err(FsbError::NotVorbis);
+    }
+    if sample.is_empty() {
+        return Err(FsbError::EmptySample {
+            index: sample.index,
+        });
+    }
     let raw = bank.sample_data(sample.index)?;
 
     let id_header = generate_vorbis_id_header(sample.frequency, sample.channels as u8);
-    let comment_header = generate_vorbis_comment_header();
-    build_ogg_file(&id_header, &comment_header, &setup, raw)
+    let comment_header = generate_vorbis_comment_header(&options.vendor, comments);
+    Ok(OggStream::new(
+        id_header,
+        comment_header,
+        setup,
+        raw,
+        sample.samples,
+        options,
+    )?)
+}
+
+// A scratch file name unique to this process and call, so concurrent calls
+// sharing `temp_dir` don't overwrite each other's files
+pub(crate) fn unique_temp_path(temp_dir: &Path, stem: &str, ext: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    temp_dir.join(format!("{}_{}_{}.{}", stem, std::process::id(), n, ext))
+}
+
+pub fn rebuild_ogg_with_replaygain(
+    bank: &FsbBank,
+    sample: &Sample,
+    temp_dir: &Path,
+) -> Result<Vec<u8>, FsbError> {
+    let ogg = rebuild_ogg(bank, sample)?;
+    let ff = find_ffmpeg().ok_or(FsbError::FfmpegMissing)?;
+
+    std::fs::create_dir_all(temp_dir)?;
+    let temp_ogg = unique_temp_path(temp_dir, "temp_replaygain", "ogg");
+    std::fs::write(&temp_ogg, &ogg)?;
+    let pcm = decode_to_pcm(&ff, &temp_ogg, sample.channels, sample.frequency);
+    let _ = std::fs::remove_file(&temp_ogg);
+
+    let loudness = measure_loudness(&pcm?, sample.channels, sample.frequency);
+    rebuild_ogg_with_comments(
+        bank,
+        sample,
+        &loudness.replaygain_tags(),
+        OggRebuildOptions::default(),
+    )
+}
+
+// Silence between samples so neighbouring waveforms stay apart
+const AUDACITY_GAP_SECS: f64 = 0.5;
+
+// Decodes every sample into one sequential WAV and writes an Audacity label
+// track next to it. Mixed-rate banks are resampled to the highest rate.
+pub fn export_audacity(
+    bank: &FsbBank,
+    wav_path: &Path,
+    temp_dir: &Path,
+) -> Result<(u32, usize), std::io::Error> {
+    let ff = find_ffmpeg().ok_or(FsbError::FfmpegMissing)?;
+    let rate = bank
+        .samples
+        .iter()
+        .map(|s| s.frequency)
+        .max()
+        .unwrap_or(44100);
+    let channels = bank
+        .samples
+        .iter()
+        .map(|s| s.channels)
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let gap = (rate as f64 * AUDACITY_GAP_SECS) as usize * channels as usize;
+
+    std::fs::create_dir_all(temp_dir)?;
+    let mut pcm: Vec<f32> = Vec::new();
+    let mut labels = format!(
+        "0.000000\t0.000000\tCUMS export: {} Hz, {} channel(s)\n",
+        rate, channels
+    );
+    let mut count = 0;
+    for sample in &bank.samples {
+        let Ok((data, ext)) = bank.export_sample(sample.index) else {
+            continue;
+        };
+        let temp = unique_temp_path(temp_dir, "temp_audacity", ext);
+        std::fs::write(&temp, data)?;
+        let decoded = decode_to_pcm(&ff, &temp, channels, rate);
+        let _ = std::fs::remove_file(&temp);
+        let Ok(decoded) = decoded else {
+            continue;
+        };
+
+        if count > 0 {
+            pcm.resize(pcm.len() + gap, 0.0);
+        }
+        let seconds = |len: usize| (len / channels as usize) as f64 / rate as f64;
+        let start = seconds(pcm.len());
+        pcm.extend_from_slice(&decoded);
+        labels.push_str(&format!(
+            "{:.6}\t{:.6}\t{}\n",
+            start,
+            seconds(pcm.len()),
+            sample.display_name().replace('\t', " ")
+        ));
+        count += 1;
+    }
+    if count == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "No samples could be decoded",
+        ));
+    }
+
+    let pcm16: Vec<u8> = pcm
+        .iter()
+        .flat_map(|s| ((s.clamp(-1.0, 1.0) * 32767.0) as i16).to_le_bytes())
+        .collect();
+    std::fs::write(
+        wav_path,
+        audio::create_wav_header(&pcm16, rate, channels as u16, 16, audio::WAVE_FORMAT_PCM),
+    )?;
+    std::fs::write(wav_path.with_extension("txt"), labels)?;
+    Ok((rate, count))
 }
 
 pub fn extract_mp3(bank: &FsbBank, sample: &Sample) -> Result<Vec<u8>, std::io::Error> {
@@ -124,89 +518,528 @@ pub fn replace_sample(
     fsbankcl_path: &Path,
     temp_dir: &Path,
     settings: &AudioSettings,
-) -> Result<(), std::io::Error> {
+) -> Result<(u32, bool), FsbError> {
+    let codec = check_replacement(bank, sample_index, settings)?;
+    let target = &bank.samples[sample_index];
+    let (new_data, new_sample, quality, fits) = encode_for_bank(
+        audio_path,
+        fsbankcl_path,
+        temp_dir,
+        settings,
+        codec,
+        (target.frequency, target.channels),
+        target.data_size as usize,
+    )?;
+    bank.codec = codec;
+    install_replacement(bank, sample_index, &new_data, &new_sample);
+    Ok((quality, fits))
+}
+
+// Replaces several samples at once. Edits sharing a format and quality are
+// encoded by a single fsbankcl run; keep_size edits search for their own
+// quality, so they and lone edits go through replace_sample. PCM16 edits
+// from a 16-bit WAV that needs no processing are copied in without an
+// encoder. Returns (quality, fits) for each edit, in order.
+pub fn replace_samples(
+    bank: &mut FsbBank,
+    edits: &[(usize, PathBuf, AudioSettings)],
+    fsbankcl_path: &Path,
+    temp_dir: &Path,
+) -> Result<Vec<(u32, bool)>, FsbError> {
+    let mut codecs = Vec::with_capacity(edits.len());
+    for (index, _, settings) in edits {
+        codecs.push(check_replacement(bank, *index, settings)?);
+    }
+
+    let mut results = vec![None; edits.len()];
+    let mut groups: Vec<((Codec, u8), Vec<usize>)> = Vec::new();
+    for (i, (index, path, settings)) in edits.iter().enumerate() {
+        let codec = codecs[i];
+        if codec == Codec::Pcm16 && bank.codec == Codec::Pcm16 && !settings.needs_processing() {
+            let wav = std::fs::read(path)?;
+            if let Ok((_, _, 16, _)) = audio::parse_wav(&wav) {
+                let old_size = bank.samples[*index].data_size;
+                bank.replace_sample_pcm(*index, &wav)?;
+                let fits = bank.samples[*index].data_size <= old_size;
+                results[i] = Some((settings.quality as u32, fits));
+                continue;
+            }
+        }
+        let key = (codec, settings.quality);
+        let group = groups
+            .iter_mut()
+            .find(|(k, members)| *k == key && !edits[members[0]].2.keep_size);
+        match group {
+            Some((_, members)) if !settings.keep_size => members.push(i),
+            _ => groups.push((key, vec![i])),
+        }
+    }
+
+    for ((codec, quality), members) in groups {
+        if members.len() == 1 || edits[members[0]].2.keep_size {
+            for i in members {
+                let (index, path, settings) = &edits[i];
+                results[i] = Some(replace_sample(
+                    bank,
+                    *index,
+                    path,
+                    fsbankcl_path,
+                    temp_dir,
+                    settings,
+                )?);
+            }
+            continue;
+        }
+
+        let batch: Vec<&(usize, PathBuf, AudioSettings)> =
+            members.iter().map(|&i| &edits[i]).collect();
+        let encoded = encode_batch(bank, &batch, codec, quality, fsbankcl_path, temp_dir)?;
+        bank.codec = codec;
+        for (i, (data, sample)) in members.into_iter().zip(encoded) {
+            let index = edits[i].0;
+            let fits = data.len() as u64 <= bank.samples[index].data_size;
+            install_replacement(bank, index, &data, &sample);
+            results[i] = Some((quality as u32, fits));
+        }
+    }
+    Ok(results.into_iter().flatten().collect())
+}
+
+// Checks an edit against the bank and returns the codec it encodes to
+fn check_replacement(
+    bank: &FsbBank,
+    sample_index: usize,
+    settings: &AudioSettings,
+) -> Result<Codec, FsbError> {
     if bank.version != Version::Fsb5 {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Use FsbBank::replace_sample for FSB4",
-        ));
+        return Err(FsbError::UnsupportedVersion(bank.version));
     }
     if sample_index >= bank.samples.len() {
+        return Err(FsbError::SampleNotFound {
+            index: sample_index,
+        });
+    }
+
+    settings.validate()?;
+    // A bank has a single codec, so only a lone sample can switch to another
+    let codec = settings.target_codec.unwrap_or(bank.codec);
+    if codec != bank.codec && bank.samples.len() > 1 {
+        return Err(FsbError::CodecMismatch {
+            bank: bank.codec,
+            target: codec,
+        });
+    }
+    Ok(codec)
+}
+
+// Puts encoded data in place of a sample and takes over its format fields
+fn install_replacement(
+    bank: &mut FsbBank,
+    sample_index: usize,
+    new_data: &[u8],
+    new_sample: &Sample,
+) {
+    let old_seek_table = bank.samples[sample_index].vorbis_seek_table.clone();
+    bank.splice_sample(sample_index, new_data);
+    bank.samples[sample_index].frequency = new_sample.frequency;
+    bank.samples[sample_index].channels = new_sample.channels;
+    bank.samples[sample_index].samples = new_sample.samples;
+    bank.samples[sample_index].vorbis_crc = new_sample.vorbis_crc;
+    // Seek points are recomputed from the data actually stored, keeping the
+    // spacing of the old table where there was one
+    bank.samples[sample_index].vorbis_seek_table = new_sample.vorbis_crc.map(|_| {
+        let interval = [&old_seek_table, &new_sample.vorbis_seek_table]
+            .into_iter()
+            .flatten()
+            .find_map(|table| table.get(2).copied().filter(|&s| s > 0))
+            .unwrap_or(VORBIS_SEEK_INTERVAL);
+        rebuild_vorbis_seek_table(new_data, interval)
+    });
+}
+
+// Encodes `audio_path` and adds it after the last sample, returning its
+// index. Vorbis banks get the rate and channels of the first sample, since
+// the setup header has to match; PCM16 banks take a PCM16 WAV unchanged.
+pub fn append_sample(
+    bank: &mut FsbBank,
+    audio_path: &Path,
+    fsbankcl_path: &Path,
+    temp_dir: &Path,
+    name: Option<String>,
+    settings: &AudioSettings,
+) -> Result<usize, std::io::Error> {
+    if bank.version != Version::Fsb5 {
         return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Sample index out of bounds",
+            std::io::ErrorKind::Unsupported,
+            "Only FSB5 banks can grow",
         ));
     }
 
-    let target_freq = bank.samples[sample_index].frequency;
-    let target_channels = bank.samples[sample_index].channels;
+    let (data, mut sample) = match bank.codec {
+        Codec::Vorbis => {
+            let target = bank
+                .samples
+                .first()
+                .map_or((44100, 2), |s| (s.frequency, s.channels));
+            let (data, mut sample, _, _) = encode_for_bank(
+                audio_path,
+                fsbankcl_path,
+                temp_dir,
+                settings,
+                Codec::Vorbis,
+                target,
+                0,
+            )?;
+            let interval = sample
+                .vorbis_seek_table
+                .as_ref()
+                .and_then(|t| t.get(2).copied())
+                .filter(|&s| s > 0)
+                .unwrap_or(VORBIS_SEEK_INTERVAL);
+            sample.vorbis_seek_table = Some(rebuild_vorbis_seek_table(&data, interval));
+            (data, sample)
+        }
+        Codec::Pcm16 => read_pcm16_wav(&std::fs::read(audio_path)?)?,
+        codec => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("Can't add samples to a {:?} bank", codec),
+            ))
+        }
+    };
+    sample.name = name;
+    sample.loop_start = None;
+    sample.loop_end = None;
+    Ok(bank.push_sample(&data, sample))
+}
 
-    let fsbankcl_dir = fsbankcl_path.parent().ok_or_else(|| {
-        std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid fsbankcl path")
-    })?;
+// Sample description and PCM payload of a 16-bit PCM WAV file
+fn read_pcm16_wav(wav: &[u8]) -> Result<(Vec<u8>, Sample), std::io::Error> {
+    let (rate, channels @ 1..=2, 16, pcm) = audio::parse_wav(wav)? else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Only mono or stereo 16-bit PCM WAV can be added",
+        ));
+    };
+    let sample = Sample {
+        index: 0,
+        name: None,
+        frequency: rate,
+        channels: channels as u32,
+        samples: (pcm.len() / (2 * channels as usize)) as u64,
+        data_offset: 0,
+        data_size: pcm.len() as u64,
+        loop_start: None,
+        loop_end: None,
+        vorbis_crc: None,
+        vorbis_seek_table: None,
+        dsp_coeffs: None,
+        at9_config: None,
+        chunks: Vec::new(),
+        mode: None,
+    };
+    Ok((pcm, sample))
+}
 
+// Resamples `audio_path` to `target` (rate, channels) when FFmpeg is around,
+// then encodes it to `codec`. `old_size` is the slot keep_size tries to fit.
+fn encode_for_bank(
+    audio_path: &Path,
+    fsbankcl_path: &Path,
+    temp_dir: &Path,
+    settings: &AudioSettings,
+    codec: Codec,
+    (target_freq, target_channels): (u32, u32),
+    old_size: usize,
+) -> Result<(Vec<u8>, Sample, u32, bool), FsbError> {
+    settings.validate()?;
+    let format = fsbankcl_format(codec)?;
+    let qualities = fit_qualities(settings.quality as u32, settings.keep_size);
     let temp_fsb = temp_dir.join("temp_replacement.fsb");
     let temp_wav = temp_dir.join("temp_resampled.wav");
+    let temp_fsb_str = temp_fsb.to_string_lossy();
+    let temp_fsb_clean = temp_fsb_str.strip_prefix(r"\\?\").unwrap_or(&temp_fsb_str);
+    let (encode_path, did_resample) = prepare_input(
+        audio_path,
+        &temp_wav,
+        settings,
+        (target_freq, target_channels),
+    )?;
+    let encode_path = encode_path.as_str();
+
+    #[cfg(feature = "native-encode")]
+    let encoded = if fsbankcl_path.exists() || codec != Codec::Vorbis {
+        encode_with_fsbankcl(
+            fsbankcl_path,
+            encode_path,
+            temp_fsb_clean,
+            format,
+            &qualities,
+            old_size,
+        )
+    } else {
+        let ff = find_ffmpeg().ok_or(FsbError::FfmpegMissing)?;
+        encode_native(
+            &ff,
+            Path::new(encode_path),
+            target_channels,
+            target_freq,
+            &qualities,
+            old_size,
+        )
+    };
+    #[cfg(not(feature = "native-encode"))]
+    let encoded = encode_with_fsbankcl(
+        fsbankcl_path,
+        encode_path,
+        temp_fsb_clean,
+        format,
+        &qualities,
+        old_size,
+    );
+    let (mut new_data, new_sample, quality, fits) = encoded?;
+    if settings.keep_size && fits {
+        new_data.resize(old_size, 0);
+    }
+    check_encoded(&new_sample, (target_freq, target_channels), did_resample)?;
+
+    let _ = std::fs::remove_file(&temp_wav);
+    Ok((new_data, new_sample, quality, fits))
+}
+
+// Encodes every edit in one fsbankcl run. fsbankcl writes one sample per
+// input file, in the order given.
+fn encode_batch(
+    bank: &FsbBank,
+    edits: &[&(usize, PathBuf, AudioSettings)],
+    codec: Codec,
+    quality: u8,
+    fsbankcl_path: &Path,
+    temp_dir: &Path,
+) -> Result<Vec<(Vec<u8>, Sample)>, FsbError> {
+    let temp_fsb = temp_dir.join("temp_batch.fsb");
+    let temp_fsb_str = temp_fsb.to_string_lossy();
+    let temp_fsb_clean = temp_fsb_str.strip_prefix(r"\\?\").unwrap_or(&temp_fsb_str);
+
+    let mut inputs = Vec::with_capacity(edits.len());
+    let mut temp_wavs = Vec::with_capacity(edits.len());
+    for (n, (index, path, settings)) in edits.iter().enumerate() {
+        let target = &bank.samples[*index];
+        let temp_wav = temp_dir.join(format!("temp_batch_{}.wav", n));
+        let (input, did_resample) = prepare_input(
+            path,
+            &temp_wav,
+            settings,
+            (target.frequency, target.channels),
+        )?;
+        inputs.push((input, did_resample));
+        temp_wavs.push(temp_wav);
+    }
+
+    let files: Vec<&str> = inputs.iter().map(|(input, _)| input.as_str()).collect();
+    let encoded = run_fsbankcl(
+        fsbankcl_path,
+        &files,
+        temp_fsb_clean,
+        fsbankcl_format(codec)?,
+        quality as u32,
+    );
+    for wav in &temp_wavs {
+        let _ = std::fs::remove_file(wav);
+    }
+    let new_bank = encoded?;
+    if new_bank.samples.len() != edits.len() {
+        return Err(FsbError::EncodedCountMismatch {
+            expected: edits.len(),
+            actual: new_bank.samples.len(),
+        });
+    }
+
+    let mut out = Vec::with_capacity(edits.len());
+    for (k, ((index, _, _), (_, did_resample))) in edits.iter().zip(&inputs).enumerate() {
+        let target = &bank.samples[*index];
+        let sample = new_bank.samples[k].clone();
+        check_encoded(&sample, (target.frequency, target.channels), *did_resample)?;
+        out.push((new_bank.sample_data(k)?.to_vec(), sample));
+    }
+    Ok(out)
+}
+
+// Runs the settings' filters and resamples to `target` (rate, channels) when
+// FFmpeg is around. Returns the file to encode and whether it was resampled.
+fn prepare_input(
+    audio_path: &Path,
+    temp_wav: &Path,
+    settings: &AudioSettings,
+    (target_freq, target_channels): (u32, u32),
+) -> Result<(String, bool), FsbError> {
     let audio_path_abs = audio_path.canonicalize()?;
     let audio_str = audio_path_abs.to_string_lossy();
     let audio_clean = audio_str.strip_prefix(r"\\?\").unwrap_or(&audio_str);
-    let temp_fsb_str = temp_fsb.to_string_lossy();
-    let temp_fsb_clean = temp_fsb_str.strip_prefix(r"\\?\").unwrap_or(&temp_fsb_str);
     let temp_wav_str = temp_wav.to_string_lossy();
     let temp_wav_clean = temp_wav_str.strip_prefix(r"\\?\").unwrap_or(&temp_wav_str);
 
-    let ffmpeg = find_ffmpeg();
-    let (encode_path, did_resample) = if let Some(ref ff) = ffmpeg {
-        let mut filters = Vec::new();
-        if let Some(f) = settings.to_ffmpeg_filter() {
-            filters.push(f);
+    let Some(ff) = find_ffmpeg() else {
+        return Ok((audio_clean.to_string(), false));
+    };
+    let mut filters = Vec::new();
+    let input_secs = if settings.fade_out_ms > 0.0 {
+        probe_duration(&ff, audio_clean)
+    } else {
+        None
+    };
+    if let Some(f) = settings.to_ffmpeg_filter_for_duration(input_secs) {
+        filters.push(f);
+    }
+    filters.push(format!(
+        "aresample={}:ochl={}",
+        target_freq,
+        if target_channels == 1 {
+            "mono"
+        } else {
+            "stereo"
         }
-        filters.push(format!(
-            "aresample={}:ochl={}",
-            target_freq,
-            if target_channels == 1 {
-                "mono"
-            } else {
-                "stereo"
-            }
-        ));
+    ));
 
-        let output = Command::new(ff)
-            .args([
-                "-y",
-                "-i",
-                audio_clean,
-                "-af",
-                &filters.join(","),
-                "-ar",
-                &target_freq.to_string(),
-                "-ac",
-                &target_channels.to_string(),
-                temp_wav_clean,
-            ])
-            .output();
-
-        if output.map(|o| o.status.success()).unwrap_or(false) {
-            (temp_wav_clean, true)
-        } else {
-            (audio_clean, false)
+    let output = Command::new(&ff)
+        .args([
+            "-y",
+            "-i",
+            audio_clean,
+            "-af",
+            &filters.join(","),
+            "-ar",
+            &target_freq.to_string(),
+            "-ac",
+            &target_channels.to_string(),
+            temp_wav_clean,
+        ])
+        .output();
+
+    if output.map(|o| o.status.success()).unwrap_or(false) {
+        Ok((temp_wav_clean.to_string(), true))
+    } else {
+        Ok((audio_clean.to_string(), false))
+    }
+}
+
+// A Vorbis sample has to match its slot's rate and channels, since FSB5 keeps
+// one setup header per CRC, and its CRC has to be one we can rebuild
+fn check_encoded(
+    sample: &Sample,
+    (target_freq, target_channels): (u32, u32),
+    did_resample: bool,
+) -> Result<(), FsbError> {
+    if let Some(new_crc) = sample.vorbis_crc {
+        let mismatch = sample.frequency != target_freq || sample.channels != target_channels;
+        if mismatch && !did_resample {
+            return Err(FsbError::FfmpegMissing);
         }
+        if get_vorbis_setup_header(new_crc).is_none() {
+            return Err(FsbError::UnknownVorbisCrc(new_crc));
+        }
+    }
+    Ok(())
+}
+
+pub fn render_preview(
+    audio_path: &Path,
+    settings: &AudioSettings,
+) -> Result<Vec<u8>, std::io::Error> {
+    if !settings.needs_processing() {
+        return std::fs::read(audio_path);
+    }
+
+    let ff = find_ffmpeg().ok_or(FsbError::FfmpegMissing)?;
+    let input = audio_path.to_string_lossy();
+    let input_secs = if settings.fade_out_ms > 0.0 {
+        probe_duration(&ff, &input)
     } else {
-        (audio_clean, false)
+        None
     };
+    let filter = settings
+        .to_ffmpeg_filter_for_duration(input_secs)
+        .unwrap_or_else(|| "anull".into());
 
-    let output = Command::new(fsbankcl_path)
-        .current_dir(fsbankcl_dir)
+    let output = Command::new(&ff)
         .args([
-            "-format",
-            "vorbis",
-            "-quality",
-            "50",
-            "-o",
-            temp_fsb_clean,
-            encode_path,
+            "-v", "error", "-i", &input, "-af", &filter, "-f", "wav", "pipe:1",
         ])
         .output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    Ok(output.stdout)
+}
+
+// Seek point spacing for samples that had no table to copy it from
+const VORBIS_SEEK_INTERVAL: u32 = 16384;
+pub const DEFAULT_QUALITY: u8 = 50;
+// With keep_size, step the quality down until the encode fits the old slot
+const FIT_QUALITIES: [u32; 7] = [50, 40, 30, 20, 10, 5, 1];
+
+fn fit_qualities(start: u32, keep_size: bool) -> Vec<u32> {
+    let mut qualities = vec![start];
+    if keep_size {
+        qualities.extend(FIT_QUALITIES.iter().filter(|&&q| q < start));
+    }
+    qualities
+}
+
+// fsbankcl's -format name for a codec it can encode
+fn fsbankcl_format(codec: Codec) -> Result<&'static str, std::io::Error> {
+    match codec {
+        Codec::Vorbis => Ok("vorbis"),
+        Codec::Pcm16 => Ok("pcm"),
+        Codec::ImaAdpcm => Ok("adpcm"),
+        codec => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("fsbankcl can't encode {:?}", codec),
+        )),
+    }
+}
+
+fn encode_with_fsbankcl(
+    fsbankcl_path: &Path,
+    encode_path: &str,
+    temp_fsb: &str,
+    format: &str,
+    qualities: &[u32],
+    old_size: usize,
+) -> Result<(Vec<u8>, Sample, u32, bool), std::io::Error> {
+    let mut encoded = None;
+    for &quality in qualities {
+        let new_bank = run_fsbankcl(fsbankcl_path, &[encode_path], temp_fsb, format, quality)?;
+        let fits = new_bank.samples[0].data_size as usize <= old_size;
+        let data = new_bank.sample_data(0)?.to_vec();
+        encoded = Some((data, new_bank.samples[0].clone(), quality, fits));
+        if fits {
+            break;
+        }
+    }
+    let _ = std::fs::remove_file(temp_fsb);
+    encoded.ok_or_else(|| std::io::Error::other("fsbankcl was not run"))
+}
+
+fn run_fsbankcl(
+    fsbankcl_path: &Path,
+    inputs: &[&str],
+    temp_fsb: &str,
+    format: &str,
+    quality: u32,
+) -> Result<FsbBank, std::io::Error> {
+    let fsbankcl_dir = fsbankcl_path.parent().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid fsbankcl path")
+    })?;
+    let quality = quality.to_string();
+    let output = Command::new(fsbankcl_path)
+        .current_dir(fsbankcl_dir)
+        .args(["-format", format, "-quality", &quality, "-o", temp_fsb])
+        .args(inputs)
+        .output()?;
 
     if !output.status.success() {
         return Err(std::io::Error::other(format!(
@@ -215,70 +1048,52 @@ pub fn replace_sample(
         )));
     }
 
-    let new_bank = FsbBank::load(&temp_fsb)?;
+    let new_bank = FsbBank::load(temp_fsb)?;
     if new_bank.samples.is_empty() {
         return Err(std::io::Error::new(
             std::io::ErrorKind::InvalidData,
             "fsbankcl produced empty FSB",
         ));
     }
+    Ok(new_bank)
+}
 
-    let new_data = new_bank.sample_data(0)?.to_vec();
-    let new_sample = &new_bank.samples[0];
-
-    if let Some(new_crc) = new_sample.vorbis_crc {
-        let mismatch =
-            new_sample.frequency != target_freq || new_sample.channels != target_channels;
-        if mismatch && !did_resample {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Format mismatch - install FFmpeg",
-            ));
-        }
-        if get_vorbis_setup_header(new_crc).is_none() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("Unsupported CRC 0x{:08X}", new_crc),
-            ));
-        }
-    }
-
-    let old_size = bank.samples[sample_index].data_size as usize;
-    let old_offset = bank.samples[sample_index].data_offset as usize;
-    let new_size = new_data.len();
-    let size_diff = new_size as i64 - old_size as i64;
-
-    let mut new_bank_data = Vec::new();
-    new_bank_data.extend_from_slice(&bank.data[..old_offset]);
-    new_bank_data.extend_from_slice(&new_data);
-    new_bank_data.extend_from_slice(&bank.data[old_offset + old_size..]);
+#[cfg(feature = "native-encode")]
+fn encode_native(
+    ffmpeg: &Path,
+    input: &Path,
+    channels: u32,
+    rate: u32,
+    qualities: &[u32],
+    old_size: usize,
+) -> Result<(Vec<u8>, Sample, u32, bool), std::io::Error> {
+    let pcm = decode_to_pcm(ffmpeg, input, channels, rate)?;
 
-    for s in &mut bank.samples {
-        if s.index > sample_index {
-            s.data_offset = (s.data_offset as i64 + size_diff) as u64;
+    let mut encoded = None;
+    for &quality in qualities {
+        let (data, sample) = encode::encode_vorbis(&pcm, channels, rate, quality as f32 / 100.0)?;
+        let fits = data.len() <= old_size;
+        encoded = Some((data, sample, quality, fits));
+        if fits {
+            break;
         }
     }
-
-    bank.samples[sample_index].data_size = new_size as u64;
-    bank.samples[sample_index].frequency = new_sample.frequency;
-    bank.samples[sample_index].channels = new_sample.channels;
-    bank.samples[sample_index].samples = new_sample.samples;
-    bank.samples[sample_index].vorbis_crc = new_sample.vorbis_crc;
-    bank.samples[sample_index].vorbis_seek_table = new_sample.vorbis_seek_table.clone();
-    bank.data_size = (bank.data_size as i64 + size_diff) as u32;
-    bank.data = new_bank_data;
-
-    let _ = std::fs::remove_file(&temp_fsb);
-    let _ = std::fs::remove_file(&temp_wav);
-    Ok(())
+    encoded.ok_or_else(|| std::io::Error::other("Encoder was not run"))
 }
 
-fn find_ffmpeg() -> Option<std::path::PathBuf> {
+// Looks at $CUMS_FFMPEG, then next to the executable, then the search path,
+// then winget's package folder
+pub fn find_ffmpeg() -> Option<std::path::PathBuf> {
+    if let Some(path) = std::env::var_os("CUMS_FFMPEG").filter(|p| !p.is_empty()) {
+        return Some(path.into());
+    }
     if let Ok(exe) = std::env::current_exe() {
         if let Some(dir) = exe.parent() {
-            let local = dir.join("ffmpeg.exe");
-            if local.exists() {
-                return Some(local);
+            for name in ["ffmpeg.exe", "ffmpeg"] {
+                let local = dir.join(name);
+                if local.is_file() {
+                    return Some(local);
+                }
             }
         }
     }
@@ -306,6 +1121,104 @@ fn find_ffmpeg() -> Option<std::path::PathBuf> {
     None
 }
 
+fn decode_to_pcm(
+    ffmpeg: &Path,
+    input: &Path,
+    channels: u32,
+    rate: u32,
+) -> Result<Vec<f32>, std::io::Error> {
+    let output = Command::new(ffmpeg)
+        .args(["-v", "error", "-i"])
+        .arg(input)
+        .args([
+            "-f",
+            "f32le",
+            "-ac",
+            &channels.to_string(),
+            "-ar",
+            &rate.to_string(),
+            "pipe:1",
+        ])
+        .output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    Ok(output
+        .stdout
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+fn probe_duration(ffmpeg: &Path, input: &str) -> Option<f32> {
+    let name = ffmpeg
+        .file_name()?
+        .to_string_lossy()
+        .replace("ffmpeg", "ffprobe");
+    let output = Command::new(ffmpeg.with_file_name(name))
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+            input,
+        ])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+pub(crate) const OGG_SERIAL: u32 = 0x12345678;
+pub(crate) const OGG_VENDOR: &str = "CUMS";
+
+// An Ogg page holds at most 255 lacing segments of up to 255 bytes each
+pub(crate) const OGG_MAX_SEGMENTS: usize = 255;
+
+pub(crate) fn ogg_segments(packet_len: usize) -> usize {
+    packet_len / 255 + 1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageFlush {
+    // End a page after this many audio packets
+    Packets(usize),
+    // End a page once its audio reaches this many bytes
+    Bytes(usize),
+}
+
+// Pages are also ended early whenever the next packet would not fit in the
+// segment table, so packets are never split across pages unless a single
+// packet is larger than a whole page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OggRebuildOptions {
+    pub page_flush: PageFlush,
+    // Stream serial number; chained streams need a different one each
+    pub serial: u32,
+    // Vendor string written into the comment header
+    pub vendor: String,
+}
+
+impl Default for OggRebuildOptions {
+    fn default() -> Self {
+        Self {
+            page_flush: PageFlush::Bytes(4096),
+            serial: OGG_SERIAL,
+            vendor: OGG_VENDOR.into(),
+        }
+    }
+}
+
+// Short and long block sizes as powers of two, 256 and 2048 like FMOD uses
+const VORBIS_BLOCKSIZES: (u8, u8) = (8, 11);
+
 fn generate_vorbis_id_header(sample_rate: u32, channels: u8) -> Vec<u8> {
     let mut h = Vec::with_capacity(30);
     h.push(0x01);
@@ -316,90 +1229,139 @@ fn generate_vorbis_id_header(sample_rate: u32, channels: u8) -> Vec<u8> {
     h.extend_from_slice(&0u32.to_le_bytes());
     h.extend_from_slice(&0u32.to_le_bytes());
     h.extend_from_slice(&0u32.to_le_bytes());
-    h.push(0xB8);
+    h.push(VORBIS_BLOCKSIZES.1 << 4 | VORBIS_BLOCKSIZES.0);
     h.push(0x01);
     h
 }
 
-fn generate_vorbis_comment_header() -> Vec<u8> {
+pub(crate) fn generate_vorbis_comment_header(vendor: &str, comments: &[String]) -> Vec<u8> {
     let mut h = Vec::new();
     h.push(0x03);
     h.extend_from_slice(b"vorbis");
-    let vendor = b"CUMS";
+    let vendor = vendor.as_bytes();
     h.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
     h.extend_from_slice(vendor);
-    h.extend_from_slice(&0u32.to_le_bytes());
+    h.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        h.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        h.extend_from_slice(comment.as_bytes());
+    }
     h.push(0x01);
     h
 }
 
-fn build_ogg_file(
-    id: &[u8],
-    comment: &[u8],
-    setup: &[u8],
-    raw: &[u8],
-) -> Result<Vec<u8>, std::io::Error> {
-    use ogg::writing::PacketWriter;
-    let mut output = Vec::new();
-    let serial = 0x12345678u32;
-    {
-        let mut writer = PacketWriter::new(&mut output);
-        writer
-            .write_packet(
-                id.to_vec(),
-                serial,
-                ogg::writing::PacketWriteEndInfo::EndPage,
-                0,
-            )
-            .map_err(|e| std::io::Error::other(e.to_string()))?;
-        writer
-            .write_packet(
-                comment.to_vec(),
-                serial,
-                ogg::writing::PacketWriteEndInfo::NormalPacket,
-                0,
-            )
-            .map_err(|e| std::io::Error::other(e.to_string()))?;
-        writer
-            .write_packet(
-                setup.to_vec(),
-                serial,
-                ogg::writing::PacketWriteEndInfo::EndPage,
-                0,
-            )
-            .map_err(|e| std::io::Error::other(e.to_string()))?;
-
-        let mut cursor = Cursor::new(raw);
-        let mut granule = 0u64;
-        let mut count = 0u32;
-
-        while cursor.position() < raw.len() as u64 {
-            let size = match cursor.read_u16::<LittleEndian>() {
-                Ok(s) => s as usize,
-                Err(_) => break,
-            };
-            if size == 0 || cursor.position() as usize + size > raw.len() {
-                break;
+// Muxes the FSB packet stream into OGG pages on demand, so only the page
+// currently being filled is held in memory
+pub struct OggStream<'a> {
+    writer: ogg::writing::PacketWriter<'a, Vec<u8>>,
+    raw: &'a [u8],
+    position: usize,
+    granule: u64,
+    // Granule of the last page, so the decoder trims the final packet
+    end_granule: u64,
+    options: OggRebuildOptions,
+    page: (usize, usize, usize),
+    consumed: usize,
+}
+
+impl<'a> OggStream<'a> {
+    pub(crate) fn new(
+        id: Vec<u8>,
+        comment: Vec<u8>,
+        setup: Vec<u8>,
+        raw: &'a [u8],
+        end_granule: u64,
+        options: OggRebuildOptions,
+    ) -> std::io::Result<Self> {
+        use ogg::writing::PacketWriteEndInfo;
+        let mut writer = ogg::writing::PacketWriter::new(Vec::new());
+        let serial = options.serial;
+        writer.write_packet(id, serial, PacketWriteEndInfo::EndPage, 0)?;
+        writer.write_packet(comment, serial, PacketWriteEndInfo::NormalPacket, 0)?;
+        writer.write_packet(setup, serial, PacketWriteEndInfo::EndPage, 0)?;
+        Ok(Self {
+            writer,
+            raw,
+            position: 0,
+            granule: 0,
+            end_granule,
+            options,
+            page: (0, 0, 0),
+            consumed: 0,
+        })
+    }
+
+    // Queues the next audio packet, returning false once the data runs out
+    fn write_next(&mut self) -> std::io::Result<bool> {
+        use ogg::writing::PacketWriteEndInfo;
+        let raw = self.raw;
+        let Some(size) = raw.get(self.position..self.position + 2) else {
+            return Ok(false);
+        };
+        let size = u16::from_le_bytes([size[0], size[1]]) as usize;
+        let start = self.position + 2;
+        if size == 0 || start + size > raw.len() {
+            return Ok(false);
+        }
+        self.position = start + size;
+
+        self.granule += 1024;
+        let (packets, bytes, segments) = &mut self.page;
+        *packets += 1;
+        *bytes += size;
+        *segments += ogg_segments(size);
+        // A truncated last packet is dropped, so the one before it ends the stream
+        let next = raw
+            .get(self.position..self.position + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .filter(|&next| next > 0 && self.position + 2 + next <= raw.len());
+        let full = match self.options.page_flush {
+            PageFlush::Packets(n) => *packets >= n,
+            PageFlush::Bytes(n) => *bytes >= n,
+        };
+        let end_info = match next {
+            None => {
+                if self.end_granule > 0 {
+                    self.granule = self.granule.min(self.end_granule);
+                }
+                PacketWriteEndInfo::EndStream
             }
-            let mut packet = vec![0u8; size];
-            if cursor.read_exact(&mut packet).is_err() {
-                break;
+            Some(next) if full || *segments + ogg_segments(next) > OGG_MAX_SEGMENTS => {
+                self.page = (0, 0, 0);
+                PacketWriteEndInfo::EndPage
             }
+            Some(_) => PacketWriteEndInfo::NormalPacket,
+        };
+        self.writer.write_packet(
+            &raw[start..start + size],
+            self.options.serial,
+            end_info,
+            self.granule,
+        )?;
+        Ok(true)
+    }
+}
 
-            granule += 1024;
-            count += 1;
-            let is_last = cursor.position() as usize >= raw.len() - 2;
-            let end_info = if is_last {
-                ogg::writing::PacketWriteEndInfo::EndStream
-            } else if count.is_multiple_of(10) {
-                ogg::writing::PacketWriteEndInfo::EndPage
-            } else {
-                ogg::writing::PacketWriteEndInfo::NormalPacket
-            };
-            writer
-                .write_packet(packet, serial, end_info, granule)
-                .map_err(|e| std::io::Error::other(e.to_string()))?;
+impl Read for OggStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let pending = &self.writer.inner()[self.consumed..];
+            if !pending.is_empty() {
+                let n = pending.len().min(buf.len());
+                buf[..n].copy_from_slice(&pending[..n]);
+                self.consumed += n;
+                if self.consumed == self.writer.inner().len() {
+                    self.writer.inner_mut().clear();
+                    self.consumed = 0;
+                }
+                return Ok(n);
+            }
+            if !self.write_next()? {
+                return Ok(0);
+            }
         }
     }
-    Ok(output)
 }
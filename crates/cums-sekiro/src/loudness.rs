@@ -0,0 +1,126 @@
+use std::f64::consts::PI;
+
+const REPLAYGAIN_REFERENCE_LUFS: f64 = -18.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    pub integrated_lufs: f64,
+    pub peak: f32,
+}
+
+impl Loudness {
+    // ReplayGain 2.0 values, referenced to -18 LUFS
+    pub fn replaygain_tags(&self) -> Vec<String> {
+        vec![
+            format!(
+                "REPLAYGAIN_TRACK_GAIN={:.2} dB",
+                REPLAYGAIN_REFERENCE_LUFS - self.integrated_lufs
+            ),
+            format!("REPLAYGAIN_TRACK_PEAK={:.6}", self.peak),
+        ]
+    }
+}
+
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[1] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[2] * y;
+        y
+    }
+}
+
+// ITU-R BS.1770 K-weighting, coefficients derived for any sample rate
+fn k_weighting(rate: u32) -> [Biquad; 2] {
+    let rate = rate as f64;
+
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let highpass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    [shelf, highpass]
+}
+
+fn lufs(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+pub fn measure_loudness(pcm: &[f32], channels: u32, rate: u32) -> Loudness {
+    let channels = channels.max(1) as usize;
+    let frames = pcm.len() / channels;
+    let peak = pcm.iter().fold(0f32, |m, s| m.max(s.abs()));
+
+    let mut squared = vec![0f64; frames];
+    for ch in 0..channels {
+        let mut filters = k_weighting(rate);
+        for (i, sq) in squared.iter_mut().enumerate() {
+            let mut x = pcm[i * channels + ch] as f64;
+            for f in &mut filters {
+                x = f.process(x);
+            }
+            *sq += x * x;
+        }
+    }
+
+    // 400 ms blocks with 75% overlap; clips shorter than one block are measured whole
+    let block = (rate as usize * 4 / 10).clamp(1, frames.max(1));
+    let step = (block / 4).max(1);
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    while start + block <= frames {
+        blocks.push(squared[start..start + block].iter().sum::<f64>() / block as f64);
+        start += step;
+    }
+
+    let gated = |threshold: f64| -> Vec<f64> {
+        blocks
+            .iter()
+            .copied()
+            .filter(|&z| z > 0.0 && lufs(z) > threshold)
+            .collect()
+    };
+    let mean = |v: &[f64]| v.iter().sum::<f64>() / v.len() as f64;
+
+    let absolute = gated(-70.0);
+    let integrated_lufs = if absolute.is_empty() {
+        -70.0
+    } else {
+        let relative = gated(lufs(mean(&absolute)) - 10.0);
+        if relative.is_empty() {
+            lufs(mean(&absolute))
+        } else {
+            lufs(mean(&relative))
+        }
+    };
+
+    Loudness {
+        integrated_lufs,
+        peak,
+    }
+}
@@ -0,0 +1,119 @@
+use crate::fsb::Sample;
+use std::io;
+
+pub const DEFAULT_NAME_TEMPLATE: &str = "{name}";
+
+// Placeholders for export file names. Numbers take an optional width, with a
+// leading zero to pad with zeros: `{index:04}_{name}_{freq}Hz` gives
+// `0007_footstep_44100Hz`. `{{` and `}}` are literal braces.
+pub const NAME_PLACEHOLDERS: [(&str, &str); 5] = [
+    ("name", "sample name, or sound_<index> if unnamed"),
+    ("index", "position in the bank"),
+    ("freq", "sample rate in Hz"),
+    ("channels", "channel count"),
+    ("samples", "length in samples"),
+];
+
+enum Part {
+    Text(String),
+    Field(String, Option<(bool, usize)>),
+}
+
+fn parse(template: &str) -> io::Result<Vec<Part>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '}' => return Err(invalid("Unmatched '}' in name template".into())),
+            '{' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => field.push(c),
+                        None => return Err(invalid("Unclosed '{' in name template".into())),
+                    }
+                }
+                let (key, spec) = match field.split_once(':') {
+                    Some((key, spec)) => (key, Some(spec)),
+                    None => (field.as_str(), None),
+                };
+                if !NAME_PLACEHOLDERS.iter().any(|(k, _)| *k == key) {
+                    return Err(invalid(format!("Unknown placeholder {{{}}}", key)));
+                }
+                let width = match spec {
+                    None => None,
+                    Some(_) if key == "name" => {
+                        return Err(invalid("{name} takes no width".into()));
+                    }
+                    Some(spec) => match spec.parse::<usize>() {
+                        Ok(width) if width <= 32 => Some((spec.starts_with('0'), width)),
+                        _ => return Err(invalid(format!("Bad width in {{{}}}", field))),
+                    },
+                };
+                parts.push(Part::Text(std::mem::take(&mut text)));
+                parts.push(Part::Field(key.to_string(), width));
+            }
+            c => text.push(c),
+        }
+    }
+    parts.push(Part::Text(text));
+    Ok(parts)
+}
+
+pub fn validate_name_template(template: &str) -> io::Result<()> {
+    parse(template).map(|_| ())
+}
+
+// Renders `template` for `sample` and appends `.ext`. Characters Windows or
+// Unix won't accept in a file name are replaced, so the result is always a
+// single path component.
+pub fn export_file_name(template: &str, sample: &Sample, ext: &str) -> io::Result<String> {
+    let mut out = String::new();
+    for part in parse(template)? {
+        match part {
+            Part::Text(text) => out.push_str(&text),
+            Part::Field(key, width) => {
+                let value = match key.as_str() {
+                    "name" => {
+                        out.push_str(&sample.display_name());
+                        continue;
+                    }
+                    "index" => sample.index as u64,
+                    "freq" => sample.frequency as u64,
+                    "channels" => sample.channels as u64,
+                    _ => sample.samples,
+                };
+                match width {
+                    Some((true, w)) => out.push_str(&format!("{:0w$}", value, w = w)),
+                    Some((false, w)) => out.push_str(&format!("{:w$}", value, w = w)),
+                    None => out.push_str(&value.to_string()),
+                }
+            }
+        }
+    }
+
+    let mut name: String = out
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    name = name.trim().trim_end_matches('.').to_string();
+    if name.is_empty() {
+        name = format!("sound_{}", sample.index);
+    }
+    Ok(format!("{}.{}", name, ext))
+}
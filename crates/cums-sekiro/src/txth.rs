@@ -0,0 +1,64 @@
+use crate::fsb::{Codec, FsbBank, Sample};
+use std::io;
+
+fn txth_codec(codec: Codec) -> Option<(&'static str, Option<u32>)> {
+    match codec {
+        Codec::Pcm8 => Some(("PCM8", Some(0x01))),
+        Codec::Pcm16 => Some(("PCM16LE", Some(0x02))),
+        Codec::Pcm24 => Some(("PCM24LE", Some(0x03))),
+        Codec::Pcm32 => Some(("PCM32LE", Some(0x04))),
+        Codec::PcmFloat => Some(("PCM_FLOAT_LE", Some(0x04))),
+        Codec::GcAdpcm => Some(("NGC_DSP", Some(0x02))),
+        Codec::ImaAdpcm => Some(("XBOX", None)),
+        Codec::Vag => Some(("PSX", Some(0x10))),
+        Codec::Xma => Some(("XMA2", None)),
+        Codec::Mpeg => Some(("MPEG", None)),
+        _ => None,
+    }
+}
+
+pub fn build_txth(bank: &FsbBank, sample: &Sample) -> io::Result<String> {
+    let (codec, interleave) = txth_codec(bank.codec).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("No TXTH codec for {:?}", bank.codec),
+        )
+    })?;
+
+    let mut lines = vec![
+        "# Generated by CUMS for vgmstream".to_string(),
+        format!(
+            "# Sample {} ({}) from a {:?} bank",
+            sample.index,
+            sample.name.as_deref().unwrap_or("unnamed"),
+            bank.version
+        ),
+        String::new(),
+        "# Decoder used for the raw bytes".to_string(),
+        format!("codec = {}", codec),
+        "# Channel count and playback rate from the sample header".to_string(),
+        format!("channels = {}", sample.channels),
+        format!("sample_rate = {}", sample.frequency),
+    ];
+    if let Some(interleave) = interleave.filter(|_| sample.channels > 1) {
+        lines.push("# Bytes of one channel before the next channel's data".to_string());
+        lines.push(format!("interleave = 0x{:02X}", interleave));
+    }
+    lines.push("# Audio starts at the first byte of the exported file".to_string());
+    lines.push("start_offset = 0x00".to_string());
+    lines.push(format!("data_size = 0x{:X}", sample.data_size));
+    lines.push("# Length in samples per channel".to_string());
+    lines.push(format!("num_samples = {}", sample.samples));
+    if let (Some(start), Some(end)) = (sample.loop_start, sample.loop_end) {
+        lines.push("# Loop region in samples, remove both lines to disable looping".to_string());
+        lines.push(format!("loop_start_sample = {}", start));
+        lines.push(format!("loop_end_sample = {}", end));
+    }
+    if bank.codec == Codec::GcAdpcm {
+        lines.push(
+            "# DSP coefficients live in the FSB header and must be added manually".to_string(),
+        );
+    }
+    lines.push(String::new());
+    Ok(lines.join("\n"))
+}
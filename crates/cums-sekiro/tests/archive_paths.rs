@@ -0,0 +1,123 @@
+mod common;
+
+use common::out_dir;
+use cums_sekiro::formats::{Bdt, Bhd5, Bhd5Bucket, Bhd5Entry, Bnd4, Bnd4Entry, ImageFormat};
+
+fn bnd(names: &[&str]) -> Bnd4 {
+    Bnd4 {
+        version: "00000000".into(),
+        flags: 0,
+        big_endian: false,
+        bit_big_endian: false,
+        unicode: true,
+        extended: 4,
+        entries: names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Bnd4Entry {
+                flags: 0,
+                id: i as i32,
+                name: name.to_string(),
+                uncompressed_size: 1,
+                compressed_size: 1,
+                data: vec![i as u8],
+                compression: None,
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn bnd4_preserves_directories() {
+    let dir = out_dir("bnd4_preserve");
+    let archive = bnd(&[r"N:\FDP\data\sound\main.fsb", "sound/other.fsb"]);
+    assert_eq!(archive.extract_to(&dir, true).expect("extract"), 2);
+    assert_eq!(
+        std::fs::read(dir.join("FDP/data/sound/main.fsb")).expect("nested"),
+        [0]
+    );
+    assert!(dir.join("sound/other.fsb").is_file());
+}
+
+#[test]
+fn bnd4_flattens_by_default() {
+    let dir = out_dir("bnd4_flat");
+    let archive = bnd(&[r"N:\FDP\data\sound\main.fsb"]);
+    archive.extract_to(&dir, false).expect("extract");
+    assert!(dir.join("main.fsb").is_file());
+}
+
+#[test]
+fn escaping_paths_stay_inside_output() {
+    let dir = out_dir("bnd4_escape");
+    let archive = bnd(&[r"..\..\evil.fsb", "/etc/abs.fsb", r"a\..\..\b.fsb", ".."]);
+    assert_eq!(archive.extract_to(&dir, true).expect("extract"), 3);
+    assert!(dir.join("evil.fsb").is_file());
+    assert!(dir.join("etc/abs.fsb").is_file());
+    assert!(dir.join("a/b.fsb").is_file());
+    assert!(!dir.parent().unwrap().join("evil.fsb").exists());
+}
+
+#[test]
+fn bdt_names_entries_from_known_paths() {
+    let dir = out_dir("bdt");
+    let salt = b"salt".to_vec();
+    let known = "/sound/voice.fsb".to_string();
+    let entry = |hash: u32, offset: u64| Bhd5Entry {
+        hash,
+        size: 4,
+        offset,
+        padded_size: 4,
+        aes_key: None,
+        aes_ranges: Vec::new(),
+    };
+    let bhd = Bhd5 {
+        version: 1,
+        salt: salt.clone(),
+        buckets: vec![Bhd5Bucket {
+            entries: vec![
+                entry(Bhd5::hash_path(&known, &salt), 0),
+                entry(0xDEADBEEF, 4),
+            ],
+        }],
+        big_endian: false,
+    };
+    let data = *b"aaaabbbb";
+
+    let written = Bdt::new(&data)
+        .extract_to(&bhd, &[known], &dir, true)
+        .expect("extract");
+    assert_eq!(written, 2);
+    assert_eq!(
+        std::fs::read(dir.join("sound/voice.fsb")).expect("named"),
+        b"aaaa"
+    );
+    assert_eq!(
+        std::fs::read(dir.join("DEADBEEF.bin")).expect("unnamed"),
+        b"bbbb"
+    );
+}
+
+#[test]
+fn bnd4_lists_image_entries_by_magic() {
+    let mut bnd = bnd(&["cover.png", "icon.dds", "photo.jpg", "sound.fsb", "tiny"]);
+    bnd.entries[0].data = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+    bnd.entries[1].data = b"DDS \x7c\0\0\0".to_vec();
+    bnd.entries[2].data = vec![0xFF, 0xD8, 0xFF, 0xE0];
+    bnd.entries[3].data = b"FSB5\x01\0\0\0".to_vec();
+    bnd.entries[4].data = vec![0x89];
+
+    let images: Vec<(&str, ImageFormat)> = bnd
+        .image_entries()
+        .into_iter()
+        .map(|(e, f)| (e.name.as_str(), f))
+        .collect();
+    assert_eq!(
+        images,
+        [
+            ("cover.png", ImageFormat::Png),
+            ("icon.dds", ImageFormat::Dds),
+            ("photo.jpg", ImageFormat::Jpeg),
+        ]
+    );
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use cums_sekiro::{audio::at9_block_info, Codec, FsbBank, FsbError};
+
+// 48 kHz stereo, 512-byte frames, four frames per superframe
+const CONFIG: [u8; 4] = [0xFE, 0x72, 0x3F, 0xF0];
+
+fn chunk_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+#[test]
+fn at9_config_is_read_from_its_chunk() {
+    let mut chunk = vec![0u8; 8];
+    chunk.extend_from_slice(&CONFIG);
+    let bank = FsbBank::from_bytes(common::fsb5_with_chunk(13, 2, 4096, 9, &chunk, &[0; 64]))
+        .expect("parse");
+    assert_eq!(bank.codec, Codec::At9);
+    assert_eq!(bank.samples[0].at9_config, Some(CONFIG));
+    assert_eq!(at9_block_info(CONFIG), Some((2048, 1024)));
+}
+
+#[test]
+fn at9_extracts_to_riff() {
+    let bank = FsbBank::from_bytes(common::fsb5_with_chunk(13, 2, 4096, 9, &CONFIG, &[0; 64]))
+        .expect("parse");
+    let (at9, ext) = bank.extract_audio(0).expect("extract");
+    assert_eq!(ext, "at9");
+    assert_eq!(&at9[0..4], b"RIFF");
+    assert_eq!(chunk_u32(&at9, 4) as usize, at9.len() - 8);
+    assert_eq!(&at9[8..12], b"WAVE");
+
+    assert_eq!(&at9[12..16], b"fmt ");
+    let fmt = &at9[20..20 + chunk_u32(&at9, 16) as usize];
+    assert_eq!(u16::from_le_bytes([fmt[0], fmt[1]]), 0xFFFE);
+    assert_eq!(u16::from_le_bytes([fmt[12], fmt[13]]), 2048);
+    assert_eq!(&fmt[44..48], &CONFIG);
+
+    let fact = 20 + fmt.len();
+    assert_eq!(&at9[fact..fact + 4], b"fact");
+    assert_eq!(chunk_u32(&at9, fact + 8), 4096);
+
+    let data = fact + 8 + chunk_u32(&at9, fact + 4) as usize;
+    assert_eq!(&at9[data..data + 4], b"data");
+    assert_eq!(chunk_u32(&at9, data + 4), 64);
+}
+
+#[test]
+fn at9_config_survives_a_save() {
+    let mut chunk = vec![7u8; 8];
+    chunk.extend_from_slice(&CONFIG);
+    let bank = FsbBank::from_bytes(common::fsb5_with_chunk(13, 2, 4096, 9, &chunk, &[0; 64]))
+        .expect("parse");
+    let path = common::out_dir("at9_save").join("saved.fsb");
+    bank.save(&path, false).expect("save");
+
+    let reloaded = FsbBank::from_bytes(std::fs::read(&path).expect("read")).expect("reload");
+    assert_eq!(reloaded.samples[0].at9_config, Some(CONFIG));
+    assert_eq!(reloaded.samples[0].chunks, [(9, chunk)]);
+    assert_eq!(reloaded.extract_audio(0).expect("extract").1, "at9");
+}
+
+#[test]
+fn at9_without_config_stays_raw() {
+    let bank = FsbBank::from_bytes(common::fsb5_with_chunk(13, 2, 4096, 9, &[0; 4], &[0; 64]))
+        .expect("parse");
+    assert_eq!(bank.samples[0].at9_config, None);
+    assert_eq!(bank.extract_audio(0).expect("extract").1, "bin");
+}
+
+#[test]
+fn at9_bad_index_is_an_error() {
+    let bank = FsbBank::from_bytes(common::fsb5_with_chunk(13, 2, 4096, 9, &CONFIG, &[0; 64]))
+        .expect("parse");
+    let err = bank.extract_audio(3).expect_err("no sample 3");
+    assert!(matches!(
+        FsbError::from(err),
+        FsbError::SampleNotFound { index: 3 }
+    ));
+}
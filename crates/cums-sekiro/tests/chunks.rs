@@ -0,0 +1,73 @@
+mod common;
+
+use cums_sekiro::{FsbBank, FsbError};
+
+// One PCM16 sample whose chunk list is `chunks` headers with the more-chunks
+// bit stuck on, inside a sample header region of `header_chunks` slots
+fn stuck_chunks(chunks: usize, header_chunks: usize) -> Vec<u8> {
+    let headers_size = 8 + header_chunks as u32 * 4;
+    let mut out = Vec::new();
+    out.extend_from_slice(b"FSB5");
+    for field in [1, 1, headers_size, 0, 32, 2, 0, 0] {
+        out.extend_from_slice(&u32::to_le_bytes(field));
+    }
+    out.resize(common::HEADER_SIZE, 0);
+    let mode: u64 = 1 | (8 << 1) | (16 << 34);
+    out.extend_from_slice(&mode.to_le_bytes());
+    for _ in 0..chunks {
+        out.extend_from_slice(&1u32.to_le_bytes());
+    }
+    out.resize(common::HEADER_SIZE + headers_size as usize, 0);
+    out.extend_from_slice(&[0; 32]);
+    out
+}
+
+fn fsb_error(bytes: Vec<u8>) -> FsbError {
+    FsbBank::from_bytes(bytes).expect_err("corrupt chunk list")
+}
+
+#[test]
+fn stuck_more_chunks_bit_stops_at_headers_end() {
+    assert!(matches!(
+        fsb_error(stuck_chunks(4, 4)),
+        FsbError::ChunkOverrun { index: 0 }
+    ));
+}
+
+#[test]
+fn runaway_chunk_list_is_capped() {
+    assert!(matches!(
+        fsb_error(stuck_chunks(64, 64)),
+        FsbError::TooManyChunks {
+            index: 0,
+            limit: 32
+        }
+    ));
+}
+
+#[test]
+fn chunk_larger_than_headers_is_rejected() {
+    let mut bytes = stuck_chunks(1, 4);
+    // Chunk claiming 0x100 bytes with only 12 left in the headers
+    let offset = common::HEADER_SIZE + 8;
+    bytes[offset..offset + 4].copy_from_slice(&(0x100u32 << 1).to_le_bytes());
+    assert!(matches!(
+        fsb_error(bytes),
+        FsbError::ChunkOverrun { index: 0 }
+    ));
+}
+
+#[test]
+fn chunks_are_exposed_by_type() {
+    let bank = FsbBank::from_bytes(common::fixture("pcm.fsb5")).expect("parse");
+    let types: Vec<u8> = bank.samples[1].chunks.iter().map(|(t, _)| *t).collect();
+    assert_eq!(types, [3]);
+    let loop_chunk = bank.samples[1].chunk(3).expect("loop chunk");
+    assert_eq!(loop_chunk, [4, 0, 0, 0, 12, 0, 0, 0]);
+    assert!(bank.samples[0].chunk(3).is_none());
+
+    let comment = b"free comment";
+    let bytes = common::fsb5_with_chunk(2, 1, 16, 10, comment, &[0; 32]);
+    let bank = FsbBank::from_bytes(bytes).expect("parse");
+    assert_eq!(bank.samples[0].chunk(10), Some(&comment[..]));
+}
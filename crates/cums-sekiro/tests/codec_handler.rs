@@ -0,0 +1,41 @@
+mod common;
+
+use common::fsb5_pcm;
+use cums_sekiro::{has_codec_handler, register_codec_handler, Codec, CodecHandler, FsbBank};
+use std::io;
+
+struct Registering;
+
+impl CodecHandler for Registering {
+    fn can_handle(&self, codec: Codec) -> bool {
+        codec == Codec::Pcm16
+    }
+
+    fn extract(&self, bank: &FsbBank, index: usize) -> io::Result<(Vec<u8>, &'static str)> {
+        register_codec_handler(Box::new(Late));
+        Ok((bank.sample_data(index)?.to_vec(), "raw"))
+    }
+}
+
+struct Late;
+
+impl CodecHandler for Late {
+    fn can_handle(&self, codec: Codec) -> bool {
+        codec == Codec::Pcm8
+    }
+
+    fn extract(&self, _: &FsbBank, _: usize) -> io::Result<(Vec<u8>, &'static str)> {
+        Ok((Vec::new(), "raw"))
+    }
+}
+
+#[test]
+fn handlers_can_register_while_extracting() {
+    let bank = FsbBank::from_bytes(fsb5_pcm(&[0, 16], 32)).expect("parse");
+    register_codec_handler(Box::new(Registering));
+
+    let (data, ext) = bank.extract_audio(1).expect("extract");
+    assert_eq!(ext, "raw");
+    assert_eq!(data, bank.sample_data(1).expect("data"));
+    assert!(has_codec_handler(Codec::Pcm8));
+}
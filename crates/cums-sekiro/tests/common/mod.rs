@@ -0,0 +1,65 @@
+#![allow(dead_code)]
+
+pub const HEADER_SIZE: usize = 60;
+
+// Uncompressed FSB5 of mono 44.1 kHz PCM16 samples. Offsets are relative to
+// the data section and must be multiples of 16.
+pub fn fsb5_pcm(offsets: &[u64], data_size: u32) -> Vec<u8> {
+    let count = offsets.len() as u32;
+    let mut out = Vec::new();
+    out.extend_from_slice(b"FSB5");
+    for field in [1, count, count * 8, 0, data_size, 2, 0, 0] {
+        out.extend_from_slice(&u32::to_le_bytes(field));
+    }
+    out.resize(HEADER_SIZE, 0);
+    for &offset in offsets {
+        let frames = 16u64;
+        let mode = (8 << 1) | ((offset / 16) << 6) | (frames << 34);
+        out.extend_from_slice(&mode.to_le_bytes());
+    }
+    out.extend((0..data_size).map(|i| i as u8));
+    out
+}
+
+// FSB5 with a single sample of `codec` carrying one chunk of `chunk_type`
+pub fn fsb5_with_chunk(
+    codec: u32,
+    channels: u32,
+    samples: u64,
+    chunk_type: u32,
+    chunk: &[u8],
+    data: &[u8],
+) -> Vec<u8> {
+    let headers_size = 8 + 4 + chunk.len() as u32;
+    let mut out = Vec::new();
+    out.extend_from_slice(b"FSB5");
+    for field in [1, 1, headers_size, 0, data.len() as u32, codec, 0, 0] {
+        out.extend_from_slice(&u32::to_le_bytes(field));
+    }
+    out.resize(HEADER_SIZE, 0);
+    let stereo = (channels > 1) as u64;
+    let mode = 1 | (8 << 1) | (stereo << 5) | (samples << 34);
+    out.extend_from_slice(&mode.to_le_bytes());
+    let chunk_header = ((chunk.len() as u32) << 1) | (chunk_type << 25);
+    out.extend_from_slice(&chunk_header.to_le_bytes());
+    out.extend_from_slice(chunk);
+    out.extend_from_slice(data);
+    out
+}
+
+pub fn fixture(name: &str) -> Vec<u8> {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+// Fresh scratch directory per test, removed from any previous run
+pub fn out_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("cums-test-{}", std::process::id()))
+        .join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("create scratch dir");
+    dir
+}
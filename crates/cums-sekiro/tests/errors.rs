@@ -0,0 +1,29 @@
+mod common;
+
+use common::fixture;
+use cums_sekiro::{rebuild_ogg, FsbBank, FsbError};
+
+#[test]
+fn parse_errors_are_typed() {
+    assert!(matches!(
+        FsbBank::from_bytes(b"FSB5".to_vec()),
+        Err(FsbError::TooSmall)
+    ));
+    assert!(matches!(
+        FsbBank::from_bytes(common::fsb5_with_chunk(99, 1, 16, 3, &[0; 8], &[0; 32])),
+        Err(FsbError::UnknownCodec(99))
+    ));
+}
+
+#[test]
+fn sample_errors_are_typed() {
+    let bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    assert!(matches!(
+        bank.sample_data(7),
+        Err(FsbError::SampleNotFound { index: 7 })
+    ));
+    assert!(matches!(
+        rebuild_ogg(&bank, &bank.samples[0]),
+        Err(FsbError::NotVorbis)
+    ));
+}
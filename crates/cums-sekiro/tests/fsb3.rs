@@ -0,0 +1,92 @@
+use cums_sekiro::{Codec, FsbBank, Version};
+
+// FSB3 bank with one sample per (name, mode, channels, data). `entry_size` is
+// 80 for FSB 3.1 sample headers and 68 for 3.0.
+fn fsb3(entry_size: u16, samples: &[(&str, u32, u16, u32, &[u8])]) -> Vec<u8> {
+    let mut headers = Vec::new();
+    let mut data = Vec::new();
+    for (name, mode, channels, sample_count, bytes) in samples {
+        let start = headers.len();
+        headers.extend_from_slice(&entry_size.to_le_bytes());
+        let mut name_buf = [0u8; 30];
+        name_buf[..name.len()].copy_from_slice(name.as_bytes());
+        headers.extend_from_slice(&name_buf);
+        for field in [*sample_count, bytes.len() as u32, 4, 12, *mode, 22050] {
+            headers.extend_from_slice(&field.to_le_bytes());
+        }
+        headers.extend_from_slice(&[255, 0, 128, 0, 128, 0]);
+        headers.extend_from_slice(&channels.to_le_bytes());
+        headers.resize(start + entry_size as usize, 0);
+        data.extend_from_slice(bytes);
+    }
+    let mut out = b"FSB3".to_vec();
+    for field in [
+        samples.len() as u32,
+        headers.len() as u32,
+        data.len() as u32,
+        0x0003_0001,
+        0,
+    ] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    out.extend(headers);
+    out.extend(data);
+    out
+}
+
+#[test]
+fn fsb3_pcm_bank_parses() {
+    let pcm: Vec<u8> = (0..64).collect();
+    // 16-bit stereo with a normal loop, then 16-bit mono without one
+    let bytes = fsb3(
+        80,
+        &[
+            ("left", 0x52, 2, 16, &pcm[..]),
+            ("right", 0x31, 1, 16, &pcm[..32]),
+        ],
+    );
+    let bank = FsbBank::from_bytes(bytes).expect("parse");
+    assert_eq!(bank.version, Version::Fsb3);
+    assert_eq!(bank.codec, Codec::Pcm16);
+    assert_eq!(bank.samples.len(), 2);
+
+    let left = &bank.samples[0];
+    assert_eq!(left.name.as_deref(), Some("left"));
+    assert_eq!(
+        (left.channels, left.frequency, left.samples),
+        (2, 22050, 16)
+    );
+    assert_eq!((left.loop_start, left.loop_end), (Some(4), Some(12)));
+    assert_eq!(bank.sample_data(0).expect("data"), &pcm[..]);
+
+    let right = &bank.samples[1];
+    assert_eq!((right.channels, right.loop_start), (1, None));
+    assert_eq!(bank.sample_data(1).expect("data"), &pcm[..32]);
+
+    let (wav, ext) = bank.extract_audio(1).expect("extract");
+    assert_eq!(ext, "wav");
+    assert_eq!(&wav[44..], &pcm[..32]);
+
+    let err = bank
+        .save(std::env::temp_dir().join("cums-fsb3.fsb"), false)
+        .expect_err("FSB3 save");
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn fsb3_0_mpeg_bank_counts_frames() {
+    // Three silent MPEG-1 Layer III frames, 128 kbps at 44.1 kHz stereo
+    let mut frames = Vec::new();
+    for _ in 0..3 {
+        let start = frames.len();
+        frames.extend_from_slice(&0xFFFB_9000u32.to_be_bytes());
+        frames.resize(start + 417, 0);
+    }
+    let bytes = fsb3(68, &[("music", 0x240, 2, 0, &frames)]);
+    let bank = FsbBank::from_bytes(bytes).expect("parse");
+    assert_eq!(bank.codec, Codec::Mpeg);
+    assert_eq!(bank.samples[0].samples, 3 * 1152);
+    let (mp3, ext) = bank.extract_audio(0).expect("extract");
+    assert_eq!(ext, "mp3");
+    assert_eq!(&mp3[..4], &0xFFFB_9000u32.to_be_bytes());
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use common::out_dir;
+use cums_sekiro::{Codec, FsbBank, FsbError};
+
+// FSB5 with one GC ADPCM sample and its DSP coefficient chunk. Predictor 0 is
+// (2048, 0), which adds each delta to the previous sample.
+fn gcadpcm_bank(channels: u32, samples: u64, data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::new();
+    for _ in 0..channels {
+        let start = chunk.len();
+        chunk.extend_from_slice(&2048i16.to_be_bytes());
+        chunk.resize(start + 0x2E, 0);
+    }
+    common::fsb5_with_chunk(6, channels, samples, 7, &chunk, data)
+}
+
+#[test]
+fn gcadpcm_decodes_to_wav() {
+    // One frame: scale 1, deltas +1 +2 then zeros
+    let mut data = vec![0x00, 0x12, 0, 0, 0, 0, 0, 0];
+    data.resize(32, 0);
+    let bank = FsbBank::from_bytes(gcadpcm_bank(1, 20, &data)).expect("parse");
+    assert_eq!(bank.codec, Codec::GcAdpcm);
+    let coeffs = bank.samples[0].dsp_coeffs.as_ref().expect("coefficients");
+    assert_eq!(coeffs.len(), 1);
+    assert_eq!(coeffs[0][0], 2048);
+
+    let (wav, ext) = bank.extract_audio(0).expect("extract");
+    assert_eq!(ext, "wav");
+    let pcm = &wav[44..];
+    assert_eq!(pcm.len(), 20 * 2);
+    let first: Vec<i16> = pcm[..8]
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    assert_eq!(first, [1, 3, 3, 3]);
+}
+
+#[test]
+fn stereo_gcadpcm_length_matches_header() {
+    let data = vec![0u8; 64];
+    let bank = FsbBank::from_bytes(gcadpcm_bank(2, 20, &data)).expect("parse");
+    let (wav, _) = bank.extract_audio(0).expect("extract");
+    assert_eq!(wav.len() - 44, 20 * 2 * 2);
+}
+
+#[test]
+fn dsp_coefficients_survive_save() {
+    let bank = FsbBank::from_bytes(gcadpcm_bank(2, 20, &[0u8; 64])).expect("parse");
+    let path = out_dir("gcadpcm").join("gcadpcm.fsb");
+    bank.save(&path, false).expect("save");
+    let reloaded = FsbBank::load(&path).expect("reload");
+    assert_eq!(reloaded.samples[0].dsp_coeffs, bank.samples[0].dsp_coeffs);
+    assert_eq!(
+        reloaded.extract_audio(0).expect("extract").0,
+        bank.extract_audio(0).expect("extract").0
+    );
+}
+
+#[test]
+fn gcadpcm_bad_index_is_an_error() {
+    let bank = FsbBank::from_bytes(gcadpcm_bank(1, 20, &[0; 32])).expect("parse");
+    let err = bank.extract_audio(5).expect_err("no sample 5");
+    assert!(matches!(
+        FsbError::from(err),
+        FsbError::SampleNotFound { index: 5 }
+    ));
+}
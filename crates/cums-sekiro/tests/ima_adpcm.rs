@@ -0,0 +1,65 @@
+mod common;
+
+use cums_sekiro::audio::decode_ima_adpcm;
+use cums_sekiro::{Codec, FsbBank, FsbError};
+
+// Predictor 100, step index 10, then 64 nibbles low nibble first
+fn one_block() -> Vec<u8> {
+    let mut block = vec![100, 0, 10, 0, 0x77, 0x77, 0x07, 0x9F];
+    for _ in 0..7 {
+        block.extend_from_slice(&[0x31, 0xC8, 0x20, 0x00]);
+    }
+    block
+}
+
+const REFERENCE: [i16; 64] = [
+    134, 210, 375, 730, 1495, 1604, 112, -527, 55, 1288, 1128, -183, -7, 794, 939, 1071, 1431,
+    2197, 2098, 1284, 1393, 1890, 1980, 2062, 2285, 2761, 2700, 2195, 2263, 2571, 2627, 2678, 2816,
+    3110, 3072, 2759, 2801, 2992, 3026, 3057, 3142, 3324, 3301, 3107, 3133, 3251, 3272, 3291, 3343,
+    3456, 3442, 3322, 3338, 3411, 3424, 3436, 3469, 3539, 3530, 3456, 3466, 3511, 3519, 3526,
+];
+
+#[test]
+fn mono_block_matches_reference() {
+    assert_eq!(decode_ima_adpcm(&one_block(), 1), REFERENCE);
+}
+
+#[test]
+fn stereo_blocks_interleave_every_four_bytes() {
+    let mono = one_block();
+    let silent = [0u8; 36];
+    let mut block = [&mono[..4], &silent[..4]].concat();
+    for group in mono[4..].chunks(4) {
+        block.extend_from_slice(group);
+        block.extend_from_slice(&silent[..4]);
+    }
+    let pcm = decode_ima_adpcm(&block, 2);
+    assert_eq!(pcm.len(), 128);
+    let left: Vec<i16> = pcm.iter().step_by(2).copied().collect();
+    assert_eq!(left, REFERENCE);
+    let right = decode_ima_adpcm(&silent, 1);
+    assert!(pcm.iter().skip(1).step_by(2).eq(right.iter()));
+}
+
+#[test]
+fn ima_adpcm_extracts_as_wav() {
+    let mut bytes = common::fsb5_pcm(&[0], 36);
+    let start = bytes.len() - 36;
+    bytes[start..].copy_from_slice(&one_block());
+    bytes[24..28].copy_from_slice(&7u32.to_le_bytes());
+    let bank = FsbBank::from_bytes(bytes).expect("parse");
+    assert_eq!(bank.codec, Codec::ImaAdpcm);
+    assert!(matches!(
+        FsbError::from(bank.extract_audio(1).expect_err("no sample 1")),
+        FsbError::SampleNotFound { index: 1 }
+    ));
+
+    let (wav, ext) = bank.extract_audio(0).expect("extract");
+    assert_eq!(ext, "wav");
+    // The header gives 16 samples, so the block is cut short
+    let pcm: Vec<i16> = wav[44..]
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    assert_eq!(pcm, REFERENCE[..16]);
+}
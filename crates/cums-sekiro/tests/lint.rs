@@ -0,0 +1,98 @@
+mod common;
+
+use common::{fixture, out_dir};
+use cums_sekiro::{Codec, Encryption, FsbBank, GameGuess, GameProfile, LintIssue};
+
+fn lint(file: &str, game: GameProfile) -> Vec<LintIssue> {
+    FsbBank::from_bytes(fixture(file))
+        .expect(file)
+        .lint_for_game(game)
+}
+
+#[test]
+fn plaintext_pcm_bank_fails_sekiro_rules() {
+    let issues = lint("pcm.fsb5", GameProfile::Sekiro);
+    assert_eq!(
+        issues,
+        [
+            LintIssue::WrongCodec {
+                game: GameProfile::Sekiro,
+                expected: Codec::Vorbis,
+                actual: Codec::Pcm16,
+            },
+            LintIssue::WrongEncryption {
+                game: GameProfile::Sekiro,
+                expected: Encryption::Aes,
+                actual: Encryption::None,
+            },
+        ]
+    );
+    assert_eq!(
+        issues[0].to_string(),
+        "Codec is Pcm16 but Sekiro only loads Vorbis"
+    );
+    assert_eq!(
+        issues[1].to_string(),
+        "This bank is plaintext but Sekiro expects encryption"
+    );
+}
+
+#[test]
+fn encryption_rules_follow_the_game() {
+    let encrypted = |issues: Vec<LintIssue>| {
+        issues
+            .iter()
+            .any(|i| matches!(i, LintIssue::WrongEncryption { .. }))
+    };
+    assert!(!encrypted(lint("aes.fsb5", GameProfile::DarkSouls3)));
+    assert!(encrypted(lint("fsbext.fsb5", GameProfile::DarkSouls3)));
+    assert!(encrypted(lint("aes.fsb5", GameProfile::DarkSouls2)));
+    assert!(!encrypted(lint("pcm.fsb5", GameProfile::DarkSouls2)));
+}
+
+#[test]
+fn fsb4_bank_is_checked_against_dark_souls_1() {
+    let issues = lint("pcm.fsb4", GameProfile::DarkSouls1);
+    assert!(matches!(
+        issues.as_slice(),
+        [LintIssue::WrongCodec {
+            expected: Codec::Mpeg,
+            ..
+        }]
+    ));
+
+    let issues = lint("pcm.fsb4", GameProfile::Sekiro);
+    assert_eq!(
+        issues[0].to_string(),
+        "This bank is FSB4 but Sekiro only loads FSB5"
+    );
+}
+
+#[test]
+fn game_is_guessed_from_the_container() {
+    let vorbis = common::fsb5_with_chunk(15, 1, 1024, 11, &[0; 4], &[0; 16]);
+    let bank = FsbBank::from_bytes(vorbis).expect("vorbis");
+    assert_eq!(bank.guess_game(), GameGuess::Game(GameProfile::DarkSouls2));
+    assert_eq!(bank.encryption_scheme(), "Plaintext");
+
+    let path = out_dir("guess_game").join("aes.fsb");
+    bank.save_as(&path, Encryption::Aes).expect("save");
+    let aes = FsbBank::load(&path).expect("reload");
+    assert_eq!(
+        aes.guess_game(),
+        GameGuess::Either(GameProfile::Sekiro, GameProfile::DarkSouls3)
+    );
+    assert_eq!(aes.guess_game().label(), "Sekiro or Dark Souls 3");
+
+    // Setting the MPEG bit in the FSB4 header flags
+    let mut mpeg = fixture("pcm.fsb4");
+    mpeg[0x16] |= 0x20;
+    let bank = FsbBank::from_bytes(mpeg).expect("mpeg");
+    assert_eq!(bank.codec, Codec::Mpeg);
+    assert_eq!(bank.guess_game(), GameGuess::Game(GameProfile::DarkSouls1));
+
+    let pcm = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("pcm");
+    assert_eq!(pcm.guess_game(), GameGuess::Unknown);
+    let fsbext = FsbBank::from_bytes(fixture("fsbext.fsb5")).expect("fsbext");
+    assert_eq!(fsbext.encryption_scheme(), "fsbext (whole file)");
+}
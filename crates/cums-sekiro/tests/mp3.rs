@@ -0,0 +1,78 @@
+mod common;
+
+use cums_sekiro::audio::{count_mp3_samples, Mp3FrameHeader};
+use cums_sekiro::{AudioSettings, FsbBank};
+
+// Silent Layer III frames: MPEG-1 128 kbps 44.1 kHz stereo is 0xFFFB9000,
+// MPEG-2 64 kbps 22.05 kHz mono is 0xFFF380C0
+fn frames(header: u32, count: usize) -> Vec<u8> {
+    let size = Mp3FrameHeader::parse(header).expect("header").frame_size;
+    let mut out = Vec::new();
+    for _ in 0..count {
+        let start = out.len();
+        out.extend_from_slice(&header.to_be_bytes());
+        out.resize(start + size, 0);
+    }
+    out
+}
+
+#[test]
+fn every_frame_is_counted() {
+    let data = frames(0xFFFB_9000, 12);
+    assert_eq!(count_mp3_samples(&data, 2), (12 * 1152, 44100));
+
+    let data = frames(0xFFF3_80C0, 5);
+    assert_eq!(count_mp3_samples(&data, 1), (5 * 576, 22050));
+}
+
+#[test]
+fn leading_junk_is_skipped() {
+    let mut data = b"ID3junk".to_vec();
+    data.extend(frames(0xFFFB_9000, 3));
+    assert_eq!(count_mp3_samples(&data, 2), (3 * 1152, 44100));
+}
+
+// Stands in for ffmpeg: records its arguments and "encodes" to out.mp3
+#[cfg(unix)]
+const FAKE_FFMPEG: &str = "#!/bin/sh
+dir=$(dirname \"$0\")
+echo \"$@\" > \"$dir/args.txt\"
+for last; do :; done
+cp \"$dir/out.mp3\" \"$last\"
+";
+
+#[cfg(unix)]
+#[test]
+fn fsb4_replacement_settings_reencode_mp3() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = common::out_dir("mp3_settings");
+    let ffmpeg = dir.join("ffmpeg");
+    std::fs::write(&ffmpeg, FAKE_FFMPEG).unwrap();
+    std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let _ = std::fs::remove_file(dir.join("args.txt"));
+    std::env::set_var("CUMS_FFMPEG", &ffmpeg);
+
+    let input = dir.join("input.mp3");
+    std::fs::write(&input, frames(0xFFFB_9000, 4)).unwrap();
+    // An octave up at the same output rate halves the length
+    std::fs::write(dir.join("out.mp3"), frames(0xFFF3_80C0, 2)).unwrap();
+
+    let mut bank = FsbBank::from_bytes(common::fixture("pcm.fsb4")).expect("parse");
+    bank.replace_sample(0, &input, &dir).expect("replace");
+    assert!(!dir.join("args.txt").exists(), "plain MP3 is copied as-is");
+    assert_eq!(bank.samples[0].frequency, 44100);
+    assert_eq!(bank.samples[0].samples, 4 * 1152);
+
+    let settings = AudioSettings {
+        pitch_semitones: 12.0,
+        ..Default::default()
+    };
+    bank.replace_sample_with_settings(0, &input, &dir, &settings)
+        .expect("replace");
+    let args = std::fs::read_to_string(dir.join("args.txt")).expect("ffmpeg ran");
+    assert!(args.contains("-af asetrate=48000*2.0000"), "{}", args);
+    assert_eq!(bank.samples[0].frequency, 22050);
+    assert_eq!(bank.samples[0].channels, 1);
+    assert_eq!(bank.samples[0].samples, 2 * 576);
+}
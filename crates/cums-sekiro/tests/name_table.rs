@@ -0,0 +1,70 @@
+use cums_sekiro::FsbBank;
+
+// FSB5 with one 16-byte mono PCM16 sample per name and a name table whose
+// offsets count from the start of the table
+fn fsb5_named(names: &[&str]) -> Vec<u8> {
+    let count = names.len() as u32;
+    let mut table = Vec::new();
+    let mut strings = Vec::new();
+    for name in names {
+        table.extend_from_slice(&(count * 4 + strings.len() as u32).to_le_bytes());
+        strings.extend_from_slice(name.as_bytes());
+        strings.push(0);
+    }
+    table.extend_from_slice(&strings);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(b"FSB5");
+    for field in [1, count, count * 8, table.len() as u32, count * 16, 2, 0, 0] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    out.resize(60, 0);
+    for i in 0..count as u64 {
+        let mode = (8u64 << 1) | (i << 6) | (8u64 << 34);
+        out.extend_from_slice(&mode.to_le_bytes());
+    }
+    out.extend_from_slice(&table);
+    out.extend((0..count * 16).map(|i| i as u8));
+    out
+}
+
+fn names(bank: &FsbBank) -> Vec<String> {
+    bank.samples.iter().map(|s| s.display_name()).collect()
+}
+
+#[test]
+fn duplicate_names_share_one_string() {
+    let mut bank = FsbBank::from_bytes(fsb5_named(&["alpha", "beta", "gamma"])).expect("parse");
+    bank.samples[2].name = Some("alpha".into());
+    let dir = std::env::temp_dir().join(format!("cums-name-table-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("create scratch dir");
+    let path = dir.join("duplicate_names.fsb");
+    bank.save(&path, false).expect("save");
+    let bytes = std::fs::read(&path).expect("read back");
+
+    let reloaded = FsbBank::from_bytes(bytes.clone()).expect("reload");
+    assert_eq!(names(&reloaded), ["alpha", "beta", "alpha"]);
+    assert_eq!(bytes.len(), bank.predicted_save_size());
+    let alpha = bytes.windows(6).filter(|w| w == b"alpha\0").count();
+    assert_eq!(alpha, 1);
+}
+
+#[test]
+fn name_offsets_relative_to_table() {
+    let bank = FsbBank::from_bytes(fsb5_named(&["alpha", "beta", "gamma"])).expect("parse");
+    assert_eq!(names(&bank), ["alpha", "beta", "gamma"]);
+}
+
+#[test]
+fn name_offsets_relative_to_strings() {
+    let mut bytes = fsb5_named(&["alpha", "beta", "gamma"]);
+    // Rebase each offset onto the first string after the offset array
+    let table = 60 + 3 * 8;
+    for i in 0..3 {
+        let at = table + i * 4;
+        let offset = u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        bytes[at..at + 4].copy_from_slice(&(offset - 3 * 4).to_le_bytes());
+    }
+    let bank = FsbBank::from_bytes(bytes).expect("parse");
+    assert_eq!(names(&bank), ["alpha", "beta", "gamma"]);
+}
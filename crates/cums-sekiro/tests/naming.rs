@@ -0,0 +1,59 @@
+mod common;
+
+use common::{fixture, out_dir};
+use cums_sekiro::{export_file_name, validate_name_template, FsbBank, DEFAULT_NAME_TEMPLATE};
+
+#[test]
+fn templates_render_placeholders() {
+    let bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    let beta = &bank.samples[1];
+
+    let name = |template| export_file_name(template, beta, "wav").expect(template);
+    assert_eq!(name(DEFAULT_NAME_TEMPLATE), "beta.wav");
+    assert_eq!(name("{index:04}_{name}_{freq}Hz"), "0001_beta_44100Hz.wav");
+    assert_eq!(name("{channels}ch {samples}"), "1ch 16.wav");
+    assert_eq!(name("[{index:3}]"), "[  1].wav");
+    assert_eq!(name("{{{name}}}"), "{beta}.wav");
+}
+
+#[test]
+fn rendered_names_are_single_components() {
+    let mut bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    let sample = &mut bank.samples[0];
+
+    sample.name = Some("sfx/../boss:roar?".into());
+    assert_eq!(
+        export_file_name("{name}", sample, "ogg").unwrap(),
+        "sfx_.._boss_roar_.ogg"
+    );
+    sample.name = Some(" .. ".into());
+    assert_eq!(
+        export_file_name("{name}", sample, "ogg").unwrap(),
+        "sound_0.ogg"
+    );
+}
+
+#[test]
+fn bad_templates_are_rejected() {
+    for template in ["{nam}", "{name", "name}", "{name:04}", "{index:x}"] {
+        let err = validate_name_template(template).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", template);
+    }
+}
+
+#[test]
+fn extract_range_uses_template() {
+    let bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    let dir = out_dir("naming");
+    let (done, wanted) = bank
+        .extract_range_with_template(0..3, &dir, "{index:02}-{name}")
+        .expect("extract");
+    assert_eq!((done, wanted), (3, 3));
+    for name in ["00-alpha.wav", "01-beta.wav", "02-gamma.wav"] {
+        assert!(dir.join(name).is_file(), "{}", name);
+    }
+
+    assert!(bank
+        .extract_range_with_template(0..3, &dir, "{bogus}")
+        .is_err());
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "native-encode")]
+
+use cums_sekiro::{
+    encode_vorbis, rebuild_ogg, Codec, CodecSource, Encryption, Fsb5Mode, FsbBank, IssueKind,
+    Sample, Version, DEFAULT_DATA_ALIGNMENT, FSB5_HEADER_VERSION,
+};
+use lewton::inside_ogg::OggStreamReader;
+use std::io::Cursor;
+
+fn sine(channels: u32, rate: u32, frames: usize) -> Vec<f32> {
+    (0..frames)
+        .flat_map(|i| {
+            let t = i as f32 / rate as f32;
+            let v = 0.25 * (2.0 * std::f32::consts::PI * 440.0 * t).sin();
+            std::iter::repeat_n(v, channels as usize)
+        })
+        .collect()
+}
+
+fn vorbis_bank(data: Vec<u8>, sample: Sample) -> FsbBank {
+    FsbBank {
+        version: Version::Fsb5,
+        codec: Codec::Vorbis,
+        samples: vec![sample],
+        encryption: Encryption::None,
+        data_size: data.len() as u32,
+        data,
+        header_size: 60,
+        sample_headers_size: 0,
+        name_table_size: 0,
+        flags: 0,
+        fsb5_mode: Fsb5Mode::default(),
+        codec_source: CodecSource::Field,
+        data_alignment: DEFAULT_DATA_ALIGNMENT,
+        fsb5_header_version: FSB5_HEADER_VERSION,
+        fsb5_v0_extra: 0,
+        header_tail: [0; 24],
+        truncated: false,
+    }
+}
+
+#[test]
+fn self_test_flags_header_mismatches() {
+    let (data, sample) = encode_vorbis(&sine(2, 44100, 44100), 2, 44100, 0.4).expect("encode");
+    let mut bank = vorbis_bank(data, sample);
+    assert!(bank.self_test().is_empty(), "{:?}", bank.self_test());
+
+    bank.samples[0].channels = 1;
+    let issues = bank.self_test();
+    assert_eq!(
+        issues[0].kind,
+        IssueKind::ChannelMismatch {
+            declared: 1,
+            actual: 2
+        }
+    );
+
+    bank.samples[0].channels = 2;
+    bank.samples[0].samples *= 2;
+    let issues = bank.self_test();
+    assert!(matches!(
+        issues[0].kind,
+        IssueKind::LengthMismatch {
+            declared: 88200,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn encoded_vorbis_rebuilds_to_playable_ogg() {
+    for (channels, rate) in [(1, 44100), (2, 44100), (1, 48000), (2, 48000)] {
+        let frames = rate as usize;
+        let (data, sample) =
+            encode_vorbis(&sine(channels, rate, frames), channels, rate, 0.5).expect("encode");
+        assert_eq!(sample.samples, frames as u64);
+
+        let bank = vorbis_bank(data, sample);
+        assert!(bank.self_test().is_empty());
+        let ogg = rebuild_ogg(&bank, &bank.samples[0]).expect("rebuild");
+
+        let mut reader = OggStreamReader::new(Cursor::new(ogg)).expect("ogg headers");
+        assert_eq!(reader.ident_hdr.audio_channels as u32, channels);
+        assert_eq!(reader.ident_hdr.audio_sample_rate, rate);
+        let mut decoded = 0;
+        while let Some(packet) = reader.read_dec_packet_itl().expect("decode") {
+            decoded += packet.len();
+        }
+        let decoded = decoded / channels as usize;
+        assert!(
+            decoded.abs_diff(frames) <= 2048,
+            "{} channel(s) at {} Hz decoded {} of {} frames",
+            channels,
+            rate,
+            decoded,
+            frames
+        );
+    }
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use cums_sekiro::{FsbBank, FsbError};
+use std::io;
+
+// Largest offset the 28-bit field can express, just under 4 GB
+const MAX_OFFSET: u64 = 0x0FFF_FFFF * 16;
+
+fn data_start(count: usize) -> u64 {
+    (common::HEADER_SIZE + count * 8) as u64
+}
+
+#[test]
+fn largest_offset_is_not_truncated() {
+    let bank = FsbBank::from_bytes(common::fsb5_pcm(&[0, MAX_OFFSET], 32)).expect("parse");
+    assert_eq!(bank.samples[1].data_offset, data_start(2) + MAX_OFFSET);
+    assert_eq!(bank.samples[1].data_size, 0);
+    assert_eq!(bank.samples[0].data_size, 32);
+}
+
+#[test]
+fn wrapped_offset_is_rejected() {
+    let bytes = common::fsb5_pcm(&[0, MAX_OFFSET, 16], 32);
+    let err = FsbBank::from_bytes(bytes).expect_err("offset went backwards");
+    assert!(matches!(
+        err,
+        FsbError::OffsetOverflow {
+            index: 2,
+            offset: 16
+        }
+    ));
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn aliased_offsets_are_allowed() {
+    let bank = FsbBank::from_bytes(common::fsb5_pcm(&[0, 16, 0], 32)).expect("parse");
+    assert_eq!(bank.samples[2].data_offset, bank.samples[0].data_offset);
+    assert_eq!(bank.samples[2].data_size, 16);
+}
@@ -0,0 +1,517 @@
+mod common;
+
+use common::fixture;
+use cums_sekiro::audio::{
+    build_ogg_file, build_ogg_file_with_options, decode_to_pcm, fmod_setup_crc,
+    framed_sample_count, generate_comment_header, generate_comment_header_with_options,
+    generate_id_header, parse_setup_modes, VorbisHeaders,
+};
+use cums_sekiro::{
+    export_vorbis_header, export_vorbis_header_json, get_vorbis_setup_header, known_vorbis_crcs,
+    rebuild_ogg, rebuild_ogg_lenient, rebuild_ogg_with_options, rebuild_ogg_with_setup,
+    rebuild_vorbis_seek_table, register_vorbis_header, Codec, CodecSource, Encryption, Fsb5Mode,
+    FsbBank, FsbError, IssueKind, OggRebuildOptions, PageFlush, Sample, Version,
+    DEFAULT_DATA_ALIGNMENT, FSB5_HEADER_VERSION, VORBIS_PRE_SKIP,
+};
+
+const KNOWN_CRC: u32 = 118203318;
+
+// Vorbis bank with fake audio packets; muxing never decodes them
+fn bank_with_packets(sizes: &[usize]) -> (FsbBank, Vec<Vec<u8>>) {
+    let packets: Vec<Vec<u8>> = sizes
+        .iter()
+        .enumerate()
+        .map(|(i, &size)| (0..size).map(|b| (b + i) as u8).collect())
+        .collect();
+    let mut data = Vec::new();
+    for packet in &packets {
+        data.extend_from_slice(&(packet.len() as u16).to_le_bytes());
+        data.extend_from_slice(packet);
+    }
+    let sample = Sample {
+        index: 0,
+        name: None,
+        frequency: 44100,
+        channels: 2,
+        samples: packets.len() as u64 * 1024,
+        data_offset: 0,
+        data_size: data.len() as u64,
+        loop_start: None,
+        loop_end: None,
+        vorbis_crc: Some(KNOWN_CRC),
+        vorbis_seek_table: None,
+        dsp_coeffs: None,
+        at9_config: None,
+        chunks: Vec::new(),
+        mode: None,
+    };
+    let bank = FsbBank {
+        version: Version::Fsb5,
+        codec: Codec::Vorbis,
+        samples: vec![sample],
+        encryption: Encryption::None,
+        data_size: data.len() as u32,
+        data,
+        header_size: 60,
+        sample_headers_size: 0,
+        name_table_size: 0,
+        flags: 0,
+        fsb5_mode: Fsb5Mode::default(),
+        codec_source: CodecSource::Field,
+        data_alignment: DEFAULT_DATA_ALIGNMENT,
+        fsb5_header_version: FSB5_HEADER_VERSION,
+        fsb5_v0_extra: 0,
+        header_tail: [0; 24],
+        truncated: false,
+    };
+    (bank, packets)
+}
+
+// Splits an Ogg stream into (header type, packets completed on the page)
+// and returns every packet reassembled from the lacing values
+fn parse_pages(ogg: &[u8]) -> (Vec<(u8, usize)>, Vec<Vec<u8>>) {
+    let mut pages = Vec::new();
+    let mut packets = Vec::new();
+    let mut current = Vec::new();
+    let mut pos = 0;
+    while pos < ogg.len() {
+        assert_eq!(&ogg[pos..pos + 4], b"OggS");
+        let header_type = ogg[pos + 5];
+        let segments = ogg[pos + 26] as usize;
+        let lacing = &ogg[pos + 27..pos + 27 + segments];
+        let mut body = pos + 27 + segments;
+        let mut completed = 0;
+        for &lace in lacing {
+            current.extend_from_slice(&ogg[body..body + lace as usize]);
+            body += lace as usize;
+            if lace < 255 {
+                packets.push(std::mem::take(&mut current));
+                completed += 1;
+            }
+        }
+        pages.push((header_type, completed));
+        pos = body;
+    }
+    (pages, packets)
+}
+
+#[test]
+fn pages_stay_within_the_segment_table() {
+    let mut sizes = vec![3000; 60];
+    sizes[20] = 60000;
+    let (bank, packets) = bank_with_packets(&sizes);
+
+    for flush in [
+        PageFlush::Packets(10),
+        PageFlush::Packets(1000),
+        PageFlush::Bytes(4096),
+        PageFlush::Bytes(1 << 20),
+    ] {
+        let options = OggRebuildOptions {
+            page_flush: flush,
+            ..Default::default()
+        };
+        let ogg = rebuild_ogg_with_options(&bank, &bank.samples[0], options).expect("rebuild");
+        let (pages, parsed) = parse_pages(&ogg);
+
+        // id, comment and setup headers come first
+        assert_eq!(&parsed[3..], &packets[..], "{:?}", flush);
+        assert!(
+            pages
+                .iter()
+                .all(|&(header_type, _)| header_type & 0x01 == 0),
+            "{:?} split a packet across pages",
+            flush
+        );
+        assert_eq!(pages.last().unwrap().0 & 0x04, 0x04);
+        if let PageFlush::Packets(n) = flush {
+            assert!(pages.iter().all(|&(_, completed)| completed <= n));
+        }
+    }
+}
+
+#[test]
+fn default_options_match_rebuild_ogg() {
+    let (bank, _) = bank_with_packets(&[200; 100]);
+    let eager = cums_sekiro::rebuild_ogg(&bank, &bank.samples[0]).expect("rebuild");
+    let options = rebuild_ogg_with_options(&bank, &bank.samples[0], OggRebuildOptions::default())
+        .expect("rebuild");
+    assert_eq!(eager, options);
+
+    let (pages, _) = parse_pages(&eager);
+    // 4096 bytes of 200-byte packets is 21 per page
+    assert!(pages[2..pages.len() - 1]
+        .iter()
+        .all(|&(_, completed)| completed == 21));
+}
+
+#[test]
+fn options_set_the_serial_and_vendor() {
+    let (bank, _) = bank_with_packets(&[40, 300, 12]);
+    let options = OggRebuildOptions {
+        serial: 0xCAFE_F00D,
+        vendor: "Xiph.Org libVorbis I 20200704 (Reducing Environment)".into(),
+        ..Default::default()
+    };
+    let headers = VorbisHeaders {
+        id_header: generate_id_header(44100, 2),
+        comment_header: generate_comment_header_with_options(&options),
+        setup_header: b"\x05vorbis setup".to_vec(),
+    };
+    let streams = [
+        rebuild_ogg_with_options(&bank, &bank.samples[0], options.clone()).expect("rebuild"),
+        bank.extract_time_range_with_options(0, 0.0, 0.05, options.clone())
+            .expect("extract range"),
+        build_ogg_file_with_options(&headers, &bank.data, 0, options.clone()).expect("build"),
+    ];
+
+    for ogg in streams {
+        let mut reader = ogg::reading::PacketReader::new(std::io::Cursor::new(&ogg));
+        let mut comment = None;
+        while let Some(packet) = reader.read_packet().expect("read") {
+            assert_eq!(packet.stream_serial(), options.serial);
+            if packet.data.starts_with(b"\x03vorbis") {
+                comment = Some(packet.data);
+            }
+        }
+        let comment = comment.expect("comment header");
+        let len = u32::from_le_bytes(comment[7..11].try_into().unwrap()) as usize;
+        assert_eq!(&comment[11..11 + len], options.vendor.as_bytes());
+        // No comments after the vendor, then the framing bit
+        assert_eq!(&comment[11 + len..], &[0, 0, 0, 0, 1]);
+    }
+}
+
+#[test]
+fn seek_table_starts_at_zero_and_increases() {
+    let sizes: Vec<usize> = (0..40).map(|i| 20 + i % 7).collect();
+    let (bank, _) = bank_with_packets(&sizes);
+    let raw = bank.sample_data(0).expect("data");
+    let table = rebuild_vorbis_seek_table(raw, 4096);
+
+    assert_eq!(&table[..2], &[0, 0]);
+    let entries: Vec<(u32, u32)> = table.chunks_exact(2).map(|e| (e[0], e[1])).collect();
+    assert_eq!(entries.len(), 10);
+    for pair in entries.windows(2) {
+        assert!(pair[1].0 > pair[0].0 && pair[1].1 > pair[0].1, "{:?}", pair);
+    }
+    // Every entry sits on a packet boundary
+    for (sample, offset) in entries {
+        let packets = sample as usize / 1024;
+        let expected: usize = sizes[..packets].iter().map(|s| s + 2).sum();
+        assert_eq!(offset as usize, expected);
+    }
+}
+
+#[test]
+fn ogg_decodes_to_pcm() {
+    // 0.5 s of a 440 Hz sine, mono at 8 kHz
+    let (pcm, rate, channels) = decode_to_pcm(&fixture("sine.ogg")).expect("decode");
+    assert_eq!((rate, channels), (8000, 1));
+    assert!(
+        pcm.len().abs_diff(4000) <= 2048,
+        "decoded {} frames",
+        pcm.len()
+    );
+    assert!(pcm.iter().all(|s| s.abs() <= 1.0));
+    assert!(pcm.iter().any(|s| s.abs() > 0.1));
+
+    assert!(decode_to_pcm(b"not an ogg").is_err());
+}
+
+#[test]
+fn supplied_setup_header_rebuilds_unknown_crc() {
+    let (known, _) = bank_with_packets(&[40, 300, 12]);
+    let expected = rebuild_ogg(&known, &known.samples[0]).expect("known crc");
+    // Captured from the bundled table, as if exported from a known-good bank
+    let setup = export_vorbis_header(KNOWN_CRC).expect("setup blob");
+
+    let (mut bank, _) = bank_with_packets(&[40, 300, 12]);
+    bank.samples[0].vorbis_crc = Some(0xDEAD_BEEF);
+    assert!(matches!(
+        rebuild_ogg(&bank, &bank.samples[0]),
+        Err(FsbError::UnknownVorbisCrc(0xDEAD_BEEF))
+    ));
+    let ogg = rebuild_ogg_with_setup(&bank, &bank.samples[0], &setup).expect("supplied setup");
+    assert_eq!(ogg, expected);
+
+    assert!(rebuild_ogg_with_setup(&bank, &bank.samples[0], &setup[1..]).is_err());
+}
+
+#[test]
+fn empty_vorbis_sample_has_its_own_error() {
+    let (bank, _) = bank_with_packets(&[]);
+    assert!(matches!(
+        rebuild_ogg(&bank, &bank.samples[0]),
+        Err(FsbError::EmptySample { index: 0 })
+    ));
+}
+
+#[test]
+fn registered_header_makes_crc_known() {
+    const NEW_CRC: u32 = 0x0BAD_F00D;
+    let (mut bank, _) = bank_with_packets(&[40, 300, 12]);
+    bank.samples[0].vorbis_crc = Some(NEW_CRC);
+    assert!(!known_vorbis_crcs().contains(&NEW_CRC));
+    assert!(rebuild_ogg(&bank, &bank.samples[0]).is_err());
+
+    register_vorbis_header(
+        NEW_CRC,
+        export_vorbis_header(KNOWN_CRC).expect("setup blob"),
+    );
+    let crcs = known_vorbis_crcs();
+    assert!(crcs.contains(&NEW_CRC) && crcs.contains(&KNOWN_CRC));
+    assert!(crcs.windows(2).all(|w| w[0] < w[1]));
+    assert!(rebuild_ogg(&bank, &bank.samples[0]).is_ok());
+
+    let json = |crc: u32| -> serde_json::Value {
+        serde_json::from_str(&export_vorbis_header_json(crc).expect("json")).expect("parse")
+    };
+    let registered = json(NEW_CRC);
+    let bundled = json(KNOWN_CRC);
+    let entry = &registered[NEW_CRC.to_string()];
+    assert_eq!(
+        entry["headerBytes"],
+        bundled[KNOWN_CRC.to_string()]["headerBytes"]
+    );
+    assert!(entry.get("seekBit").is_none());
+    assert!(bundled[KNOWN_CRC.to_string()]["seekBit"].is_number());
+}
+
+#[test]
+fn setup_crc_matches_the_table() {
+    let setup = export_vorbis_header(KNOWN_CRC).expect("setup blob");
+    assert_eq!(fmod_setup_crc(&setup), KNOWN_CRC);
+    assert_ne!(fmod_setup_crc(&setup[..setup.len() - 1]), KNOWN_CRC);
+}
+
+#[test]
+fn vorbis_pre_skip_is_left_out_of_the_length() {
+    let (mut bank, _) = bank_with_packets(&[100; 10]);
+    // 9716 playable frames plus the 3840-frame pre-skip
+    bank.samples[0].samples = 13556;
+    assert_eq!(bank.samples[0].effective_samples(), 9716);
+
+    bank.samples[0].vorbis_crc = None;
+    assert_eq!(bank.samples[0].effective_samples(), 13556);
+    bank.samples[0].vorbis_crc = Some(KNOWN_CRC);
+    bank.samples[0].samples = VORBIS_PRE_SKIP;
+    assert_eq!(bank.samples[0].effective_samples(), VORBIS_PRE_SKIP);
+}
+
+#[test]
+fn truncated_last_packet_still_ends_the_stream() {
+    let (mut bank, packets) = bank_with_packets(&[100, 100, 100, 100, 100, 100]);
+    // Cut the last packet short, as a clamped data section would
+    bank.data.truncate(bank.data.len() - 50);
+    bank.samples[0].data_size -= 50;
+
+    let ogg = rebuild_ogg(&bank, &bank.samples[0]).expect("rebuild");
+    let (pages, parsed) = parse_pages(&ogg);
+    assert_eq!(&parsed[3..], &packets[..5]);
+    assert_eq!(pages.last().unwrap().0 & 0x04, 0x04);
+}
+
+#[test]
+fn rebuilt_ogg_ends_at_the_header_length() {
+    let (mut bank, _) = bank_with_packets(&[100; 10]);
+    // No priming: ten packets mux to granule 10240, cut back to the header's
+    // 10000 and not to 10000 less the pre-skip
+    bank.samples[0].samples = 10000;
+    let ogg = rebuild_ogg(&bank, &bank.samples[0]).expect("rebuild");
+    let last = ogg.windows(4).rposition(|w| w == b"OggS").unwrap();
+    let granule = u64::from_le_bytes(ogg[last + 6..last + 14].try_into().unwrap());
+    assert_eq!(granule, 10000);
+}
+
+#[test]
+fn lenient_rebuild_borrows_a_compatible_setup_header() {
+    let (mut bank, packets) = bank_with_packets(&[100, 120, 80]);
+    bank.samples[0].vorbis_crc = Some(0xDEAD_BEEF);
+    assert!(matches!(
+        rebuild_ogg(&bank, &bank.samples[0]),
+        Err(FsbError::UnknownVorbisCrc(0xDEAD_BEEF))
+    ));
+
+    let ogg = rebuild_ogg_lenient(&bank, &bank.samples[0]).expect("lenient rebuild");
+    let (_, parsed) = parse_pages(&ogg);
+    assert!(parsed[2].starts_with(b"\x05vorbis"));
+    assert!(known_vorbis_crcs().contains(&fmod_setup_crc(&parsed[2])));
+    assert_eq!(&parsed[3..], &packets[..]);
+
+    // Known CRCs still use their own header
+    bank.samples[0].vorbis_crc = Some(KNOWN_CRC);
+    assert_eq!(
+        rebuild_ogg_lenient(&bank, &bank.samples[0]).expect("rebuild"),
+        rebuild_ogg(&bank, &bank.samples[0]).expect("rebuild")
+    );
+}
+
+#[test]
+fn range_extraction_cuts_at_packet_boundaries() {
+    let (mut bank, packets) = bank_with_packets(&[40; 200]);
+    bank.samples[0].vorbis_seek_table = Some(rebuild_vorbis_seek_table(&bank.data, 16384));
+
+    let ogg = bank.extract_time_range(0, 1.0, 2.0).expect("extract range");
+    let (_, got) = parse_pages(&ogg);
+    // Packet 43 holds sample 44100 and packet 86 holds sample 88199
+    assert_eq!(got[3..], packets[43..87]);
+    let last = ogg
+        .windows(4)
+        .rposition(|w| w == b"OggS")
+        .expect("last page");
+    let granule = u64::from_le_bytes(ogg[last + 6..last + 14].try_into().unwrap());
+    let secs = granule as f64 / 44100.0;
+    assert!((secs - 1.0).abs() < 0.05, "{} seconds", secs);
+
+    assert!(bank.extract_time_range(0, 2.0, 1.0).is_err());
+    assert!(bank.extract_time_range(0, 1.0, 1.0).is_err());
+}
+
+#[test]
+fn concatenated_export_has_a_track_per_sample() {
+    let (mut bank, packets) = bank_with_packets(&[40; 100]);
+    for (index, name) in [(1, "door"), (2, "wind")] {
+        let mut sample = bank.samples[0].clone();
+        sample.index = index;
+        sample.name = Some(name.into());
+        sample.frequency = 48000;
+        bank.samples.push(sample);
+    }
+    let mut empty = bank.samples[0].clone();
+    empty.data_size = 0;
+    bank.samples.insert(1, empty);
+    for (index, sample) in bank.samples.iter_mut().enumerate() {
+        sample.index = index;
+    }
+
+    let (ogg, cue, skipped) = bank.export_concatenated_ogg("bank.ogg").expect("export");
+    assert_eq!(skipped, 1);
+    assert!(cue.contains("1 empty sample(s) left out"));
+    let tracks: Vec<&str> = cue.lines().filter(|l| l.contains("TRACK")).collect();
+    assert_eq!(
+        tracks,
+        ["  TRACK 01 AUDIO", "  TRACK 02 AUDIO", "  TRACK 03 AUDIO"]
+    );
+    assert!(cue.contains("FILE \"bank.ogg\" OGG"));
+    assert!(cue.contains("TITLE \"sound_0\""));
+    assert!(cue.contains("TITLE \"door\""));
+    assert!(cue.contains("48000 Hz"));
+    // 100 packets at 44100 Hz put the second track at 2.32 s
+    assert!(cue.contains("INDEX 01 00:02:24"), "{}", cue);
+
+    // One chained stream per sample, each with its own serial
+    let (pages, parsed) = parse_pages(&ogg);
+    let starts = pages.iter().filter(|&&(ty, _)| ty & 0x02 != 0).count();
+    assert_eq!(starts, 3);
+    assert_eq!(parsed.len(), 3 * (3 + packets.len()));
+    let mut serials: Vec<&[u8]> = ogg
+        .windows(4)
+        .enumerate()
+        .filter(|(_, w)| *w == b"OggS")
+        .map(|(i, _)| &ogg[i + 14..i + 18])
+        .collect();
+    serials.dedup();
+    assert_eq!(serials.len(), 3);
+}
+
+#[test]
+fn built_ogg_pages_end_by_size() {
+    let mut sizes = vec![700; 50];
+    sizes[30] = 60000;
+    let (bank, packets) = bank_with_packets(&sizes);
+    let headers = VorbisHeaders {
+        id_header: generate_id_header(44100, 2),
+        comment_header: generate_comment_header(),
+        setup_header: b"\x05vorbis setup".to_vec(),
+    };
+    let samples = 50 * 1024 - 300;
+    let ogg = build_ogg_file(&headers, &bank.data, samples).expect("build");
+
+    let (pages, parsed) = parse_pages(&ogg);
+    assert_eq!(&parsed[3..], &packets[..]);
+    // The big packet gets a page of its own rather than being split
+    assert!(pages.iter().all(|&(ty, _)| ty & 0x01 == 0));
+    // Six 700-byte packets pass 4 KB
+    assert_eq!(pages[2].1, 6);
+
+    let mut reader = ogg::reading::PacketReader::new(std::io::Cursor::new(&ogg));
+    let mut granules = Vec::new();
+    while let Some(packet) = reader.read_packet().expect("read") {
+        if packet.last_in_page() {
+            granules.push(packet.absgp_page());
+        }
+    }
+    assert!(granules.windows(2).all(|w| w[0] <= w[1]), "{:?}", granules);
+    assert_eq!(granules.last(), Some(&samples));
+}
+
+#[test]
+fn self_test_flags_header_mismatches_in_packet_data() {
+    let (mut bank, packets) = bank_with_packets(&[40; 50]);
+    // Zeroed packets are valid audio packets that all use mode 0
+    let mut pos = 0;
+    for packet in &packets {
+        bank.data[pos + 2..pos + 2 + packet.len()].fill(0);
+        pos += 2 + packet.len();
+    }
+    // A stereo header with channel coupling, so it can't parse as mono
+    let (crc, modes) = known_vorbis_crcs()
+        .into_iter()
+        .find_map(|crc| {
+            let setup = get_vorbis_setup_header(crc)?;
+            let stereo = parse_setup_modes(&setup, 2)?;
+            parse_setup_modes(&setup, 1)
+                .is_none()
+                .then_some((crc, stereo))
+        })
+        .expect("coupled stereo header");
+    bank.samples[0].vorbis_crc = Some(crc);
+    let framed = framed_sample_count(&bank.data, &modes).expect("framed");
+    bank.samples[0].samples = framed;
+    assert!(bank.self_test().is_empty(), "{:?}", bank.self_test());
+
+    bank.samples[0].channels = 1;
+    assert_eq!(
+        bank.self_test()[0].kind,
+        IssueKind::ChannelMismatch {
+            declared: 1,
+            actual: 2
+        }
+    );
+
+    bank.samples[0].channels = 2;
+    bank.samples[0].samples = framed + 1;
+    assert_eq!(
+        bank.self_test()[0].kind,
+        IssueKind::LengthMismatch {
+            declared: framed + 1,
+            actual: framed
+        }
+    );
+
+    bank.samples[0].samples = framed;
+    bank.data[2] = 0x01;
+    assert_eq!(bank.self_test()[0].kind, IssueKind::BadAudioPacket);
+}
+
+#[test]
+fn streamed_ogg_matches_eager_rebuild() {
+    let mut sizes = vec![3000; 40];
+    sizes[10] = 60000;
+    let (bank, _) = bank_with_packets(&sizes);
+    let eager = rebuild_ogg(&bank, &bank.samples[0]).expect("rebuild");
+
+    let mut stream = bank.stream_ogg(0).expect("stream");
+    let mut streamed = Vec::new();
+    let mut chunk = [0u8; 7];
+    loop {
+        let n = std::io::Read::read(&mut stream, &mut chunk).expect("read");
+        if n == 0 {
+            break;
+        }
+        streamed.extend_from_slice(&chunk[..n]);
+    }
+    assert_eq!(streamed, eager);
+    assert!(bank.stream_ogg(1).is_err());
+}
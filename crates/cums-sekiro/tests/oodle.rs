@@ -0,0 +1,39 @@
+use cums_sekiro::formats::{Dcx, DcxType};
+use std::io;
+
+const PLAIN: &[u8] = b"FSB5 inside a Kraken DCX";
+
+// A DCX holding a single stored Kraken block: header byte 0xCC (reset,
+// uncompressed, Oodle magic nibble) and decoder type 6, then the raw bytes
+fn kraken_dcx() -> Vec<u8> {
+    let mut block = vec![0xCC, 0x06];
+    block.extend_from_slice(PLAIN);
+    let mut dcx = Dcx::compress(&block, DcxType::None).expect("wrap");
+    dcx[0x1C..0x20].copy_from_slice(&(PLAIN.len() as u32).to_be_bytes());
+    dcx[0x28..0x2C].copy_from_slice(b"KRAK");
+    dcx
+}
+
+#[cfg(feature = "oodle")]
+#[test]
+fn kraken_dcx_decompresses() {
+    match Dcx::decompress(&kraken_dcx()) {
+        Ok(dcx) => {
+            assert_eq!(dcx.compression, DcxType::Kraken);
+            assert_eq!(dcx.data, PLAIN);
+        }
+        // The DLL can't be bundled, so machines without it only check the error
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+            assert!(e.to_string().contains("oo2core_6_win64.dll"), "{}", e);
+        }
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[cfg(not(feature = "oodle"))]
+#[test]
+fn kraken_dcx_names_the_missing_dll() {
+    let err = Dcx::decompress(&kraken_dcx()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    assert!(err.to_string().contains("oo2core_6_win64.dll"));
+}
@@ -0,0 +1,827 @@
+mod common;
+
+use common::{fixture, fsb5_pcm, out_dir};
+use cums_sekiro::formats::{Bdt, BdtWriter, Bhd5, Bnd3, Bnd4, Bnd4Entry, Dcx, DcxType};
+use cums_sekiro::{
+    decrypt_aes_data, encrypt_aes_data, fsbext_decrypt, fsbext_encrypt, inspect, replace_sample,
+    AudioSettings, Codec, CodecSource, Encryption, FsbBank, FsbError, Version, FSB5_OFFSET_QUANTUM,
+    FSB_KEY,
+};
+
+fn sample_bytes(bank: &FsbBank) -> Vec<Vec<u8>> {
+    (0..bank.samples.len())
+        .map(|i| bank.sample_data(i).expect("sample data").to_vec())
+        .collect()
+}
+
+fn names(bank: &FsbBank) -> Vec<String> {
+    bank.samples.iter().map(|s| s.display_name()).collect()
+}
+
+fn save_and_reload(bank: &FsbBank, name: &str, encrypt: bool) -> (Vec<u8>, FsbBank) {
+    let path = out_dir(name).join("saved.fsb");
+    bank.save(&path, encrypt).expect("save");
+    let bytes = std::fs::read(&path).expect("read back");
+    let reloaded = FsbBank::from_bytes(bytes.clone()).expect("reload");
+    (bytes, reloaded)
+}
+
+#[test]
+fn fsb5_fixture_parses() {
+    let bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    assert_eq!(bank.version, Version::Fsb5);
+    assert_eq!(bank.codec, Codec::Pcm16);
+    assert_eq!(bank.encryption, Encryption::None);
+    assert_eq!(names(&bank), ["alpha", "beta", "gamma"]);
+
+    let sizes: Vec<u64> = bank.samples.iter().map(|s| s.data_size).collect();
+    assert_eq!(sizes, [64, 32, 32]);
+    assert_eq!(bank.samples[1].loop_start, Some(4));
+    assert_eq!(bank.samples[1].loop_end, Some(12));
+    assert!(bank.samples.iter().all(|s| s.frequency == 44100));
+}
+
+#[test]
+fn inspect_reads_only_the_header() {
+    for (file, version, encryption) in [
+        ("pcm.fsb5", Version::Fsb5, Encryption::None),
+        ("aes.fsb5", Version::Fsb5, Encryption::Aes),
+        ("fsbext.fsb5", Version::Fsb5, Encryption::Fsbext),
+        ("pcm.fsb4", Version::Fsb4, Encryption::None),
+    ] {
+        let bytes = fixture(file);
+        let info = inspect(&bytes[..64]).expect(file);
+        assert_eq!(info.version, version, "{}", file);
+        assert_eq!(info.encryption, encryption, "{}", file);
+        assert_eq!(info.likely_codec, Some(Codec::Pcm16), "{}", file);
+    }
+    assert!(inspect(b"RIFF\0\0\0\0WAVEfmt ").is_err());
+}
+
+#[test]
+fn banks_load_from_readers() {
+    let bytes = fixture("aes.fsb5");
+    let plain = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse plain");
+
+    let bank = FsbBank::from_reader(bytes.as_slice()).expect("from_reader");
+    assert_eq!(bank.encryption, Encryption::Aes);
+    assert_eq!(sample_bytes(&bank), sample_bytes(&plain));
+
+    // Embedded after a prefix, as in an archive entry
+    let mut stream = std::io::Cursor::new([b"junk".as_slice(), &bytes].concat());
+    stream.set_position(4);
+    let bank = FsbBank::from_reader_seek(stream).expect("from_reader_seek");
+    assert_eq!(names(&bank), names(&plain));
+    assert_eq!(sample_bytes(&bank), sample_bytes(&plain));
+}
+
+#[test]
+fn replacement_settings_are_validated() {
+    let dir = out_dir("replace_settings");
+    let mut bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    let mut replace = |settings: AudioSettings| {
+        let err = replace_sample(
+            &mut bank,
+            0,
+            &dir.join("missing.wav"),
+            &dir.join("fsbankcl.exe"),
+            &dir,
+            &settings,
+        )
+        .unwrap_err();
+        std::io::Error::from(err).kind()
+    };
+
+    for quality in [0, 101] {
+        let settings = AudioSettings {
+            quality,
+            ..Default::default()
+        };
+        assert_eq!(replace(settings), std::io::ErrorKind::InvalidInput);
+    }
+    let settings = AudioSettings {
+        target_codec: Some(Codec::Mpeg),
+        ..Default::default()
+    };
+    assert_eq!(replace(settings), std::io::ErrorKind::Unsupported);
+    // Three samples share the bank's codec, so one can't switch alone
+    let settings = AudioSettings {
+        target_codec: Some(Codec::Vorbis),
+        ..Default::default()
+    };
+    assert_eq!(replace(settings), std::io::ErrorKind::InvalidInput);
+    assert_eq!(bank.codec, Codec::Pcm16);
+}
+
+#[test]
+fn replacement_errors_are_typed() {
+    let dir = out_dir("replace_typed");
+    let settings = AudioSettings {
+        target_codec: Some(Codec::Vorbis),
+        ..Default::default()
+    };
+    let replace = |bank: &mut FsbBank| {
+        replace_sample(
+            bank,
+            0,
+            &dir.join("missing.wav"),
+            &dir.join("fsbankcl.exe"),
+            &dir,
+            &settings,
+        )
+        .unwrap_err()
+    };
+
+    let mut bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    assert!(matches!(
+        replace(&mut bank),
+        FsbError::CodecMismatch {
+            bank: Codec::Pcm16,
+            target: Codec::Vorbis
+        }
+    ));
+    let mut bank = FsbBank::from_bytes(fixture("pcm.fsb4")).expect("parse");
+    assert!(matches!(
+        replace(&mut bank),
+        FsbError::UnsupportedVersion(Version::Fsb4)
+    ));
+}
+
+#[test]
+fn reencrypted_banks_match_the_original_file() {
+    for file in ["aes.fsb5", "fsbext.fsb5", "pcm.fsb5"] {
+        let bytes = fixture(file);
+        let bank = FsbBank::from_bytes(bytes.clone()).expect(file);
+        assert_eq!(bank.reencrypt(), bytes, "{}", file);
+    }
+
+    let mut bytes = fixture("fsbext.fsb5");
+    fsbext_decrypt(&mut bytes, FSB_KEY);
+    let bank = FsbBank::from_bytes(fixture("fsbext.fsb5")).expect("parse");
+    assert_eq!(bank.decrypted_bytes(), bytes);
+    fsbext_encrypt(&mut bytes, FSB_KEY);
+    assert_eq!(bytes, fixture("fsbext.fsb5"));
+}
+
+#[test]
+fn opened_banks_read_samples_lazily() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    for file in ["pcm.fsb5", "aes.fsb5", "fsbext.fsb5", "pcm.fsb4"] {
+        let bank = FsbBank::from_bytes(fixture(file)).expect(file);
+        let mut reader = FsbBank::open(dir.join(file)).expect(file);
+        assert_eq!(reader.encryption, bank.encryption, "{}", file);
+        assert_eq!(reader.samples.len(), bank.samples.len(), "{}", file);
+        // Backwards, so every read has to seek
+        for i in (0..bank.samples.len()).rev() {
+            let lazy = reader.read_sample(i).expect(file);
+            assert_eq!(
+                lazy,
+                bank.sample_data(i).expect(file),
+                "{} sample {}",
+                file,
+                i
+            );
+        }
+        assert!(reader.read_sample(bank.samples.len()).is_err());
+    }
+}
+
+#[test]
+fn parallel_extraction_matches_sequential() {
+    for file in ["pcm.fsb5", "aes.fsb5", "pcm.fsb4"] {
+        let bank = FsbBank::from_bytes(fixture(file)).expect("parse");
+        let (results, skipped) = bank.extract_all_parallel();
+        assert_eq!(skipped, 0);
+        let parallel: Vec<_> = results.into_iter().map(|r| r.expect("extract")).collect();
+        let sequential: Vec<_> = bank
+            .samples
+            .iter()
+            .map(|s| {
+                let (data, ext) = bank.export_sample(s.index).expect("export");
+                (s.display_name(), data, ext)
+            })
+            .collect();
+        assert_eq!(parallel, sequential, "{}", file);
+    }
+}
+
+#[test]
+fn empty_samples_are_reported_not_exported() {
+    // A sample starting at the end of the data section has no bytes
+    let bank = FsbBank::from_bytes(fsb5_pcm(&[0, 32, 64], 64)).expect("parse");
+    let empty: Vec<bool> = bank.samples.iter().map(|s| s.is_empty()).collect();
+    assert_eq!(empty, [false, false, true]);
+
+    let err = bank.export_sample(2).expect_err("empty sample");
+    assert!(matches!(
+        FsbError::from(err),
+        FsbError::EmptySample { index: 2 }
+    ));
+
+    let (results, skipped) = bank.extract_all_parallel();
+    assert_eq!(skipped, 1);
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|r| r.is_ok()));
+}
+
+#[test]
+fn fsb5_round_trips() {
+    let original = fixture("pcm.fsb5");
+    let bank = FsbBank::from_bytes(original.clone()).expect("parse");
+    let (bytes, reloaded) = save_and_reload(&bank, "fsb5_round_trip", false);
+
+    assert_eq!(bytes.len(), bank.predicted_save_size());
+    assert_eq!(names(&reloaded), names(&bank));
+    assert_eq!(sample_bytes(&reloaded), sample_bytes(&bank));
+    assert_eq!(reloaded.samples[1].loop_start, Some(4));
+    assert_eq!(bytes, original);
+}
+
+#[test]
+fn verified_save_rejects_a_bad_write() {
+    let dir = out_dir("save_verified");
+    let path = dir.join("bank.fsb");
+    let original = fixture("pcm.fsb5");
+    std::fs::write(&path, &original).expect("write original");
+
+    let mut bank = FsbBank::from_bytes(original.clone()).expect("parse");
+    bank.save_verified(&path, false).expect("verified save");
+    assert_eq!(std::fs::read(&path).expect("read back"), original);
+
+    // A size past the end of the data makes the save skip the sample
+    bank.samples[1].data_size += 4096;
+    let err = bank.save_verified(&path, false).expect_err("corrupt save");
+    assert!(err.to_string().contains("sample 1 (beta)"), "{}", err);
+    assert_eq!(std::fs::read(&path).expect("read back"), original);
+    assert!(!dir.join("bank.fsb.verify").exists());
+}
+
+#[test]
+fn edited_loop_points_persist() {
+    let mut bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    bank.samples[0].loop_start = Some(2);
+    bank.samples[0].loop_end = Some(10);
+    bank.samples[1].loop_start = Some(6);
+    bank.samples[1].loop_end = None;
+    let (_, reloaded) = save_and_reload(&bank, "loop_points", false);
+
+    let points = |i: usize| (reloaded.samples[i].loop_start, reloaded.samples[i].loop_end);
+    assert_eq!(points(0), (Some(2), Some(10)));
+    let samples = bank.samples[1].samples as u32;
+    assert_eq!(points(1), (Some(6), Some(samples)));
+    assert_eq!(points(2), (None, None));
+}
+
+#[test]
+fn encrypted_fsb5_matches_plain() {
+    let plain = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse plain");
+    for (file, encryption) in [
+        ("aes.fsb5", Encryption::Aes),
+        ("fsbext.fsb5", Encryption::Fsbext),
+    ] {
+        let original = fixture(file);
+        let bank = FsbBank::from_bytes(original.clone()).expect(file);
+        assert_eq!(bank.encryption, encryption, "{}", file);
+        assert_eq!(names(&bank), names(&plain), "{}", file);
+        assert_eq!(sample_bytes(&bank), sample_bytes(&plain), "{}", file);
+
+        let (bytes, _) = save_and_reload(&bank, file, true);
+        assert_eq!(bytes, original, "{} re-encrypts identically", file);
+    }
+}
+
+#[test]
+fn aes_leaves_a_partial_data_block_in_the_clear() {
+    let mut region: Vec<u8> = (0..17).collect();
+    encrypt_aes_data(&mut region, FSB_KEY);
+    assert_ne!(region[..16], (0..16).collect::<Vec<u8>>());
+    assert_eq!(region[16], 16);
+    decrypt_aes_data(&mut region, FSB_KEY);
+    assert_eq!(region, (0..17).collect::<Vec<u8>>());
+
+    // A bank whose data section ends mid-block, encrypted with the same
+    // helpers; loading and streaming have to agree with them
+    let plain = fsb5_pcm(&[0], 17);
+    let mut aes = plain.clone();
+    encrypt_aes_data(&mut aes[..32], FSB_KEY);
+    let data_start = aes.len() - 17;
+    encrypt_aes_data(&mut aes[data_start..], FSB_KEY);
+
+    let expected = sample_bytes(&FsbBank::from_bytes(plain).expect("parse plain"));
+    let bank = FsbBank::from_bytes(aes.clone()).expect("parse aes");
+    assert_eq!(bank.encryption, Encryption::Aes);
+    assert_eq!(sample_bytes(&bank), expected);
+
+    let path = out_dir("aes_partial_block").join("aes.fsb");
+    std::fs::write(&path, &aes).expect("write");
+    let mut reader = FsbBank::open(&path).expect("open reader");
+    assert_eq!(reader.read_sample(0).expect("read sample"), expected[0]);
+}
+
+#[test]
+fn aes_header_tail_survives_save() {
+    let mut plain = fixture("pcm.fsb5");
+    for (i, byte) in plain[36..60].iter_mut().enumerate() {
+        *byte = 0xA0 + i as u8;
+    }
+    let path = out_dir("header_tail").join("aes.fsb");
+    FsbBank::from_bytes(plain.clone())
+        .expect("parse plain")
+        .save_as(&path, Encryption::Aes)
+        .expect("save aes");
+    let aes = std::fs::read(&path).expect("read back");
+
+    let bank = FsbBank::from_bytes(aes.clone()).expect("parse aes");
+    assert_eq!(bank.encryption, Encryption::Aes);
+    assert_eq!(bank.header_tail, plain[36..60]);
+    let (bytes, _) = save_and_reload(&bank, "header_tail_resave", true);
+    assert_eq!(bytes[36..60], aes[36..60]);
+    assert_eq!(bytes, aes);
+}
+
+#[test]
+fn save_as_converts_encryption() {
+    let plain = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse plain");
+    let dir = out_dir("save_as");
+    for (file, encryption) in [
+        ("aes.fsb5", Encryption::Aes),
+        ("fsbext.fsb5", Encryption::Fsbext),
+    ] {
+        let path = dir.join(file);
+        plain.save_as(&path, encryption).expect(file);
+        assert_eq!(std::fs::read(&path).expect("read back"), fixture(file));
+
+        let encrypted = FsbBank::from_bytes(fixture(file)).expect(file);
+        let path = dir.join(format!("{}.plain", file));
+        encrypted.save_as(&path, Encryption::None).expect(file);
+        assert_eq!(
+            std::fs::read(&path).expect("read back"),
+            fixture("pcm.fsb5")
+        );
+    }
+
+    let fsb4 = FsbBank::from_bytes(fixture("pcm.fsb4")).expect("parse fsb4");
+    let err = fsb4
+        .save_as(dir.join("fsb4.fsb"), Encryption::Aes)
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn appended_sample_survives_save() {
+    let dir = out_dir("append");
+    let pcm: Vec<u8> = (0..400u32)
+        .flat_map(|i| (i as i16 * 50).to_le_bytes())
+        .collect();
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + pcm.len() as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    for field in [16u32, 1 | (2 << 16), 44100, 44100 * 4, 4 | (16 << 16)] {
+        wav.extend_from_slice(&field.to_le_bytes());
+    }
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(pcm.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&pcm);
+    let wav_path = dir.join("added.wav");
+    std::fs::write(&wav_path, &wav).expect("write wav");
+
+    let mut bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    let before = bank.samples.len();
+    let index = bank
+        .append_sample(
+            wav_path.as_path(),
+            dir.join("fsbankcl.exe").as_path(),
+            dir.as_path(),
+            Some("added".into()),
+            &AudioSettings::default(),
+        )
+        .expect("append");
+    assert_eq!(index, before);
+
+    let (_, reloaded) = save_and_reload(&bank, "append_saved", false);
+    assert_eq!(reloaded.samples.len(), before + 1);
+    assert_eq!(names(&reloaded)[index], "added");
+    assert_eq!(reloaded.samples[index].channels, 2);
+    assert_eq!(reloaded.samples[index].samples, 200);
+    assert_eq!(reloaded.sample_data(index).expect("data"), pcm);
+    assert_eq!(
+        sample_bytes(&reloaded)[..before],
+        sample_bytes(&bank)[..before]
+    );
+}
+
+#[test]
+fn removed_samples_leave_the_rest_intact() {
+    for (file, name) in [("pcm.fsb5", "remove_fsb5"), ("pcm.fsb4", "remove_fsb4")] {
+        let original = FsbBank::from_bytes(fixture(file)).expect(file);
+        let mut bank = FsbBank::from_bytes(fixture(file)).expect(file);
+        // The middle of three in pcm.fsb5, the second of two in pcm.fsb4
+        let middle = 1;
+        bank.remove_sample(middle).expect("remove");
+        let survivors: Vec<usize> = (0..original.samples.len())
+            .filter(|&i| i != middle)
+            .collect();
+        assert_eq!(bank.samples.len(), survivors.len(), "{}", file);
+        assert!(bank.samples.iter().enumerate().all(|(i, s)| s.index == i));
+
+        let (_, reloaded) = save_and_reload(&bank, name, false);
+        assert_eq!(reloaded.samples.len(), survivors.len(), "{}", file);
+        for (now, before) in survivors.into_iter().enumerate() {
+            assert_eq!(
+                reloaded.extract_audio(now).expect("extract"),
+                original.extract_audio(before).expect("extract"),
+                "{} sample {}",
+                file,
+                before
+            );
+            assert_eq!(names(&reloaded)[now], names(&original)[before]);
+        }
+    }
+
+    // Dropping the last sample must not keep its stale name entry
+    let mut bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    let kept = names(&bank)[..2].to_vec();
+    bank.remove_sample(2).expect("remove");
+    let (_, reloaded) = save_and_reload(&bank, "remove_last", false);
+    assert_eq!(names(&reloaded), kept);
+    assert!(bank.remove_sample(2).is_err());
+}
+
+#[test]
+fn fsb4_round_trips() {
+    let original = fixture("pcm.fsb4");
+    let bank = FsbBank::from_bytes(original.clone()).expect("parse");
+    assert_eq!(bank.version, Version::Fsb4);
+    assert_eq!(names(&bank), ["left", "right"]);
+    assert!(bank.samples.iter().all(|s| s.frequency == 22050));
+    assert_eq!(bank.predicted_save_size(), original.len());
+
+    let (bytes, _) = save_and_reload(&bank, "fsb4_round_trip", false);
+    assert_eq!(bytes, original);
+}
+
+#[test]
+fn fsbext_fsb4_round_trips() {
+    let plain = fixture("pcm.fsb4");
+    let mut encrypted = plain.clone();
+    fsbext_encrypt(&mut encrypted, FSB_KEY);
+
+    let bank = FsbBank::from_bytes(encrypted.clone()).expect("parse");
+    assert_eq!(bank.version, Version::Fsb4);
+    assert_eq!(bank.encryption, Encryption::Fsbext);
+    assert_eq!(bank.decrypted_bytes(), plain);
+    assert_eq!(bank.reencrypt(), encrypted);
+    let plain_bank = FsbBank::from_bytes(plain.clone()).expect("parse plain");
+    assert_eq!(sample_bytes(&bank), sample_bytes(&plain_bank));
+
+    let (bytes, reloaded) = save_and_reload(&bank, "fsb4_fsbext", true);
+    assert_eq!(bytes, encrypted);
+    assert_eq!(reloaded.encryption, Encryption::Fsbext);
+    let (bytes, _) = save_and_reload(&bank, "fsb4_fsbext_plain", false);
+    assert_eq!(bytes, plain);
+
+    let path = out_dir("fsb4_fsbext_reader").join("bank.fsb");
+    plain_bank
+        .save_as(&path, Encryption::Fsbext)
+        .expect("save as fsbext");
+    assert_eq!(std::fs::read(&path).expect("read back"), encrypted);
+    let mut reader = FsbBank::open(&path).expect("open");
+    assert_eq!(reader.encryption, Encryption::Fsbext);
+    assert_eq!(
+        reader.read_sample(1).expect("read"),
+        sample_bytes(&plain_bank)[1]
+    );
+}
+
+#[test]
+fn planned_layout_matches_saved_file() {
+    for (file, name) in [("pcm.fsb5", "layout_fsb5"), ("pcm.fsb4", "layout_fsb4")] {
+        let mut bank = FsbBank::from_bytes(fixture(file)).expect(file);
+        assert_eq!(bank.planned_layout_table(), bank.layout_table(), "{}", file);
+
+        // Swapping differently sized samples moves the data between them
+        bank.swap_samples(0, 1, false).expect("swap");
+        let planned = bank.planned_layout_table();
+        assert_ne!(planned, bank.layout_table(), "{}", file);
+        let (_, reloaded) = save_and_reload(&bank, name, false);
+        assert_eq!(reloaded.layout_table(), planned, "{}", file);
+    }
+
+    let bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    let sizes: Vec<u64> = bank
+        .layout_table()
+        .iter()
+        .map(|&(_, _, size)| size)
+        .collect();
+    assert_eq!(sizes, [64, 32, 32]);
+}
+
+#[test]
+fn sixteen_byte_aligned_bank_keeps_its_layout() {
+    let original = fsb5_pcm(&[0, 16, 48], 64);
+    let bank = FsbBank::from_bytes(original.clone()).expect("parse");
+    assert_eq!(bank.data_alignment, FSB5_OFFSET_QUANTUM);
+    assert_eq!(bank.predicted_save_size(), original.len());
+
+    let (bytes, reloaded) = save_and_reload(&bank, "sixteen_byte_alignment", false);
+    assert_eq!(bytes, original);
+    assert_eq!(reloaded.layout_table(), bank.layout_table());
+    assert_eq!(reloaded.data_size, bank.data_size);
+
+    // Forcing 32-byte padding moves the second and third samples
+    let mut padded = bank;
+    padded.data_alignment = 32;
+    let layout = padded.planned_layout_table();
+    let offsets: Vec<u64> = layout.iter().map(|&(_, o, _)| o - layout[0].1).collect();
+    assert_eq!(offsets, [0, 32, 64]);
+}
+
+#[test]
+fn names_can_be_added_to_a_nameless_bank() {
+    let mut bank = FsbBank::from_bytes(fsb5_pcm(&[0, 32, 48], 64)).expect("parse");
+    assert_eq!(bank.name_table_size, 0);
+    bank.set_sample_name(0, "footstep".into()).expect("name 0");
+    bank.set_sample_name(2, "door".into()).expect("name 2");
+    assert!(bank.set_sample_name(3, "missing".into()).is_err());
+
+    let (_, reloaded) = save_and_reload(&bank, "added_names", false);
+    assert!(reloaded.name_table_size > 0);
+    assert_eq!(reloaded.samples[0].name.as_deref(), Some("footstep"));
+    assert_eq!(reloaded.samples[1].name, None);
+    assert_eq!(reloaded.samples[2].name.as_deref(), Some("door"));
+    assert_eq!(sample_bytes(&reloaded), sample_bytes(&bank));
+}
+
+#[test]
+fn aliased_samples_are_grouped() {
+    let bank = FsbBank::from_bytes(common::fsb5_pcm(&[0, 16, 0], 32)).expect("parse");
+    assert_eq!(bank.alias_groups(), [vec![0, 2]]);
+    assert_eq!(bank.aliases_of(2), [0]);
+    assert!(bank.aliases_of(1).is_empty());
+}
+
+#[test]
+fn aliases_stay_shared_after_save() {
+    let bank = FsbBank::from_bytes(fsb5_pcm(&[0, 16, 0], 32)).expect("parse");
+    let (bytes, reloaded) = save_and_reload(&bank, "aliases_shared", false);
+    assert_eq!(reloaded.alias_groups(), [vec![0, 2]]);
+    assert_eq!(sample_bytes(&reloaded), sample_bytes(&bank));
+    assert_eq!(bytes.len(), bank.predicted_save_size());
+
+    let planned = bank.planned_layout_table();
+    assert_eq!(planned[0].1, planned[2].1);
+    assert_eq!(planned, reloaded.layout_table());
+}
+
+#[test]
+fn bnd4_round_trips() {
+    let original = fixture("sound.bnd");
+    let bnd = Bnd4::read(&original).expect("parse");
+    assert_eq!(bnd.entries.len(), 2);
+    assert_eq!(bnd.entries[1].data, b"hello");
+
+    let bank = FsbBank::from_bytes(bnd.entries[0].data.clone()).expect("nested bank");
+    assert_eq!(bank.samples.len(), 3);
+    assert_eq!(bnd.write().expect("write"), original);
+}
+
+// Little-endian BND3 in format 0x74: 24-byte entry headers with an id, a
+// name offset and the uncompressed size, then names, then 16-aligned data
+fn bnd3_bytes(entries: &[(i32, &str, &[u8])]) -> Vec<u8> {
+    let names_start = 0x20 + entries.len() * 24;
+    let headers_end = names_start + entries.iter().map(|(_, n, _)| n.len() + 1).sum::<usize>();
+    let mut data_offset = (headers_end + 15) & !15;
+
+    let mut out = b"BND307D7R6\0\0".to_vec();
+    out.extend_from_slice(&[0x74, 0, 0, 0]);
+    for field in [entries.len(), headers_end, 0x54, 0] {
+        out.extend_from_slice(&(field as i32).to_le_bytes());
+    }
+    let mut name_offset = names_start;
+    let mut body = Vec::new();
+    for (id, name, data) in entries {
+        out.extend_from_slice(&[0x40, 0, 0, 0]);
+        out.extend_from_slice(&(data.len() as i32).to_le_bytes());
+        out.extend_from_slice(&(data_offset as u32).to_le_bytes());
+        out.extend_from_slice(&id.to_le_bytes());
+        out.extend_from_slice(&(name_offset as u32).to_le_bytes());
+        out.extend_from_slice(&(data.len() as i32).to_le_bytes());
+        name_offset += name.len() + 1;
+        body.push(data_offset);
+        data_offset = (data_offset + data.len() + 15) & !15;
+    }
+    for (_, name, _) in entries {
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+    }
+    for ((_, _, data), offset) in entries.iter().zip(body) {
+        out.resize(offset, 0);
+        out.extend_from_slice(data);
+    }
+    out.resize(data_offset, 0);
+    out
+}
+
+#[test]
+fn bnd3_round_trips() {
+    let bank = fixture("pcm.fsb5");
+    let original = bnd3_bytes(&[
+        (100, "N:\\FRPG\\sound\\pcm.fsb", &bank),
+        (101, "notes.txt", b"hello"),
+    ]);
+    let mut bnd = Bnd3::read(&original).expect("parse");
+    assert_eq!(bnd.version, "07D7R6");
+    assert_eq!(bnd.format, 0x74);
+    assert_eq!(bnd.unk18, 0x54);
+    assert_eq!(bnd.entries.len(), 2);
+    assert_eq!(bnd.entries[0].id, 100);
+    assert_eq!(bnd.entries[0].flags, 0x40);
+    assert_eq!(bnd.entries[0].data, bank);
+    assert_eq!(bnd.get_entry("notes.txt").expect("entry").data, b"hello");
+    assert_eq!(bnd.write().expect("write"), original);
+
+    bnd.get_entry_mut("notes.txt").expect("entry").data = b"a longer note".to_vec();
+    let edited = Bnd3::read(&bnd.write().expect("write")).expect("reparse");
+    assert_eq!(edited.entries[0].data, bank);
+    assert_eq!(edited.entries[1].data, b"a longer note");
+    assert_eq!(edited.entries[1].id, 101);
+}
+
+#[test]
+fn bnd4_compressed_entries_round_trip() {
+    let plain: Vec<u8> = b"footstep ".iter().copied().cycle().take(4096).collect();
+    let entry = |name: &str, compression| Bnd4Entry {
+        flags: 0x40,
+        id: 0,
+        name: name.into(),
+        uncompressed_size: plain.len() as u64,
+        compressed_size: plain.len() as u64,
+        data: plain.clone(),
+        compression,
+    };
+    let mut bnd = Bnd4 {
+        version: "07D7R6".into(),
+        flags: 0x74,
+        big_endian: false,
+        bit_big_endian: false,
+        unicode: true,
+        extended: 0x10,
+        entries: vec![
+            entry("packed.fsb", Some(DcxType::Dflt)),
+            entry("stored.fsb", None),
+        ],
+    };
+
+    let read = Bnd4::read(&bnd.write().expect("write")).expect("read back");
+    let (packed, stored) = (&read.entries[0], &read.entries[1]);
+    assert!(read.is_compressed(packed));
+    assert_eq!(packed.flags, 0xC0);
+    assert_eq!(packed.uncompressed_size, plain.len() as u64);
+    assert_eq!(packed.compressed_size, packed.data.len() as u64);
+    assert!(packed.data.len() < plain.len());
+    assert_eq!(Dcx::decompress(&packed.data).expect("dcx").data, plain);
+
+    assert!(!read.is_compressed(stored));
+    assert_eq!(stored.data, plain);
+
+    bnd.extended = 4;
+    assert!(bnd.write().is_err());
+}
+
+#[test]
+fn dcx_round_trips() {
+    let dcx = Dcx::decompress(&fixture("sound.bnd.dcx")).expect("decompress");
+    assert_eq!(dcx.compression, DcxType::Dflt);
+    assert_eq!(dcx.data, fixture("sound.bnd"));
+
+    let recompressed = Dcx::compress(&dcx.data, DcxType::Dflt).expect("compress");
+    let again = Dcx::decompress(&recompressed).expect("decompress again");
+    assert_eq!(again.data, dcx.data);
+}
+
+#[test]
+fn bhd5_entries_read_from_bdt() {
+    let bhd = Bhd5::read(&fixture("archive.bhd")).expect("parse");
+    let bdt_bytes = fixture("archive.bdt");
+    let bdt = Bdt::new(&bdt_bytes);
+    assert_eq!(bhd.all_entries().len(), 3);
+
+    let hash = Bhd5::hash_path("/sound/pcm.fsb", &bhd.salt);
+    let entry = bhd.get_entry(hash).expect("entry by hash");
+    assert_eq!(bdt.read_entry(entry).expect("read"), fixture("pcm.fsb5"));
+
+    let readme = bhd
+        .get_entry(Bhd5::hash_path("/SOUND/README.TXT", &bhd.salt))
+        .expect("hash ignores case");
+    assert_eq!(bdt.read_entry(readme).expect("read"), b"hello");
+}
+
+#[test]
+fn bhd5_and_bdt_repack() {
+    let mut bhd = Bhd5::read(&fixture("archive.bhd")).expect("parse");
+    let bdt_bytes = fixture("archive.bdt");
+    let bdt = Bdt::new(&bdt_bytes);
+    let payloads: Vec<(u32, Vec<u8>)> = bhd
+        .all_entries()
+        .into_iter()
+        .map(|e| (e.hash, bdt.read_entry(e).expect("read")))
+        .collect();
+
+    // An untouched header writes back the same tables
+    assert_eq!(
+        bhd.write().expect("write")[8..],
+        fixture("archive.bhd")[8..]
+    );
+
+    let mut writer = BdtWriter::new();
+    for entry in bhd.buckets.iter_mut().flat_map(|b| b.entries.iter_mut()) {
+        let payload = bdt.read_entry(entry).expect("read");
+        writer.add(entry, &payload);
+    }
+    let new_bdt = writer.finish();
+
+    for big_endian in [false, true] {
+        bhd.big_endian = big_endian;
+        let reread = Bhd5::read(&bhd.write().expect("write")).expect("reparse");
+        assert_eq!(reread.big_endian, big_endian);
+        assert_eq!(reread.salt, bhd.salt);
+        assert_eq!(reread.buckets.len(), bhd.buckets.len());
+        let bdt = Bdt::new(&new_bdt);
+        let repacked: Vec<(u32, Vec<u8>)> = reread
+            .all_entries()
+            .into_iter()
+            .map(|e| {
+                assert_eq!(e.offset % 16, 0);
+                (e.hash, bdt.read_entry(e).expect("read repacked"))
+            })
+            .collect();
+        assert_eq!(repacked, payloads);
+    }
+}
+
+#[test]
+fn swapped_samples_survive_save() {
+    let mut bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    let before = sample_bytes(&bank);
+    bank.swap_samples(0, 2, false).expect("swap audio");
+    let (_, reloaded) = save_and_reload(&bank, "swap_audio", false);
+
+    let after = sample_bytes(&reloaded);
+    assert_eq!(after[0], before[2]);
+    assert_eq!(after[2], before[0]);
+    assert_eq!(after[1], before[1]);
+    assert_eq!(names(&reloaded), ["alpha", "beta", "gamma"]);
+
+    let mut bank = reloaded;
+    bank.swap_samples(1, 2, true).expect("swap with names");
+    assert_eq!(bank.samples[1].loop_start, None);
+    assert_eq!(bank.samples[2].loop_start, Some(4));
+    let (_, reloaded) = save_and_reload(&bank, "swap_names", false);
+    assert_eq!(names(&reloaded), ["alpha", "gamma", "beta"]);
+    assert_eq!(reloaded.samples[2].loop_end, Some(12));
+    assert_eq!(sample_bytes(&reloaded)[2], before[1]);
+}
+
+#[test]
+fn version_zero_header_is_parsed_and_kept() {
+    // Version 0 has an extra u32 after the codec, making the header 64 bytes
+    let mut bytes = b"FSB5".to_vec();
+    for field in [0, 1, 8, 0, 32, 2, 0xDEADBEEF, 0, 0] {
+        bytes.extend_from_slice(&u32::to_le_bytes(field));
+    }
+    bytes.resize(64, 0);
+    let mode: u64 = (9 << 1) | (16 << 34);
+    bytes.extend_from_slice(&mode.to_le_bytes());
+    bytes.extend(0..32u8);
+
+    let bank = FsbBank::from_bytes(bytes.clone()).expect("parse");
+    assert_eq!(bank.fsb5_header_version, 0);
+    assert_eq!(bank.fsb5_v0_extra, 0xDEADBEEF);
+    assert_eq!(bank.samples[0].frequency, 48000);
+    assert_eq!(bank.samples[0].samples, 16);
+    assert_eq!(bank.sample_data(0).expect("data"), &bytes[72..]);
+
+    let (saved, reloaded) = save_and_reload(&bank, "version_zero", false);
+    assert_eq!(saved, bytes);
+    assert_eq!(reloaded.fsb5_header_version, 0);
+    assert_eq!(reloaded.fsb5_v0_extra, 0xDEADBEEF);
+    assert_eq!(reloaded.samples[0].frequency, 48000);
+    assert_eq!(sample_bytes(&reloaded), sample_bytes(&bank));
+}
+
+#[test]
+fn codec_in_the_mode_field_is_used_and_kept() {
+    let mut bytes = fsb5_pcm(&[0], 32);
+    bytes[24..28].copy_from_slice(&0u32.to_le_bytes());
+    bytes[28..32].copy_from_slice(&15u32.to_le_bytes());
+
+    let bank = FsbBank::from_bytes(bytes.clone()).expect("parse");
+    assert_eq!(bank.codec, Codec::Vorbis);
+    assert_eq!(bank.codec_source, CodecSource::Mode);
+
+    let (saved, reloaded) = save_and_reload(&bank, "codec_in_mode", false);
+    assert_eq!(saved[24..32], bytes[24..32]);
+    assert_eq!(reloaded.codec, Codec::Vorbis);
+    assert_eq!(reloaded.codec_source, CodecSource::Mode);
+}
@@ -0,0 +1,10 @@
+use cums_sekiro::find_ffmpeg;
+
+// Kept in its own test binary since the variable is process-wide
+#[test]
+fn ffmpeg_env_var_takes_priority() {
+    let path = std::env::temp_dir().join("wine-ffmpeg");
+    std::env::set_var("CUMS_FFMPEG", &path);
+    assert_eq!(find_ffmpeg(), Some(path));
+    std::env::remove_var("CUMS_FFMPEG");
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use cums_sekiro::{FsbBank, IssueKind};
+use std::io;
+
+const SAMPLE_SIZE: usize = 32;
+
+fn three_samples() -> Vec<u8> {
+    common::fsb5_pcm(&[0, 32, 64], 3 * SAMPLE_SIZE as u32)
+}
+
+#[test]
+fn intact_bank_parses() {
+    let bank = FsbBank::from_bytes(three_samples()).expect("parse");
+    assert_eq!(bank.samples.len(), 3);
+    assert!(bank
+        .samples
+        .iter()
+        .all(|s| s.data_size == SAMPLE_SIZE as u64));
+}
+
+#[test]
+fn truncated_data_section_is_clamped() {
+    let mut bytes = three_samples();
+    bytes.truncate(bytes.len() - SAMPLE_SIZE - SAMPLE_SIZE / 2);
+    let bank = FsbBank::from_bytes(bytes).expect("parse truncated bank");
+
+    assert_eq!(bank.data_size as usize, SAMPLE_SIZE + SAMPLE_SIZE / 2);
+    let sizes: Vec<u64> = bank.samples.iter().map(|s| s.data_size).collect();
+    assert_eq!(sizes, [SAMPLE_SIZE as u64, SAMPLE_SIZE as u64 / 2, 0]);
+
+    assert_eq!(bank.sample_data(0).expect("first").len(), SAMPLE_SIZE);
+    assert_eq!(bank.sample_data(1).expect("partial").len(), SAMPLE_SIZE / 2);
+    assert!(bank.sample_data(2).is_err());
+
+    let issues = bank.self_test();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].index, 2);
+    assert_eq!(issues[0].kind, IssueKind::DataOutOfBounds);
+}
+
+#[test]
+fn truncated_headers_are_rejected() {
+    let mut bytes = three_samples();
+    bytes.truncate(common::HEADER_SIZE + 4);
+    let err = FsbBank::from_bytes(bytes).expect_err("no complete sample header");
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn bank_cut_mid_chunk_keeps_earlier_samples() {
+    let intact = FsbBank::from_bytes(common::fixture("pcm.fsb5")).expect("parse");
+    assert!(!intact.truncated);
+
+    // Sample 1's loop chunk body starts at 0x50
+    let mut bytes = common::fixture("pcm.fsb5");
+    bytes.truncate(0x54);
+    let bank = FsbBank::from_bytes(bytes).expect("partial bank");
+    assert!(bank.truncated);
+    assert_eq!(bank.samples.len(), 2);
+    assert_eq!(bank.samples[0].samples, intact.samples[0].samples);
+    assert_eq!(bank.samples[0].data_offset, intact.samples[0].data_offset);
+    assert_eq!(bank.samples[1].samples, intact.samples[1].samples);
+    assert!(bank.samples[1].chunks.is_empty());
+    assert_eq!(bank.samples[1].loop_start, None);
+}
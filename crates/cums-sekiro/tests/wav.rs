@@ -0,0 +1,189 @@
+mod common;
+
+use common::{fixture, fsb5_pcm, out_dir};
+use cums_sekiro::audio::{
+    create_wav_header, parse_wav, write_float_wav, write_wav, WAVE_FORMAT_IEEE_FLOAT,
+    WAVE_FORMAT_PCM,
+};
+use cums_sekiro::{AudioSettings, Codec, FsbBank};
+use std::io;
+
+fn tone(frames: usize, channels: usize) -> Vec<u8> {
+    (0..frames * channels)
+        .flat_map(|i| (i as i16 * 100).to_le_bytes())
+        .collect()
+}
+
+#[test]
+fn wav_header_round_trips() {
+    let pcm = tone(100, 2);
+    let wav = create_wav_header(&pcm, 48000, 2, 16, WAVE_FORMAT_PCM);
+    assert_eq!(parse_wav(&wav).expect("parse"), (48000, 2, 16, pcm));
+
+    assert!(parse_wav(b"RIFF\0\0\0\0AVI ").is_err());
+    assert!(parse_wav(&wav[..36]).is_err());
+}
+
+#[test]
+fn wav_writers_fill_in_the_format() {
+    let u16_at = |wav: &[u8], i: usize| u16::from_le_bytes([wav[i], wav[i + 1]]);
+    let u32_at = |wav: &[u8], i: usize| u32::from_le_bytes(wav[i..i + 4].try_into().unwrap());
+
+    let pcm = tone(10, 2);
+    let wav = write_wav(&pcm, 44100, 2, 16);
+    assert_eq!(wav, create_wav_header(&pcm, 44100, 2, 16, WAVE_FORMAT_PCM));
+    assert_eq!(u16_at(&wav, 20), WAVE_FORMAT_PCM);
+    assert_eq!(u32_at(&wav, 28), 44100 * 4);
+    assert_eq!(u16_at(&wav, 32), 4);
+    assert_eq!(u16_at(&wav, 34), 16);
+    assert_eq!(parse_wav(&wav).expect("parse"), (44100, 2, 16, pcm));
+
+    let float: Vec<u8> = [0.5f32, -0.25, 1.0]
+        .iter()
+        .flat_map(|f| f.to_le_bytes())
+        .collect();
+    let wav = write_float_wav(&float, 48000, 1);
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(u32_at(&wav, 4) as usize, wav.len() - 8);
+    assert_eq!(u16_at(&wav, 20), WAVE_FORMAT_IEEE_FLOAT);
+    assert_eq!(u16_at(&wav, 22), 1);
+    assert_eq!(u32_at(&wav, 24), 48000);
+    assert_eq!(u32_at(&wav, 28), 48000 * 4);
+    assert_eq!(u16_at(&wav, 32), 4);
+    assert_eq!(u16_at(&wav, 34), 32);
+    assert_eq!(u32_at(&wav, 40) as usize, float.len());
+    assert_eq!(&wav[44..], float);
+}
+
+#[test]
+fn pcm_sample_is_replaced_from_wav() {
+    let mut bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    let others: Vec<Vec<u8>> = [0, 2]
+        .iter()
+        .map(|&i| bank.sample_data(i).expect("data").to_vec())
+        .collect();
+    let pcm = tone(120, 2);
+    bank.replace_sample_pcm(1, &create_wav_header(&pcm, 22050, 2, 16, WAVE_FORMAT_PCM))
+        .expect("replace");
+    assert_eq!(bank.samples[1].frequency, 22050);
+    assert_eq!(bank.samples[1].channels, 2);
+    assert_eq!(bank.samples[1].samples, 120);
+
+    let path = out_dir("wav_replace").join("replaced.fsb");
+    bank.save(&path, false).expect("save");
+    let reloaded = FsbBank::load(&path).expect("reload");
+    assert_eq!(reloaded.sample_data(1).expect("replaced"), &pcm[..]);
+    assert_eq!(reloaded.sample_data(0).expect("first"), &others[0][..]);
+    assert_eq!(reloaded.sample_data(2).expect("last"), &others[1][..]);
+    assert_eq!(reloaded.samples[1].frequency, 22050);
+    assert_eq!(reloaded.samples[1].samples, 120);
+}
+
+#[test]
+fn replacing_one_alias_leaves_the_others() {
+    let mut bank = FsbBank::from_bytes(fsb5_pcm(&[0, 16, 0], 32)).expect("parse");
+    let shared = bank.sample_data(2).expect("data").to_vec();
+    let middle = bank.sample_data(1).expect("data").to_vec();
+    let pcm = vec![0xAA; 32];
+    bank.replace_sample_pcm(0, &create_wav_header(&pcm, 22050, 1, 16, WAVE_FORMAT_PCM))
+        .expect("replace");
+
+    assert_eq!(bank.sample_data(0).expect("replaced"), &pcm[..]);
+    assert_eq!(bank.sample_data(1).expect("middle"), &middle[..]);
+    assert_eq!(bank.sample_data(2).expect("alias"), &shared[..]);
+    assert_eq!(bank.samples[2].frequency, 44100);
+    assert!(bank.alias_groups().is_empty());
+
+    let path = out_dir("wav_replace_alias").join("replaced.fsb");
+    bank.save(&path, false).expect("save");
+    let reloaded = FsbBank::load(&path).expect("reload");
+    assert_eq!(reloaded.sample_data(0).expect("replaced"), &pcm[..]);
+    assert_eq!(reloaded.sample_data(1).expect("middle"), &middle[..]);
+    assert_eq!(reloaded.sample_data(2).expect("alias"), &shared[..]);
+}
+
+#[test]
+fn eight_bit_wav_is_rejected() {
+    let mut bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    let wav = create_wav_header(&[0x80; 64], 44100, 1, 8, WAVE_FORMAT_PCM);
+    let err = bank.replace_sample_pcm(0, &wav).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("16-bit"));
+}
+
+#[test]
+fn batch_replacement_updates_every_edit() {
+    let dir = out_dir("wav_batch");
+    let mut bank = FsbBank::from_bytes(fixture("pcm.fsb5")).expect("parse");
+    let untouched = bank.sample_data(1).expect("data").to_vec();
+    let mut edits = Vec::new();
+    for (index, frames) in [(0, 50), (2, 80)] {
+        let path = dir.join(format!("edit{}.wav", index));
+        std::fs::write(
+            &path,
+            create_wav_header(&tone(frames, 1), 44100, 1, 16, WAVE_FORMAT_PCM),
+        )
+        .expect("write");
+        edits.push((index, path, AudioSettings::default()));
+    }
+
+    // PCM16 edits from plain WAVs need no encoder, so fsbankcl can be missing
+    let results = bank
+        .replace_samples(&edits, dir.join("fsbankcl.exe"), dir.clone())
+        .expect("replace");
+    assert_eq!(results.len(), 2);
+    assert_eq!(bank.samples[0].data_size, 100);
+    assert_eq!(bank.samples[2].data_size, 160);
+    assert_eq!(bank.sample_data(0).expect("first"), &tone(50, 1)[..]);
+    assert_eq!(bank.sample_data(1).expect("middle"), &untouched[..]);
+    assert_eq!(bank.sample_data(2).expect("last"), &tone(80, 1)[..]);
+}
+
+#[test]
+fn every_pcm_codec_exports_a_wav() {
+    let u16_at = |wav: &[u8], i: usize| u16::from_le_bytes([wav[i], wav[i + 1]]);
+    let u32_at = |wav: &[u8], i: usize| u32::from_le_bytes(wav[i..i + 4].try_into().unwrap());
+    let data: Vec<u8> = (0..96).collect();
+    for (codec, bits, format_tag) in [
+        (Codec::Pcm8, 8, 1),
+        (Codec::Pcm16, 16, 1),
+        (Codec::Pcm24, 24, 1),
+        (Codec::Pcm32, 32, 1),
+        (Codec::PcmFloat, 32, 3),
+    ] {
+        let bytes = common::fsb5_with_chunk(codec as u32, 2, 0, 3, &[0; 8], &data);
+        let bank = FsbBank::from_bytes(bytes).expect("parse");
+        let (wav, ext) = bank.extract_audio(0).expect("extract");
+        assert_eq!(ext, "wav", "{:?}", codec);
+        assert_eq!(u16_at(&wav, 20), format_tag, "{:?}", codec);
+        assert_eq!(u16_at(&wav, 22), 2);
+        assert_eq!(u32_at(&wav, 24), 44100);
+        assert_eq!(u32_at(&wav, 28), 44100 * 2 * bits / 8, "{:?}", codec);
+        assert_eq!(u16_at(&wav, 32), 2 * bits as u16 / 8, "{:?}", codec);
+        assert_eq!(u16_at(&wav, 34), bits as u16, "{:?}", codec);
+        assert_eq!(&wav[44..], &data[..]);
+    }
+}
+
+#[test]
+fn batch_replacement_of_an_alias_leaves_the_others() {
+    let dir = out_dir("wav_batch_alias");
+    let mut bank = FsbBank::from_bytes(fsb5_pcm(&[0, 16, 0], 32)).expect("parse");
+    let shared = bank.sample_data(2).expect("alias").to_vec();
+    let path = dir.join("edit0.wav");
+    std::fs::write(
+        &path,
+        create_wav_header(&tone(16, 1), 44100, 1, 16, WAVE_FORMAT_PCM),
+    )
+    .expect("write");
+
+    bank.replace_samples(
+        &[(0, path, AudioSettings::default())],
+        dir.join("fsbankcl.exe"),
+        dir.clone(),
+    )
+    .expect("replace");
+    assert_eq!(bank.sample_data(0).expect("edited"), &tone(16, 1)[..]);
+    assert_eq!(bank.sample_data(2).expect("alias"), &shared[..]);
+    assert!(bank.alias_groups().is_empty());
+}